@@ -0,0 +1,154 @@
+// Where the client secret and the Spotify token are kept: the OS keyring where there is
+// one, otherwise a file encrypted with a key from the user's config directory. Nothing
+// secret is written in plain text next to the exe any more.
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+
+pub mod file;
+#[cfg(target_os = "linux")]
+pub mod secret_service;
+#[cfg(windows)]
+pub mod wincred;
+
+// Everything is stored under this service name, one entry per key
+pub const SERVICE: &str = "SpotifyBinds";
+pub const CLIENT_SECRET: &str = "client-secret";
+pub const TOKEN: &str = "token";
+
+// The field token.json used to keep the client secret in
+pub const SECRET_FIELD: &str = "RSPOTIFY_CLIENT_SECRET";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CredentialError {
+    Unavailable(String), // this kind of store doesn't exist here
+    Locked,              // the keyring stayed locked, e.g. the unlock prompt was dismissed
+    Corrupt(String),     // what's stored can't be read back
+    Io(String),
+}
+
+impl fmt::Display for CredentialError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CredentialError::Unavailable(e) => write!(f, "credential store unavailable: {}", e),
+            CredentialError::Locked => write!(f, "the keyring is locked"),
+            CredentialError::Corrupt(e) => write!(f, "stored credentials are unreadable: {}", e),
+            CredentialError::Io(e) => write!(f, "couldn't access stored credentials: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for CredentialError {}
+
+impl From<std::io::Error> for CredentialError {
+    fn from(e: std::io::Error) -> Self {
+        CredentialError::Io(e.to_string())
+    }
+}
+
+pub type CredentialResult<T> = Result<T, CredentialError>;
+
+pub trait CredentialStore: Send + Sync {
+    // Shown to the user, e.g. "Windows Credential Manager"
+    fn name(&self) -> &'static str;
+    fn get(&self, key: &str) -> CredentialResult<Option<String>>;
+    fn set(&self, key: &str, value: &str) -> CredentialResult<()>;
+    // Deleting a key that isn't there is fine
+    fn delete(&self, key: &str) -> CredentialResult<()>;
+}
+
+// Keeps everything in memory, for tests
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    pub entries: Mutex<HashMap<String, String>>,
+}
+
+impl CredentialStore for MemoryStore {
+    fn name(&self) -> &'static str {
+        "memory"
+    }
+
+    fn get(&self, key: &str) -> CredentialResult<Option<String>> {
+        Ok(self.entries.lock().unwrap().get(key).cloned())
+    }
+
+    fn set(&self, key: &str, value: &str) -> CredentialResult<()> {
+        self.entries.lock().unwrap().insert(key.to_owned(), value.to_owned());
+        Ok(())
+    }
+
+    fn delete(&self, key: &str) -> CredentialResult<()> {
+        self.entries.lock().unwrap().remove(key);
+        Ok(())
+    }
+}
+
+// Store the secret from the credentials form. The form only shows the stored secret
+// once a login has read it, so an empty field leaves the stored one alone; forgetting
+// it is done with an explicit delete.
+pub fn save_client_secret(store: &dyn CredentialStore, secret: &str) -> CredentialResult<()> {
+    if secret.is_empty() {
+        return Ok(());
+    }
+    store.set(CLIENT_SECRET, secret)
+}
+
+// The OS keyring, or an encrypted file in `dir` when there isn't one
+#[cfg(windows)]
+pub fn default_store(_dir: &Path) -> Box<dyn CredentialStore> {
+    Box::new(wincred::CredentialManager)
+}
+
+#[cfg(not(windows))]
+pub fn default_store(dir: &Path) -> Box<dyn CredentialStore> {
+    #[cfg(target_os = "linux")]
+    match secret_service::SecretServiceStore::connect() {
+        Ok(store) => return Box::new(store),
+        Err(e) => println!("No Secret Service, using an encrypted file instead: {}", e),
+    }
+    Box::new(file::EncryptedFileStore::new(dir.join(file::DATA_FILE), file::default_key_path()))
+}
+
+// What a migration moved into the store
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Migrated {
+    pub client_secret: bool,
+    pub token: bool,
+}
+
+// Move secrets out of the plain text files older versions wrote: the client secret in
+// token.json is blanked and rspotify's token cache is deleted. A file is only changed
+// once its secret is in the store, so a failed migration is retried next launch.
+pub fn migrate_plaintext(
+    store: &dyn CredentialStore,
+    settings_path: &Path,
+    token_cache_path: &Path,
+) -> CredentialResult<Migrated> {
+    let mut migrated = Migrated::default();
+
+    if let Ok(content) = fs::read_to_string(settings_path) {
+        let mut settings: serde_json::Value =
+            serde_json::from_str(&content).map_err(|e| CredentialError::Corrupt(e.to_string()))?;
+        let secret = settings.get(SECRET_FIELD).and_then(|s| s.as_str()).unwrap_or_default().to_owned();
+        if !secret.is_empty() {
+            store.set(CLIENT_SECRET, &secret)?;
+            settings[SECRET_FIELD] = serde_json::Value::from("");
+            let json = serde_json::to_string_pretty(&settings).map_err(|e| CredentialError::Corrupt(e.to_string()))?;
+            fs::write(settings_path, json)?;
+            migrated.client_secret = true;
+        }
+    }
+
+    if let Ok(token) = fs::read_to_string(token_cache_path) {
+        // a cache rspotify couldn't read either isn't worth keeping
+        if serde_json::from_str::<serde_json::Value>(&token).is_ok() {
+            store.set(TOKEN, token.trim())?;
+            migrated.token = true;
+        }
+        fs::remove_file(token_cache_path)?;
+    }
+
+    Ok(migrated)
+}
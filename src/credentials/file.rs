@@ -0,0 +1,138 @@
+// The fallback when there's no keyring. Entries are kept as one ChaCha20-Poly1305
+// encrypted JSON object, with the key in a separate file under the user's config
+// directory. That keeps secrets out of a copied or shared app folder, but anyone who
+// can read the user's files can read both halves.
+use super::{CredentialError, CredentialResult, CredentialStore};
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use std::collections::HashMap;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+pub const DATA_FILE: &str = "credentials.enc";
+pub const KEY_FILE: &str = "credentials.key";
+
+const NONCE_LEN: usize = 12;
+
+// e.g. ~/.config/SpotifyBinds/credentials.key
+pub fn default_key_path() -> PathBuf {
+    let dir = dirs::config_dir().unwrap_or_else(|| PathBuf::from("."));
+    dir.join(super::SERVICE).join(KEY_FILE)
+}
+
+pub struct EncryptedFileStore {
+    path: PathBuf,
+    key_path: PathBuf,
+    // Held across read-modify-write so concurrent sets don't drop each other's entries
+    lock: Mutex<()>,
+}
+
+// Create a file only the current user can read
+fn write_private(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options.open(path)?.write_all(contents)
+}
+
+impl EncryptedFileStore {
+    pub fn new(path: PathBuf, key_path: PathBuf) -> Self {
+        Self { path, key_path, lock: Mutex::new(()) }
+    }
+
+    fn read_key(&self) -> CredentialResult<Option<ChaCha20Poly1305>> {
+        match fs::read(&self.key_path) {
+            Ok(key) if key.len() == 32 => Ok(Some(ChaCha20Poly1305::new(Key::from_slice(&key)))),
+            Ok(_) => Err(CredentialError::Corrupt(format!("{} isn't a key", self.key_path.display()))),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn create_key(&self) -> CredentialResult<ChaCha20Poly1305> {
+        let key = ChaCha20Poly1305::generate_key(&mut OsRng);
+        write_private(&self.key_path, &key)?;
+        Ok(ChaCha20Poly1305::new(&key))
+    }
+
+    fn load(&self, cipher: &ChaCha20Poly1305) -> CredentialResult<HashMap<String, String>> {
+        let data = match fs::read(&self.path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(HashMap::new()),
+            Err(e) => return Err(e.into()),
+        };
+        if data.len() < NONCE_LEN {
+            return Err(CredentialError::Corrupt(format!("{} is truncated", self.path.display())));
+        }
+        let (nonce, ciphertext) = data.split_at(NONCE_LEN);
+        let plaintext = cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| CredentialError::Corrupt(format!("{} doesn't match its key", self.path.display())))?;
+        serde_json::from_slice(&plaintext).map_err(|e| CredentialError::Corrupt(e.to_string()))
+    }
+
+    // Written to a temporary file first so a crash can't leave half a file behind
+    fn save(&self, cipher: &ChaCha20Poly1305, entries: &HashMap<String, String>) -> CredentialResult<()> {
+        let plaintext = serde_json::to_vec(entries).map_err(|e| CredentialError::Corrupt(e.to_string()))?;
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext =
+            cipher.encrypt(&nonce, plaintext.as_slice()).map_err(|e| CredentialError::Corrupt(e.to_string()))?;
+        let mut data = nonce.to_vec();
+        data.extend_from_slice(&ciphertext);
+
+        let temp = self.path.with_extension("tmp");
+        write_private(&temp, &data)?;
+        fs::rename(&temp, &self.path)?;
+        Ok(())
+    }
+
+    // The key is made the first time something is stored. Without it anything already
+    // in the file is lost, so it starts over empty.
+    fn update(&self, change: impl FnOnce(&mut HashMap<String, String>)) -> CredentialResult<()> {
+        let _guard = self.lock.lock().unwrap();
+        let (cipher, mut entries) = match self.read_key()? {
+            Some(cipher) => {
+                let entries = self.load(&cipher)?;
+                (cipher, entries)
+            }
+            None => (self.create_key()?, HashMap::new()),
+        };
+        change(&mut entries);
+        self.save(&cipher, &entries)
+    }
+}
+
+impl CredentialStore for EncryptedFileStore {
+    fn name(&self) -> &'static str {
+        "encrypted file"
+    }
+
+    fn get(&self, key: &str) -> CredentialResult<Option<String>> {
+        let _guard = self.lock.lock().unwrap();
+        match self.read_key()? {
+            Some(cipher) => Ok(self.load(&cipher)?.remove(key)),
+            None => Ok(None),
+        }
+    }
+
+    fn set(&self, key: &str, value: &str) -> CredentialResult<()> {
+        self.update(|entries| {
+            entries.insert(key.to_owned(), value.to_owned());
+        })
+    }
+
+    fn delete(&self, key: &str) -> CredentialResult<()> {
+        self.update(|entries| {
+            entries.remove(key);
+        })
+    }
+}
@@ -0,0 +1,185 @@
+// The freedesktop Secret Service (org.freedesktop.secrets), which GNOME Keyring and
+// KWallet both provide. Entries go in the default collection, found by their service
+// and key attributes. The session uses the "plain" algorithm, the same as libsecret
+// when it talks to a keyring over the user's own session bus.
+use super::{CredentialError, CredentialResult, CredentialStore, SERVICE};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use zbus::blocking::Connection;
+use zbus::zvariant::{ObjectPath, OwnedObjectPath, OwnedValue, Type, Value};
+
+pub const SECRETS_NAME: &str = "org.freedesktop.secrets";
+pub const SECRETS_PATH: &str = "/org/freedesktop/secrets";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub struct Secret {
+    pub session: OwnedObjectPath,
+    pub parameters: Vec<u8>,
+    pub value: Vec<u8>,
+    pub content_type: String,
+}
+
+#[zbus::proxy(
+    interface = "org.freedesktop.Secret.Service",
+    default_service = "org.freedesktop.secrets",
+    default_path = "/org/freedesktop/secrets",
+    gen_async = false
+)]
+trait Service {
+    fn open_session(&self, algorithm: &str, input: &Value<'_>) -> zbus::Result<(OwnedValue, OwnedObjectPath)>;
+    fn search_items(
+        &self,
+        attributes: HashMap<&str, &str>,
+    ) -> zbus::Result<(Vec<OwnedObjectPath>, Vec<OwnedObjectPath>)>;
+    fn unlock(&self, objects: &[&ObjectPath<'_>]) -> zbus::Result<(Vec<OwnedObjectPath>, OwnedObjectPath)>;
+    fn read_alias(&self, name: &str) -> zbus::Result<OwnedObjectPath>;
+}
+
+#[zbus::proxy(interface = "org.freedesktop.Secret.Collection", default_service = "org.freedesktop.secrets", gen_async = false)]
+trait Collection {
+    fn create_item(
+        &self,
+        properties: HashMap<&str, Value<'_>>,
+        secret: &Secret,
+        replace: bool,
+    ) -> zbus::Result<(OwnedObjectPath, OwnedObjectPath)>;
+}
+
+#[zbus::proxy(interface = "org.freedesktop.Secret.Item", default_service = "org.freedesktop.secrets", gen_async = false)]
+trait Item {
+    fn get_secret(&self, session: &ObjectPath<'_>) -> zbus::Result<Secret>;
+    fn delete(&self) -> zbus::Result<OwnedObjectPath>;
+}
+
+#[zbus::proxy(interface = "org.freedesktop.Secret.Prompt", default_service = "org.freedesktop.secrets", gen_async = false)]
+trait Prompt {
+    fn prompt(&self, window_id: &str) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    fn completed(&self, dismissed: bool, result: Value<'_>) -> zbus::Result<()>;
+}
+
+fn unavailable(e: zbus::Error) -> CredentialError {
+    CredentialError::Unavailable(e.to_string())
+}
+
+fn io_error(e: zbus::Error) -> CredentialError {
+    CredentialError::Io(e.to_string())
+}
+
+fn attributes(key: &str) -> HashMap<&str, &str> {
+    HashMap::from([("service", SERVICE), ("key", key)])
+}
+
+// "/" stands for no object, e.g. when no prompt is needed
+fn is_none(path: &ObjectPath<'_>) -> bool {
+    path.as_str() == "/"
+}
+
+pub struct SecretServiceStore {
+    connection: Connection,
+    session: OwnedObjectPath,
+}
+
+impl SecretServiceStore {
+    pub fn connect() -> CredentialResult<Self> {
+        Self::with_connection(Connection::session().map_err(unavailable)?)
+    }
+
+    // Fails when nothing provides the service or there's no default collection to
+    // store things in
+    pub fn with_connection(connection: Connection) -> CredentialResult<Self> {
+        let service = ServiceProxy::new(&connection).map_err(unavailable)?;
+        let (_, session) = service.open_session("plain", &Value::from("")).map_err(unavailable)?;
+        let collection = service.read_alias("default").map_err(unavailable)?;
+        if is_none(&collection) {
+            return Err(CredentialError::Unavailable("the keyring has no default collection".to_owned()));
+        }
+        Ok(Self { connection, session })
+    }
+
+    fn service(&self) -> CredentialResult<ServiceProxy<'_>> {
+        ServiceProxy::new(&self.connection).map_err(io_error)
+    }
+
+    // Some actions need the user's say-so first, e.g. typing the keyring password
+    fn run_prompt(&self, prompt: &ObjectPath<'_>) -> CredentialResult<()> {
+        if is_none(prompt) {
+            return Ok(());
+        }
+        let proxy = PromptProxy::builder(&self.connection).path(prompt.to_owned()).map_err(io_error)?.build().map_err(io_error)?;
+        let mut completed = proxy.receive_completed().map_err(io_error)?;
+        proxy.prompt("").map_err(io_error)?;
+        let signal = completed.next().ok_or(CredentialError::Locked)?;
+        match signal.args() {
+            Ok(args) if !args.dismissed => Ok(()),
+            _ => Err(CredentialError::Locked),
+        }
+    }
+
+    fn unlock(&self, objects: &[OwnedObjectPath]) -> CredentialResult<()> {
+        if objects.is_empty() {
+            return Ok(());
+        }
+        let objects: Vec<&ObjectPath<'_>> = objects.iter().map(|o| &**o).collect();
+        let (_, prompt) = self.service()?.unlock(&objects).map_err(io_error)?;
+        self.run_prompt(&prompt)
+    }
+
+    // Every item stored under `key`, unlocked
+    fn items(&self, key: &str) -> CredentialResult<Vec<OwnedObjectPath>> {
+        let (mut unlocked, locked) = self.service()?.search_items(attributes(key)).map_err(io_error)?;
+        self.unlock(&locked)?;
+        unlocked.extend(locked);
+        Ok(unlocked)
+    }
+
+    fn item(&self, path: &ObjectPath<'_>) -> CredentialResult<ItemProxy<'_>> {
+        ItemProxy::builder(&self.connection).path(path.to_owned()).map_err(io_error)?.build().map_err(io_error)
+    }
+}
+
+impl CredentialStore for SecretServiceStore {
+    fn name(&self) -> &'static str {
+        "Secret Service keyring"
+    }
+
+    fn get(&self, key: &str) -> CredentialResult<Option<String>> {
+        let Some(path) = self.items(key)?.into_iter().next() else {
+            return Ok(None);
+        };
+        let secret = self.item(&path)?.get_secret(&self.session).map_err(io_error)?;
+        String::from_utf8(secret.value).map(Some).map_err(|e| CredentialError::Corrupt(e.to_string()))
+    }
+
+    fn set(&self, key: &str, value: &str) -> CredentialResult<()> {
+        let path = self.service()?.read_alias("default").map_err(io_error)?;
+        self.unlock(std::slice::from_ref(&path))?;
+        let collection = CollectionProxy::builder(&self.connection)
+            .path(path)
+            .map_err(io_error)?
+            .build()
+            .map_err(io_error)?;
+
+        let properties = HashMap::from([
+            ("org.freedesktop.Secret.Item.Label", Value::from(format!("{} {}", SERVICE, key))),
+            ("org.freedesktop.Secret.Item.Attributes", Value::from(attributes(key))),
+        ]);
+        let secret = Secret {
+            session: self.session.clone(),
+            parameters: Vec::new(),
+            value: value.as_bytes().to_vec(),
+            content_type: "text/plain".to_owned(),
+        };
+        let (_, prompt) = collection.create_item(properties, &secret, true).map_err(io_error)?;
+        self.run_prompt(&prompt)
+    }
+
+    fn delete(&self, key: &str) -> CredentialResult<()> {
+        for path in self.items(key)? {
+            let prompt = self.item(&path)?.delete().map_err(io_error)?;
+            self.run_prompt(&prompt)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,64 @@
+// Windows Credential Manager. Each key is a generic credential named
+// "SpotifyBinds/<key>", kept in the user's profile and visible in Control Panel.
+use super::{CredentialError, CredentialResult, CredentialStore, SERVICE};
+use windows::core::{HSTRING, PWSTR};
+use windows::Win32::Foundation::ERROR_NOT_FOUND;
+use windows::Win32::Security::Credentials::{
+    CredDeleteW, CredFree, CredReadW, CredWriteW, CREDENTIALW, CRED_PERSIST_LOCAL_MACHINE, CRED_TYPE_GENERIC,
+};
+
+pub struct CredentialManager;
+
+fn target(key: &str) -> HSTRING {
+    HSTRING::from(format!("{}/{}", SERVICE, key))
+}
+
+fn is_not_found(e: &windows::core::Error) -> bool {
+    e.code() == ERROR_NOT_FOUND.to_hresult()
+}
+
+fn io_error(e: windows::core::Error) -> CredentialError {
+    CredentialError::Io(e.message().to_string())
+}
+
+impl CredentialStore for CredentialManager {
+    fn name(&self) -> &'static str {
+        "Windows Credential Manager"
+    }
+
+    fn get(&self, key: &str) -> CredentialResult<Option<String>> {
+        let mut credential: *mut CREDENTIALW = std::ptr::null_mut();
+        if let Err(e) = unsafe { CredReadW(&target(key), CRED_TYPE_GENERIC.0, 0, &mut credential) }.ok() {
+            return if is_not_found(&e) { Ok(None) } else { Err(io_error(e)) };
+        }
+        let value = unsafe {
+            let credential = &*credential;
+            std::slice::from_raw_parts(credential.CredentialBlob, credential.CredentialBlobSize as usize).to_vec()
+        };
+        unsafe { CredFree(credential as *const _) };
+        String::from_utf8(value).map(Some).map_err(|e| CredentialError::Corrupt(e.to_string()))
+    }
+
+    fn set(&self, key: &str, value: &str) -> CredentialResult<()> {
+        let mut name: Vec<u16> = target(key).as_wide().iter().copied().chain([0]).collect();
+        let mut user: Vec<u16> = SERVICE.encode_utf16().chain([0]).collect();
+        let mut blob = value.as_bytes().to_vec();
+        let credential = CREDENTIALW {
+            Type: CRED_TYPE_GENERIC,
+            TargetName: PWSTR(name.as_mut_ptr()),
+            UserName: PWSTR(user.as_mut_ptr()),
+            CredentialBlobSize: blob.len() as u32,
+            CredentialBlob: blob.as_mut_ptr(),
+            Persist: CRED_PERSIST_LOCAL_MACHINE,
+            ..Default::default()
+        };
+        unsafe { CredWriteW(&credential, 0) }.ok().map_err(io_error)
+    }
+
+    fn delete(&self, key: &str) -> CredentialResult<()> {
+        match unsafe { CredDeleteW(&target(key), CRED_TYPE_GENERIC.0, 0) }.ok() {
+            Err(e) if !is_not_found(&e) => Err(io_error(e)),
+            _ => Ok(()),
+        }
+    }
+}
@@ -1,341 +1,1635 @@
-use rdev::{listen, EventType, Key};
+use rdev::{listen, Button, EventType, Key};
 use eframe::egui;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::HashSet;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::{Duration, Instant};
 use tokio::sync::mpsc::UnboundedSender;
 
+#[cfg(target_os = "linux")]
+pub mod evdev;
+#[cfg(target_os = "linux")]
+pub mod portal;
 
 
-// Simple enum to describe key events sent from the listener to the UI
-#[derive(Debug, Clone)]
+// What a play bind starts: a `spotify:` URI or open.spotify.com link, checked as a
+// `SpotifyUri` when the bind table is saved
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PlayTarget {
+    pub uri: String,
+    pub shuffle: bool,
+    pub offset: u32, // track to start from in an album or playlist, 0 is the first
+}
+
+// The playlist an add-to-playlist bind puts the playing track in
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PlaylistTarget {
+    pub uri: String,
+    pub allow_duplicates: bool, // add the track again even if it's already there
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpotifyItem {
+    Track,
+    Episode,
+    Album,
+    Artist,
+    Playlist,
+    Show,
+}
+
+impl SpotifyItem {
+    const ALL: [SpotifyItem; 6] = [
+        SpotifyItem::Track,
+        SpotifyItem::Episode,
+        SpotifyItem::Album,
+        SpotifyItem::Artist,
+        SpotifyItem::Playlist,
+        SpotifyItem::Show,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SpotifyItem::Track => "track",
+            SpotifyItem::Episode => "episode",
+            SpotifyItem::Album => "album",
+            SpotifyItem::Artist => "artist",
+            SpotifyItem::Playlist => "playlist",
+            SpotifyItem::Show => "show",
+        }
+    }
+
+    // Albums, artists, playlists and shows play as a context, tracks and episodes on their own
+    pub fn is_context(&self) -> bool {
+        !matches!(self, SpotifyItem::Track | SpotifyItem::Episode)
+    }
+}
+
+// A playable Spotify item. Parses `spotify:album:ID` as well as share links such as
+// `https://open.spotify.com/album/ID?si=...`, and displays as the `spotify:` form.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpotifyUri {
+    pub item: SpotifyItem,
+    pub id: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UriParseError {
+    Empty,
+    NotSpotify(String),
+    UnknownItem(String),
+    InvalidId(String),
+    NotPlaylist(String),
+}
+
+impl fmt::Display for UriParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UriParseError::Empty => write!(f, "no URI given"),
+            UriParseError::NotSpotify(s) => write!(f, "'{}' is not a spotify: URI or open.spotify.com link", s),
+            UriParseError::UnknownItem(i) => write!(f, "can't play a '{}'", i),
+            UriParseError::InvalidId(id) => write!(f, "'{}' is not a Spotify ID", id),
+            UriParseError::NotPlaylist(s) => write!(f, "'{}' is not a playlist", s),
+        }
+    }
+}
+
+impl std::error::Error for UriParseError {}
+
+impl FromStr for SpotifyUri {
+    type Err = UriParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.is_empty() {
+            return Err(UriParseError::Empty);
+        }
+
+        let (item, id) = if let Some(rest) = s.strip_prefix("spotify:") {
+            rest.split_once(':').ok_or_else(|| UriParseError::NotSpotify(s.to_owned()))?
+        } else {
+            let link = s.strip_prefix("https://").or_else(|| s.strip_prefix("http://")).unwrap_or(s);
+            let path = link
+                .strip_prefix("open.spotify.com/")
+                .ok_or_else(|| UriParseError::NotSpotify(s.to_owned()))?;
+            let path = path.split(['?', '#']).next().unwrap_or_default();
+            let mut segments = path.split('/').filter(|p| !p.is_empty());
+            // Localised links look like open.spotify.com/intl-de/album/ID
+            let mut item = segments.next().unwrap_or_default();
+            if item.starts_with("intl-") {
+                item = segments.next().unwrap_or_default();
+            }
+            (item, segments.next().unwrap_or_default())
+        };
+
+        let item = SpotifyItem::ALL
+            .into_iter()
+            .find(|i| i.name() == item)
+            .ok_or_else(|| UriParseError::UnknownItem(item.to_owned()))?;
+        if id.len() != 22 || !id.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return Err(UriParseError::InvalidId(id.to_owned()));
+        }
+        Ok(SpotifyUri { item, id: id.to_owned() })
+    }
+}
+
+impl fmt::Display for SpotifyUri {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "spotify:{}:{}", self.item.name(), self.id)
+    }
+}
+
+// Simple enum to describe key events sent from the listener to the UI.
+// Some actions carry an argument, set per binding.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum KeyEvent {
     Toggle,
     Play,
     Pause,
     Next,
     Previous,
-    Volup,
-    Voldown,
+    Volup(u32),   // percentage points
+    Voldown(u32), // percentage points
     Mute,
+    Seek(i32), // seconds, negative goes back
+    Restart,
+    CycleShuffle,
+    CycleRepeat,
+    ToggleLike,
+    SetVolume(u32), // percent
+    PlayUri(PlayTarget),
+    AddToPlaylist(PlaylistTarget),
+    TransferTo(String), // device name
+    CycleDevice,
+}
+
+pub const DEFAULT_VOLUME_STEP: u32 = 5;
+pub const DEFAULT_SEEK_SECS: i32 = 10;
+pub const DEFAULT_SET_VOLUME: u32 = 50;
+
+impl KeyEvent {
+    // Order used for the action picker in the bind table
+    pub const ALL: [KeyEvent; 18] = [
+        KeyEvent::Toggle,
+        KeyEvent::Next,
+        KeyEvent::Previous,
+        KeyEvent::Play,
+        KeyEvent::Pause,
+        KeyEvent::Volup(DEFAULT_VOLUME_STEP),
+        KeyEvent::Voldown(DEFAULT_VOLUME_STEP),
+        KeyEvent::Mute,
+        KeyEvent::SetVolume(DEFAULT_SET_VOLUME),
+        KeyEvent::Seek(DEFAULT_SEEK_SECS),
+        KeyEvent::Restart,
+        KeyEvent::CycleShuffle,
+        KeyEvent::CycleRepeat,
+        KeyEvent::ToggleLike,
+        KeyEvent::PlayUri(PlayTarget { uri: String::new(), shuffle: false, offset: 0 }),
+        KeyEvent::AddToPlaylist(PlaylistTarget { uri: String::new(), allow_duplicates: false }),
+        KeyEvent::TransferTo(String::new()),
+        KeyEvent::CycleDevice,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            KeyEvent::Toggle => "Toggle playback",
+            KeyEvent::Play => "Play",
+            KeyEvent::Pause => "Pause",
+            KeyEvent::Next => "Skip",
+            KeyEvent::Previous => "Previous",
+            KeyEvent::Volup(_) => "Volume up",
+            KeyEvent::Voldown(_) => "Volume down",
+            KeyEvent::Mute => "Mute",
+            KeyEvent::Seek(_) => "Seek",
+            KeyEvent::Restart => "Restart track",
+            KeyEvent::CycleShuffle => "Shuffle on/off",
+            KeyEvent::CycleRepeat => "Cycle repeat",
+            KeyEvent::ToggleLike => "Like/unlike track",
+            KeyEvent::SetVolume(_) => "Set volume",
+            KeyEvent::PlayUri(_) => "Play URI",
+            KeyEvent::AddToPlaylist(_) => "Add to playlist",
+            KeyEvent::TransferTo(_) => "Transfer playback",
+            KeyEvent::CycleDevice => "Next device",
+        }
+    }
+
+    // Whether the action plays on a device, so its binding can pick which one
+    pub fn targets_device(&self) -> bool {
+        !matches!(
+            self,
+            KeyEvent::ToggleLike | KeyEvent::AddToPlaylist(_) | KeyEvent::TransferTo(_) | KeyEvent::CycleDevice
+        )
+    }
+
+    // Checks any URI the action holds and rewrites it in spotify: form, before the bind
+    // table is saved. An empty URI is left for the user to fill in.
+    pub fn normalize_uri(&mut self) -> Result<(), UriParseError> {
+        let (uri, playlist_only) = match self {
+            KeyEvent::PlayUri(target) => (&mut target.uri, false),
+            KeyEvent::AddToPlaylist(target) => (&mut target.uri, true),
+            _ => return Ok(()),
+        };
+        if uri.trim().is_empty() {
+            return Ok(());
+        }
+        let parsed: SpotifyUri = uri.parse()?;
+        if playlist_only && parsed.item != SpotifyItem::Playlist {
+            return Err(UriParseError::NotPlaylist(uri.trim().to_owned()));
+        }
+        *uri = parsed.to_string();
+        Ok(())
+    }
+
+    // Same action, whatever its argument
+    pub fn same_kind(&self, other: &KeyEvent) -> bool {
+        std::mem::discriminant(self) == std::mem::discriminant(other)
+    }
+
+    // Volume and seek keys keep stepping while held, everything else fires once per press
+    pub fn default_repeat(&self) -> RepeatPolicy {
+        match self {
+            KeyEvent::Volup(_) | KeyEvent::Voldown(_) | KeyEvent::Seek(_) => RepeatPolicy::DEFAULT_REPEAT,
+            _ => RepeatPolicy::Ignore,
+        }
+    }
+
+    // Actions as settings files stored them before they had arguments
+    fn from_legacy_name(name: &str) -> Option<KeyEvent> {
+        let action = match name {
+            "Volup" => KeyEvent::Volup(DEFAULT_VOLUME_STEP),
+            "Voldown" => KeyEvent::Voldown(DEFAULT_VOLUME_STEP),
+            "SetVolume" => KeyEvent::SetVolume(DEFAULT_SET_VOLUME),
+            "SeekForward" => KeyEvent::Seek(DEFAULT_SEEK_SECS),
+            "SeekBack" => KeyEvent::Seek(-DEFAULT_SEEK_SECS),
+            _ => return None,
+        };
+        Some(action)
+    }
+}
+
+// Accepts actions written before they carried arguments, such as a bare "Volup"
+fn deserialize_action<'de, D>(deserializer: D) -> Result<KeyEvent, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Stored {
+        Current(KeyEvent),
+        Legacy(String),
+    }
+
+    match Stored::deserialize(deserializer)? {
+        Stored::Current(action) => Ok(action),
+        Stored::Legacy(name) => {
+            KeyEvent::from_legacy_name(&name).ok_or_else(|| de::Error::custom(format!("unknown action '{}'", name)))
+        }
+    }
+}
+
+// What a press-triggered binding does while its chord stays held. OS key
+// repeats are never passed through; the listener generates its own.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RepeatPolicy {
+    Ignore,
+    // Fire again after `interval_ms`, getting faster with each repeat down to `min_interval_ms`
+    Repeat { interval_ms: u64, min_interval_ms: u64 },
+}
+
+impl RepeatPolicy {
+    pub const DEFAULT_REPEAT: RepeatPolicy = RepeatPolicy::Repeat { interval_ms: 250, min_interval_ms: 80 };
+}
+
+// Repeats never come faster than this, whatever the settings file says
+pub const MIN_REPEAT_MS: u64 = 30;
+
+// Each repeat waits this fraction of the previous interval
+const REPEAT_ACCELERATION: f64 = 0.8;
+
+// How the last chord of a binding has to be pressed for it to fire.
+// Hold and Tap let one chord carry several actions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Trigger {
+    #[default]
+    Press,
+    Release,
+    Hold(u64), // milliseconds
+    Tap(u32),  // number of taps, each shorter than any hold on the same chord
+}
+
+impl Trigger {
+    pub const KINDS: [Trigger; 4] = [Trigger::Press, Trigger::Release, Trigger::Hold(500), Trigger::Tap(2)];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Trigger::Press => "On press",
+            Trigger::Release => "On release",
+            Trigger::Hold(_) => "Hold",
+            Trigger::Tap(_) => "Multi-tap",
+        }
+    }
+
+    pub fn same_kind(&self, other: &Trigger) -> bool {
+        std::mem::discriminant(self) == std::mem::discriminant(other)
+    }
+}
+
+// One row of the bind table: a key combination (or sequence of them) and the
+// action it fires. Several bindings may share the same action.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Binding {
+    #[serde(default, alias = "chord", deserialize_with = "deserialize_keys")]
+    pub keys: Option<KeySequence>,
+    #[serde(deserialize_with = "deserialize_action")]
+    pub action: KeyEvent,
+    #[serde(default)]
+    pub trigger: Trigger,
+    #[serde(default)]
+    pub repeat: Option<RepeatPolicy>, // None uses the action's default
+    #[serde(default)]
+    pub consume: bool, // keep the matched keys from reaching the focused app
+    #[serde(default)]
+    pub device: Option<String>, // device name, None plays wherever playback is active
+}
+
+// An action fired by a binding, as the listener sends it on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fired {
+    pub action: KeyEvent,
+    pub device: Option<String>,
+}
+
+impl Binding {
+    pub fn new(action: KeyEvent) -> Self {
+        Self { keys: None, action, trigger: Trigger::Press, repeat: None, consume: false, device: None }
+    }
+
+    pub fn fired(&self) -> Fired {
+        Fired { action: self.action.clone(), device: self.device.clone() }
+    }
+
+    pub fn repeat_policy(&self) -> RepeatPolicy {
+        self.repeat.unwrap_or_else(|| self.action.default_repeat())
+    }
+
+    pub fn is_unbound(&self) -> bool {
+        self.keys.is_none()
+    }
+}
+
+// One empty binding per action, used when there are no saved binds yet
+pub fn default_binds() -> Vec<Binding> {
+    KeyEvent::ALL.into_iter().map(Binding::new).collect()
+}
+
+// How long the listener waits for the next chord of a sequence
+pub const DEFAULT_SEQUENCE_TIMEOUT: Duration = Duration::from_millis(1000);
+
+// Tracks progress through multi-chord bindings such as "Ctrl+Alt+S, N".
+// Single-chord bindings are sequences of length one and complete straight away.
+pub struct SequenceMatcher {
+    binds: Vec<Binding>,
+    timeout: Duration,
+    candidates: Vec<usize>, // binds whose first `matched` chords have been pressed
+    matched: usize,
+    last_press: Option<Instant>,
+}
+
+impl SequenceMatcher {
+    pub fn new(binds: &[Binding], timeout: Duration) -> Self {
+        Self {
+            // Unbound rows never fire
+            binds: binds.iter().filter(|b| !b.is_unbound()).cloned().collect(),
+            timeout,
+            candidates: Vec::new(),
+            matched: 0,
+            last_press: None,
+        }
+    }
+
+    pub fn binding(&self, index: usize) -> &Binding {
+        &self.binds[index]
+    }
+
+    pub fn is_pending(&self) -> bool {
+        self.matched > 0
+    }
+
+    // Feed one non-modifier key press and return the binds it completes.
+    pub fn press(&mut self, key: Key, held: &HeldModifiers, now: Instant) -> Vec<usize> {
+        if self.last_press.is_some_and(|last| now.duration_since(last) > self.timeout) {
+            self.reset();
+        }
+
+        if self.is_pending() {
+            let next = self.step(self.candidates.iter().copied(), key, held);
+            if !next.is_empty() {
+                return self.advance(next, now);
+            }
+            // Wrong key mid-sequence: drop it and see if this press starts something new
+            self.reset();
+        }
+
+        let first = self.step(0..self.binds.len(), key, held);
+        self.advance(first, now)
+    }
+
+    fn chords(&self, index: usize) -> &[KeyChord] {
+        self.binds[index].keys.as_ref().map(KeySequence::chords).unwrap_or_default()
+    }
+
+    fn step(&self, candidates: impl Iterator<Item = usize>, key: Key, held: &HeldModifiers) -> Vec<usize> {
+        candidates
+            .filter(|i| self.chords(*i).get(self.matched).is_some_and(|c| c.matches(key, held)))
+            .collect()
+    }
+
+    fn advance(&mut self, candidates: Vec<usize>, now: Instant) -> Vec<usize> {
+        if candidates.is_empty() {
+            return Vec::new();
+        }
+
+        let matched = self.matched + 1;
+        let complete: Vec<usize> = candidates
+            .iter()
+            .copied()
+            .filter(|i| self.chords(*i).len() == matched)
+            .collect();
+
+        // A complete binding wins over longer sequences sharing its prefix
+        if !complete.is_empty() {
+            self.reset();
+            return complete;
+        }
+
+        self.candidates = candidates;
+        self.matched = matched;
+        self.last_press = Some(now);
+        Vec::new()
+    }
+
+    fn reset(&mut self) {
+        self.candidates.clear();
+        self.matched = 0;
+        self.last_press = None;
+    }
+}
+
+// Longest gap between taps that still counts towards a multi-tap
+pub const MULTI_TAP_WINDOW: Duration = Duration::from_millis(300);
+
+// Presses and releases of the key that completed a hold/tap/release binding
+struct Gesture {
+    key: Key,
+    binds: Vec<usize>,
+    taps: u32,
+    down_at: Option<Instant>, // Some while the key is held
+    released_at: Option<Instant>,
+    fired_holds: Vec<usize>, // hold binds already fired during this press
+}
+
+// A held press binding with a repeat policy
+struct Repeating {
+    key: Key,
+    fired: Fired,
+    interval: Duration,
+    min_interval: Duration,
+    next_at: Instant,
+}
+
+// Turns raw key events into actions: tracks modifiers, walks sequences and
+// times holds and taps. `poll` must be called by `next_deadline` for holds and
+// multi-taps to resolve when no further key events arrive.
+pub struct TriggerEngine {
+    sequences: SequenceMatcher,
+    held: HeldModifiers,
+    down: HashSet<Key>,
+    gesture: Option<Gesture>,
+    repeating: Vec<Repeating>,
+}
+
+impl TriggerEngine {
+    pub fn new(binds: &[Binding], sequence_timeout: Duration) -> Self {
+        Self {
+            sequences: SequenceMatcher::new(binds, sequence_timeout),
+            held: HeldModifiers::default(),
+            down: HashSet::new(),
+            gesture: None,
+            repeating: Vec::new(),
+        }
+    }
+
+    pub fn handle(&mut self, event: EventType, now: Instant) -> Vec<Fired> {
+        let mut out = self.poll(now);
+        for event in key_events(event) {
+            match event {
+                EventType::KeyPress(key) => self.press(key, now, &mut out),
+                EventType::KeyRelease(key) => self.release(key, now, &mut out),
+                _ => {}
+            }
+        }
+        out
+    }
+
+    // Fire holds that have been held long enough, taps whose window has closed
+    // and repeats that are due
+    pub fn poll(&mut self, now: Instant) -> Vec<Fired> {
+        let mut out = Vec::new();
+        for r in self.repeating.iter_mut() {
+            // Step from the scheduled time so a late poll catches up on every repeat it missed
+            while now >= r.next_at {
+                out.push(r.fired.clone());
+                r.interval = r.interval.mul_f64(REPEAT_ACCELERATION).max(r.min_interval);
+                r.next_at += r.interval;
+            }
+        }
+
+        let Some(g) = self.gesture.as_mut() else {
+            return out;
+        };
+
+        if let Some(down_at) = g.down_at {
+            for i in &g.binds {
+                let bind = self.sequences.binding(*i);
+                let Trigger::Hold(ms) = bind.trigger else {
+                    continue;
+                };
+                if now.duration_since(down_at) >= Duration::from_millis(ms) && !g.fired_holds.contains(i) {
+                    g.fired_holds.push(*i);
+                    out.push(bind.fired());
+                }
+            }
+        } else if g.released_at.is_some_and(|r| now.duration_since(r) >= MULTI_TAP_WINDOW) {
+            out.extend(self.finish_taps());
+        }
+        out
+    }
+
+    // The input has ended: settle a pending multi-tap and stop holds and repeats
+    pub fn flush(&mut self) -> Vec<Fired> {
+        self.repeating.clear();
+        match &self.gesture {
+            Some(g) if g.down_at.is_none() => self.finish_taps(),
+            _ => {
+                self.gesture = None;
+                Vec::new()
+            }
+        }
+    }
+
+    pub fn next_deadline(&self) -> Option<Instant> {
+        let repeat = self.repeating.iter().map(|r| r.next_at).min();
+        let gesture = self.gesture_deadline();
+        match (repeat, gesture) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        }
+    }
+
+    fn gesture_deadline(&self) -> Option<Instant> {
+        let g = self.gesture.as_ref()?;
+        match g.down_at {
+            Some(down_at) => g
+                .binds
+                .iter()
+                .filter(|i| !g.fired_holds.contains(i))
+                .filter_map(|i| match self.sequences.binding(*i).trigger {
+                    Trigger::Hold(ms) => Some(down_at + Duration::from_millis(ms)),
+                    _ => None,
+                })
+                .min(),
+            None => g.released_at.map(|r| r + MULTI_TAP_WINDOW),
+        }
+    }
+
+    fn press(&mut self, key: Key, now: Instant, out: &mut Vec<Fired>) {
+        // Track modifier key states
+        if self.held.update(key, true) {
+            return;
+        }
+
+        // OS auto-repeat: repeats are timed by `poll` instead
+        if !self.down.insert(key) {
+            return;
+        }
+
+        // Another key interrupts a pending multi-tap and any repeat
+        if self.gesture.as_ref().is_some_and(|g| g.key != key) {
+            out.extend(self.finish_taps());
+        }
+        self.repeating.clear();
+
+        let complete = self.sequences.press(key, &self.held, now);
+        let mut timed = Vec::new();
+        for i in complete {
+            let bind = self.sequences.binding(i);
+            if bind.trigger != Trigger::Press {
+                timed.push(i);
+                continue;
+            }
+            out.push(bind.fired());
+            if let RepeatPolicy::Repeat { interval_ms, min_interval_ms } = bind.repeat_policy() {
+                let interval_ms = interval_ms.max(MIN_REPEAT_MS);
+                self.repeating.push(Repeating {
+                    key,
+                    fired: bind.fired(),
+                    interval: Duration::from_millis(interval_ms),
+                    min_interval: Duration::from_millis(min_interval_ms.clamp(MIN_REPEAT_MS, interval_ms)),
+                    next_at: now + Duration::from_millis(interval_ms),
+                });
+            }
+        }
+
+        match self.gesture.as_mut() {
+            Some(g) if g.key == key => {
+                g.down_at = Some(now);
+                g.fired_holds.clear();
+            }
+            _ if !timed.is_empty() => {
+                self.gesture = Some(Gesture {
+                    key,
+                    binds: timed,
+                    taps: 0,
+                    down_at: Some(now),
+                    released_at: None,
+                    fired_holds: Vec::new(),
+                });
+            }
+            _ => {}
+        }
+    }
+
+    fn release(&mut self, key: Key, now: Instant, out: &mut Vec<Fired>) {
+        if self.held.update(key, false) {
+            return;
+        }
+        self.down.remove(&key);
+        self.repeating.retain(|r| r.key != key);
+
+        let Some(g) = self.gesture.as_mut() else {
+            return;
+        };
+        if g.key != key || g.down_at.take().is_none() {
+            return;
+        }
+
+        // A hold that fired uses up this press
+        if !g.fired_holds.is_empty() {
+            self.gesture = None;
+            return;
+        }
+
+        let mut most_taps = 0;
+        for i in &g.binds {
+            match self.sequences.binding(*i).trigger {
+                Trigger::Release => out.push(self.sequences.binding(*i).fired()),
+                Trigger::Tap(count) => most_taps = most_taps.max(count),
+                _ => {}
+            }
+        }
+
+        g.taps += 1;
+        g.released_at = Some(now);
+        if g.taps >= most_taps {
+            out.extend(self.finish_taps());
+        }
+    }
+
+    // Fire the multi-tap bind matching the taps counted so far and forget the gesture
+    fn finish_taps(&mut self) -> Vec<Fired> {
+        let Some(g) = self.gesture.take() else {
+            return Vec::new();
+        };
+        g.binds
+            .iter()
+            .map(|i| self.sequences.binding(*i))
+            .filter(|b| b.trigger == Trigger::Tap(g.taps))
+            .map(Binding::fired)
+            .collect()
+    }
 }
 
-pub fn capture_key_input(ctx: &egui::Context) -> Option<String> {
-    
+// `super_held` comes from the platform, since egui only reports Super as Cmd on macOS
+pub fn capture_key_input(ctx: &egui::Context, super_held: bool) -> Option<KeyChord> {
+    // egui doesn't say which side a modifier was pressed on, so recorded
+    // chords accept either. Side-specific chords can be typed in instead.
+    let chord = |modifiers: egui::Modifiers, key: Key| {
+        let side = |held: bool| if held { ModSide::Either } else { ModSide::Off };
+        KeyChord {
+            modifiers: ModifierSet {
+                ctrl: side(modifiers.ctrl),
+                shift: side(modifiers.shift),
+                alt: side(modifiers.alt),
+                meta: side(modifiers.mac_cmd || super_held),
+            },
+            key,
+        }
+    };
+
     for event in &ctx.input(|i| i.events.clone()) {
-        if let egui::Event::Key { key, pressed: true, .. } = event {
-            let modifiers = ctx.input(|i| i.modifiers);
-            
-            
-            let mut combo = String::new();
-            
-            if modifiers.ctrl {
-                combo.push_str("Ctrl+");
+        match event {
+            egui::Event::Key { key, physical_key, pressed: true, .. } => {
+                let modifiers = ctx.input(|i| i.modifiers);
+
+                // prefer the physical key so the chord matches what rdev sees on any layout
+                let Some(key) = physical_key.and_then(egui_to_key).or_else(|| egui_to_key(*key)) else {
+                    println!("Unsupported key: {:?}", key);
+                    continue;
+                };
+                return Some(chord(modifiers, key));
             }
-            if modifiers.shift {
-                combo.push_str("Shift+");
+            egui::Event::PointerButton { button, pressed: true, modifiers, .. } => {
+                use egui::PointerButton::*;
+                // Plain left and right clicks are for working the UI
+                let key = match button {
+                    Primary | Secondary if !modifiers.any() => continue,
+                    Primary => MOUSE_LEFT,
+                    Secondary => MOUSE_RIGHT,
+                    Middle => MOUSE_MIDDLE,
+                    Extra1 => MOUSE_BACK,
+                    Extra2 => MOUSE_FORWARD,
+                };
+                return Some(chord(*modifiers, key));
             }
-            if modifiers.alt {
-                combo.push_str("Alt+");
+            // Plain scrolling is for the UI too. egui's delta is the way the content
+            // moves, so scrolling up gives a positive y.
+            egui::Event::MouseWheel { delta, modifiers, .. } if modifiers.any() => {
+                let key = if delta.y > 0.0 {
+                    WHEEL_UP
+                } else if delta.y < 0.0 {
+                    WHEEL_DOWN
+                } else if delta.x > 0.0 {
+                    WHEEL_LEFT
+                } else if delta.x < 0.0 {
+                    WHEEL_RIGHT
+                } else {
+                    continue;
+                };
+                return Some(chord(*modifiers, key));
             }
-            
-            // for egui key events (capture), use debug name like "A" or "Enter"
-            combo.push_str(&format!("{:?}", key));
-            
-            return Some(combo);
+            _ => {}
         }
     }
     None
 }
 
-pub fn listenforkey_send(
-    tx: UnboundedSender<KeyEvent>,
-    toggle_key_str: String,
-    play_key_str: String,
-    pause_key_str: String,
-    next_key_str: String,
-    previous_key_str: String,
-    volup_key_str: String,
-    voldown_key_str: String,
-    mute_key_str: String,
-) {
-    
-    
-    // Parse the keybinds using str_to_key
-    let toggle_key = str_to_key(&toggle_key_str);
-    let play_key = str_to_key(&play_key_str);
-    let pause_key = str_to_key(&pause_key_str);
-    let next_key = str_to_key(&next_key_str);
-    let previous_key = str_to_key(&previous_key_str);
-    let volup_key = str_to_key(&volup_key_str);
-    let voldown_key = str_to_key(&voldown_key_str);
-    let mute_key = str_to_key(&mute_key_str);
-
-    // Debug: print parsed binds
-    eprintln!("[LISTENER] Parsed binds:");
-    eprintln!("  Toggle: {} -> {:?}", toggle_key_str, toggle_key);
-    eprintln!("  Play: {} -> {:?}", play_key_str, play_key);
-    eprintln!("  Pause: {} -> {:?}", pause_key_str, pause_key);
-    eprintln!("  Next: {} -> {:?}", next_key_str, next_key);
-    eprintln!("  Previous: {} -> {:?}", previous_key_str, previous_key);
-    eprintln!("  VolUp: {} -> {:?}", volup_key_str, volup_key);
-    eprintln!("  VolDown: {} -> {:?}", voldown_key_str, voldown_key);
-    eprintln!("  Mute: {} -> {:?}", mute_key_str, mute_key);
-
-    
-    // Create shared state for modifier keys
-    let mut ctrl_pressed = false;
-    let mut shift_pressed = false;
-    let mut alt_pressed = false;
-    
-    if let Err(error) = listen(move |event| {
-        match event.event_type {
+// Mouse buttons and wheel ticks go through the engine as stand-in keys, so they
+// get modifiers, sequences and triggers the same as keys do
+const MOUSE_KEY_BASE: u32 = 0x20000;
+pub const MOUSE_LEFT: Key = Key::Unknown(MOUSE_KEY_BASE);
+pub const MOUSE_RIGHT: Key = Key::Unknown(MOUSE_KEY_BASE + 1);
+pub const MOUSE_MIDDLE: Key = Key::Unknown(MOUSE_KEY_BASE + 2);
+pub const MOUSE_BACK: Key = Key::Unknown(MOUSE_KEY_BASE + 3);
+pub const MOUSE_FORWARD: Key = Key::Unknown(MOUSE_KEY_BASE + 4);
+pub const WHEEL_UP: Key = Key::Unknown(MOUSE_KEY_BASE + 5);
+pub const WHEEL_DOWN: Key = Key::Unknown(MOUSE_KEY_BASE + 6);
+pub const WHEEL_LEFT: Key = Key::Unknown(MOUSE_KEY_BASE + 7);
+pub const WHEEL_RIGHT: Key = Key::Unknown(MOUSE_KEY_BASE + 8);
+
+pub fn is_mouse(key: Key) -> bool {
+    matches!(key, Key::Unknown(code) if (MOUSE_KEY_BASE..=MOUSE_KEY_BASE + 8).contains(&code))
+}
+
+// rdev's codes for the back and forward side buttons
+#[cfg(target_os = "windows")]
+pub const SIDE_BUTTONS: (u8, u8) = (1, 2);
+#[cfg(not(target_os = "windows"))]
+pub const SIDE_BUTTONS: (u8, u8) = (8, 9);
+
+pub fn button_key(button: Button) -> Option<Key> {
+    match button {
+        Button::Left => Some(MOUSE_LEFT),
+        Button::Right => Some(MOUSE_RIGHT),
+        Button::Middle => Some(MOUSE_MIDDLE),
+        Button::Unknown(code) if code == SIDE_BUTTONS.0 => Some(MOUSE_BACK),
+        Button::Unknown(code) if code == SIDE_BUTTONS.1 => Some(MOUSE_FORWARD),
+        Button::Unknown(_) => None,
+    }
+}
+
+// Input as the key presses and releases the engine works with. Each wheel notch
+// is a press and an immediate release; mouse movement is dropped.
+pub fn key_events(event: EventType) -> Vec<EventType> {
+    match event {
+        EventType::KeyPress(_) | EventType::KeyRelease(_) => vec![event],
+        EventType::ButtonPress(button) => button_key(button).map(EventType::KeyPress).into_iter().collect(),
+        EventType::ButtonRelease(button) => button_key(button).map(EventType::KeyRelease).into_iter().collect(),
+        EventType::Wheel { delta_x, delta_y } => {
+            let ticks = |delta: i64, positive: Key, negative: Key| {
+                let key = if delta > 0 { positive } else { negative };
+                (0..delta.unsigned_abs()).flat_map(move |_| [EventType::KeyPress(key), EventType::KeyRelease(key)])
+            };
+            ticks(delta_y, WHEEL_UP, WHEEL_DOWN).chain(ticks(delta_x, WHEEL_RIGHT, WHEEL_LEFT)).collect()
+        }
+        EventType::MouseMove { .. } => Vec::new(),
+    }
+}
+
+// Decides, as each key event happens, whether a grabbing input source should keep it
+// from the focused app. Only the key of a chord that matches (or moves along) a
+// consuming bind is held back, together with its auto-repeats and release;
+// modifiers and every other key pass through.
+pub struct ConsumeFilter {
+    sequences: SequenceMatcher,
+    held: HeldModifiers,
+    swallowed: HashSet<Key>,
+}
+
+impl ConsumeFilter {
+    pub fn new(binds: &[Binding], sequence_timeout: Duration) -> Self {
+        let consuming: Vec<Binding> = binds.iter().filter(|b| b.consume).cloned().collect();
+        Self {
+            sequences: SequenceMatcher::new(&consuming, sequence_timeout),
+            held: HeldModifiers::default(),
+            swallowed: HashSet::new(),
+        }
+    }
+
+    pub fn consume(&mut self, event: &EventType, now: Instant) -> bool {
+        // A wheel tick is held back if its press is
+        let mut consumed = false;
+        for event in key_events(*event) {
+            consumed |= self.consume_key(event, now);
+        }
+        consumed
+    }
+
+    fn consume_key(&mut self, event: EventType, now: Instant) -> bool {
+        match event {
             EventType::KeyPress(key) => {
-                // Track modifier key states
-                match key {
-                    Key::ControlLeft | Key::ControlRight => {
-                        ctrl_pressed = true;
-                        return;
-                    },
-                    Key::ShiftLeft | Key::ShiftRight => {
-                        shift_pressed = true;
-                        return;
-                    },
-                    Key::Alt => {
-                        alt_pressed = true;
-                        return;
-                    },
-                    _ => {}
+                if self.held.update(key, true) {
+                    return false;
                 }
-                
-                // Get current modifier states
-                let has_ctrl = ctrl_pressed;
-                let has_shift = shift_pressed;
-                let has_alt = alt_pressed;
-                
-                println!("Detected key: {:?} | Ctrl: {}, Shift: {}, Alt: {}", key, has_ctrl, has_shift, has_alt);
-                
-                // Compare with keybinds (check key and modifiers match)
-
-                if let Some(tk) = toggle_key {
-                    if key == tk && has_ctrl == toggle_key_str.contains("Ctrl") && 
-                       has_shift == toggle_key_str.contains("Shift") && has_alt == toggle_key_str.contains("Alt") {
-                        let _ = tx.send(KeyEvent::Toggle);
-                    }
-                }
-                if let Some(pk) = play_key {
-                    if key == pk && has_ctrl == play_key_str.contains("Ctrl") && 
-                       has_shift == play_key_str.contains("Shift") && has_alt == play_key_str.contains("Alt") {
-                        let _ = tx.send(KeyEvent::Play);
-                    }
-                }
-                if let Some(psk) = pause_key {
-                    if key == psk && has_ctrl == pause_key_str.contains("Ctrl") && 
-                       has_shift == pause_key_str.contains("Shift") && has_alt == pause_key_str.contains("Alt") {
-                        let _ = tx.send(KeyEvent::Pause);
-                    }
-                }
-                if let Some(nk) = next_key {
-                    if key == nk && has_ctrl == next_key_str.contains("Ctrl") && 
-                       has_shift == next_key_str.contains("Shift") && has_alt == next_key_str.contains("Alt") {
-                        let _ = tx.send(KeyEvent::Next);
-                    }
-                }
-                if let Some(pk) = previous_key {
-                    if key == pk && has_ctrl == previous_key_str.contains("Ctrl") && 
-                       has_shift == previous_key_str.contains("Shift") && has_alt == previous_key_str.contains("Alt") {
-                        let _ = tx.send(KeyEvent::Previous);
-                    }
+                if self.swallowed.contains(&key) {
+                    return true;
                 }
-                if let Some(vu) = volup_key {
-                    if key == vu && has_ctrl == volup_key_str.contains("Ctrl") && 
-                       has_shift == volup_key_str.contains("Shift") && has_alt == volup_key_str.contains("Alt") {
-                        let _ = tx.send(KeyEvent::Volup);
-                    }
+                let complete = self.sequences.press(key, &self.held, now);
+                let consumed = !complete.is_empty() || self.sequences.is_pending();
+                if consumed {
+                    self.swallowed.insert(key);
                 }
-                if let Some(vd) = voldown_key {
-                    if key == vd && has_ctrl == voldown_key_str.contains("Ctrl") && 
-                       has_shift == voldown_key_str.contains("Shift") && has_alt == voldown_key_str.contains("Alt") {
-                        let _ = tx.send(KeyEvent::Voldown);
+                consumed
+            }
+            EventType::KeyRelease(key) => {
+                self.held.update(key, false);
+                self.swallowed.remove(&key)
+            }
+            _ => false,
+        }
+    }
+}
+
+// A raw key or mouse event and when it happened
+pub type InputEvent = (EventType, Instant);
+
+// Where input events come from. `run` blocks, sending every key and mouse button
+// press and release and every wheel tick to `sink`, and returns once the source has
+// nothing more to send.
+pub trait InputSource {
+    type Error: fmt::Debug;
+
+    fn run(self, sink: mpsc::Sender<InputEvent>) -> Result<(), Self::Error>;
+}
+
+// The global keyboard hook. Never returns unless the hook fails.
+pub struct RdevInput;
+
+impl InputSource for RdevInput {
+    type Error = rdev::ListenError;
+
+    fn run(self, sink: mpsc::Sender<InputEvent>) -> Result<(), Self::Error> {
+        listen(move |event| {
+            if !matches!(event.event_type, EventType::MouseMove { .. }) {
+                let _ = sink.send((event.event_type, Instant::now()));
+            }
+        })
+    }
+}
+
+// The global keyboard hook in grabbing mode, which can hold events back from
+// other apps. Everything is still sent on to the engine.
+#[cfg(windows)]
+pub struct GrabInput {
+    filter: std::cell::RefCell<ConsumeFilter>,
+}
+
+#[cfg(windows)]
+impl GrabInput {
+    pub fn new(binds: &[Binding], sequence_timeout: Duration) -> Self {
+        Self { filter: std::cell::RefCell::new(ConsumeFilter::new(binds, sequence_timeout)) }
+    }
+}
+
+#[cfg(windows)]
+impl InputSource for GrabInput {
+    type Error = rdev::GrabError;
+
+    fn run(self, sink: mpsc::Sender<InputEvent>) -> Result<(), Self::Error> {
+        rdev::grab(move |event| {
+            if matches!(event.event_type, EventType::MouseMove { .. }) {
+                return Some(event);
+            }
+            let now = Instant::now();
+            let consume = self.filter.borrow_mut().consume(&event.event_type, now);
+            let _ = sink.send((event.event_type, now));
+            if consume { None } else { Some(event) }
+        })
+    }
+}
+
+// Replays a fixed list of input events, for driving the listener without a keyboard.
+// Times are milliseconds from when `run` starts; nothing actually sleeps.
+#[derive(Default)]
+pub struct ScriptedInput {
+    events: Vec<(u64, EventType)>,
+}
+
+impl ScriptedInput {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn press(mut self, at_ms: u64, key: Key) -> Self {
+        self.events.push((at_ms, EventType::KeyPress(key)));
+        self
+    }
+
+    pub fn release(mut self, at_ms: u64, key: Key) -> Self {
+        self.events.push((at_ms, EventType::KeyRelease(key)));
+        self
+    }
+
+    pub fn event(mut self, at_ms: u64, event: EventType) -> Self {
+        self.events.push((at_ms, event));
+        self
+    }
+
+    // Scroll by `notches`, positive being up
+    pub fn wheel(self, at_ms: u64, notches: i64) -> Self {
+        self.event(at_ms, EventType::Wheel { delta_x: 0, delta_y: notches })
+    }
+
+    // Press and release `key`, holding it for `held_ms`
+    pub fn tap(self, at_ms: u64, key: Key, held_ms: u64) -> Self {
+        self.press(at_ms, key).release(at_ms + held_ms, key)
+    }
+}
+
+impl InputSource for ScriptedInput {
+    type Error = mpsc::SendError<InputEvent>;
+
+    fn run(mut self, sink: mpsc::Sender<InputEvent>) -> Result<(), Self::Error> {
+        let start = Instant::now();
+        self.events.sort_by_key(|(at, _)| *at);
+        for (at_ms, event) in self.events {
+            sink.send((event, start + Duration::from_millis(at_ms)))?;
+        }
+        Ok(())
+    }
+}
+
+// `warn` is told about binds that won't work with the input source picked here
+#[cfg_attr(windows, allow(unused_variables))]
+pub fn listenforkey_send(tx: UnboundedSender<Fired>, binds: Vec<Binding>, sequence_timeout: Duration, warn: impl Fn(String)) {
+    let consume = binds.iter().any(|b| b.consume && !b.is_unbound());
+
+    #[cfg(windows)]
+    if consume {
+        return run_listener(GrabInput::new(&binds, sequence_timeout), tx, binds, sequence_timeout);
+    }
+
+    // The X11 hook can't see keys typed into Wayland windows. Ask the desktop portal for
+    // the shortcuts there, and failing that read the devices directly.
+    #[cfg(target_os = "linux")]
+    {
+        let wayland = std::env::var("XDG_SESSION_TYPE").is_ok_and(|t| t == "wayland") || std::env::var_os("DISPLAY").is_none();
+        if wayland {
+            // Portal shortcuts never reach the focused app, so this covers consuming binds too
+            match portal::PortalInput::connect(&binds) {
+                Ok(source) => {
+                    let skipped: Vec<String> = portal::skipped_binds(&binds)
+                        .filter_map(|bind| Some(format!("{} ({})", bind.keys.as_ref()?, bind.action.label())))
+                        .collect();
+                    if !skipped.is_empty() {
+                        warn(format!(
+                            "The desktop's shortcuts portal can't take sequences or mouse binds, so these won't work: {}",
+                            skipped.join(", ")
+                        ));
                     }
+                    return run_listener(source, tx, portal::portal_binds(&binds), sequence_timeout);
                 }
-                if let Some(mk) = mute_key {
-                    if key == mk && has_ctrl == mute_key_str.contains("Ctrl") && 
-                       has_shift == mute_key_str.contains("Shift") && has_alt == mute_key_str.contains("Alt") {
-                        let _ = tx.send(KeyEvent::Mute);
+                Err(e) => println!("Global shortcuts portal unavailable, reading input devices instead: {}", e),
+            }
+        }
+        if consume {
+            match evdev::EvdevGrab::open(&binds, sequence_timeout) {
+                Ok(source) => return run_listener(source, tx, binds, sequence_timeout),
+                Err(e) => warn(format!("Could not grab the keyboards, bound keys will still reach other apps: {}", e)),
+            }
+        }
+        if wayland {
+            match evdev::EvdevInput::open() {
+                Ok(source) => return run_listener(source, tx, binds, sequence_timeout),
+                Err(e) => println!("Could not read input devices, falling back to X11: {}", e),
+            }
+        }
+    }
+
+    #[cfg(not(any(windows, target_os = "linux")))]
+    if consume {
+        warn("Consuming keys isn't supported here, bound keys will still reach other apps".to_owned());
+    }
+    run_listener(RdevInput, tx, binds, sequence_timeout);
+}
+
+// Feed `source` through the bind table, sending matched actions to `tx`.
+// Blocks until the source ends.
+pub fn run_listener<S: InputSource>(source: S, tx: UnboundedSender<Fired>, binds: Vec<Binding>, sequence_timeout: Duration) {
+    // Input sources only call us back on events, so events go to a second thread
+    // that can also wake up on its own when a hold, multi-tap or repeat is due.
+    let (raw_tx, raw_rx) = mpsc::channel::<InputEvent>();
+    let engine_thread = std::thread::spawn(move || {
+        let mut engine = TriggerEngine::new(&binds, sequence_timeout);
+        loop {
+            let deadline = engine.next_deadline();
+            let received = match deadline {
+                Some(deadline) => raw_rx.recv_timeout(deadline.saturating_duration_since(Instant::now())),
+                None => raw_rx.recv().map_err(|_| RecvTimeoutError::Disconnected),
+            };
+            let actions = match (received, deadline) {
+                (Ok((event, at)), _) => engine.handle(event, at),
+                (Err(RecvTimeoutError::Timeout), Some(deadline)) => engine.poll(deadline),
+                (Err(RecvTimeoutError::Timeout), None) => Vec::new(),
+                (Err(RecvTimeoutError::Disconnected), _) => {
+                    for action in engine.flush() {
+                        let _ = tx.send(action);
                     }
+                    break;
                 }
-            },
-            EventType::KeyRelease(key) => {
-                
-                match key {
-                    Key::ControlLeft | Key::ControlRight => {
-                        ctrl_pressed = false;
-                    },
-                    Key::ShiftLeft | Key::ShiftRight => {
-                        shift_pressed = false;
-                    },
-                    Key::Alt => {
-                        alt_pressed = false;
-                    },
-                    _ => {}
-                }
+            };
+            for action in actions {
+                let _ = tx.send(action);
             }
-            _ => {}
         }
-    }) {
+    });
+
+    if let Err(error) = source.run(raw_tx) {
         println!("Error: {:?}", error);
     }
+    let _ = engine_thread.join();
+}
+
+
+// Which physical key(s) satisfy a modifier in a chord
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum ModSide {
+    #[default]
+    Off, // must not be held
+    Either,
+    Left,
+    Right,
+}
+
+impl ModSide {
+    pub const ALL: [ModSide; 4] = [ModSide::Off, ModSide::Either, ModSide::Left, ModSide::Right];
+
+    fn matches(self, left: bool, right: bool) -> bool {
+        match self {
+            ModSide::Off => !left && !right,
+            ModSide::Either => left || right,
+            ModSide::Left => left && !right,
+            ModSide::Right => right && !left,
+        }
+    }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Modifier {
+    Ctrl,
+    Shift,
+    Alt,
+    Meta,
+}
 
+// Modifier names in a chord. The first name for a modifier and side is the one
+// written back out. Right Alt is AltGr, and Super is the Windows/Command key.
+const MODIFIER_NAMES: &[(&str, Modifier, ModSide)] = &[
+    ("Ctrl", Modifier::Ctrl, ModSide::Either),
+    ("LeftCtrl", Modifier::Ctrl, ModSide::Left),
+    ("RightCtrl", Modifier::Ctrl, ModSide::Right),
+    ("Shift", Modifier::Shift, ModSide::Either),
+    ("LeftShift", Modifier::Shift, ModSide::Left),
+    ("RightShift", Modifier::Shift, ModSide::Right),
+    ("Alt", Modifier::Alt, ModSide::Either),
+    ("LeftAlt", Modifier::Alt, ModSide::Left),
+    ("AltGr", Modifier::Alt, ModSide::Right),
+    ("Super", Modifier::Meta, ModSide::Either),
+    ("LeftSuper", Modifier::Meta, ModSide::Left),
+    ("RightSuper", Modifier::Meta, ModSide::Right),
+
+    ("Control", Modifier::Ctrl, ModSide::Either),
+    ("RightAlt", Modifier::Alt, ModSide::Right),
+    ("Meta", Modifier::Meta, ModSide::Either),
+    ("Win", Modifier::Meta, ModSide::Either),
+    ("Cmd", Modifier::Meta, ModSide::Either),
+];
+
+// Modifiers that have to be held for a chord to fire
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct ModifierSet {
+    pub ctrl: ModSide,
+    pub shift: ModSide,
+    pub alt: ModSide,
+    pub meta: ModSide,
+}
+
+impl ModifierSet {
+    pub const NONE: ModifierSet = ModifierSet {
+        ctrl: ModSide::Off,
+        shift: ModSide::Off,
+        alt: ModSide::Off,
+        meta: ModSide::Off,
+    };
+
+    // Every combination of modifiers, used to enumerate chords in tests
+    pub fn all() -> impl Iterator<Item = ModifierSet> {
+        ModSide::ALL.into_iter().flat_map(|ctrl| {
+            ModSide::ALL.into_iter().flat_map(move |shift| {
+                ModSide::ALL.into_iter().flat_map(move |alt| {
+                    ModSide::ALL.into_iter().map(move |meta| ModifierSet { ctrl, shift, alt, meta })
+                })
+            })
+        })
+    }
+
+    fn get_mut(&mut self, modifier: Modifier) -> &mut ModSide {
+        match modifier {
+            Modifier::Ctrl => &mut self.ctrl,
+            Modifier::Shift => &mut self.shift,
+            Modifier::Alt => &mut self.alt,
+            Modifier::Meta => &mut self.meta,
+        }
+    }
+
+    fn names(&self) -> impl Iterator<Item = &'static str> + '_ {
+        [
+            (Modifier::Ctrl, self.ctrl),
+            (Modifier::Shift, self.shift),
+            (Modifier::Alt, self.alt),
+            (Modifier::Meta, self.meta),
+        ]
+        .into_iter()
+        .filter(|(_, side)| *side != ModSide::Off)
+        .filter_map(|(modifier, side)| {
+            MODIFIER_NAMES
+                .iter()
+                .find(|(_, m, s)| *m == modifier && *s == side)
+                .map(|(name, _, _)| *name)
+        })
+    }
+}
+
+// Physical modifier keys currently held down, tracked by the listener.
+// Each pair is (left, right).
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HeldModifiers {
+    ctrl: (bool, bool),
+    shift: (bool, bool),
+    alt: (bool, bool),
+    meta: (bool, bool),
+}
+
+impl HeldModifiers {
+    // Record a press or release. Returns false if `key` isn't a modifier.
+    pub fn update(&mut self, key: Key, down: bool) -> bool {
+        let slot = match key {
+            Key::ControlLeft => &mut self.ctrl.0,
+            Key::ControlRight => &mut self.ctrl.1,
+            Key::ShiftLeft => &mut self.shift.0,
+            Key::ShiftRight => &mut self.shift.1,
+            Key::Alt => &mut self.alt.0,
+            Key::AltGr => &mut self.alt.1,
+            Key::MetaLeft => &mut self.meta.0,
+            Key::MetaRight => &mut self.meta.1,
+            _ => return false,
+        };
+        *slot = down;
+        true
+    }
+
+    pub fn satisfies(&self, modifiers: &ModifierSet) -> bool {
+        modifiers.ctrl.matches(self.ctrl.0, self.ctrl.1)
+            && modifiers.shift.matches(self.shift.0, self.shift.1)
+            && modifiers.alt.matches(self.alt.0, self.alt.1)
+            && modifiers.meta.matches(self.meta.0, self.meta.1)
+    }
+}
+
+// A key plus the modifiers held with it, e.g. "Ctrl+Shift+P".
+// Display and FromStr round-trip, which is the format written to the settings file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct KeyChord {
+    pub modifiers: ModifierSet,
+    pub key: Key,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChordParseError {
+    Empty,
+    UnknownModifier(String),
+    DuplicateModifier(String),
+    UnknownKey(String),
+}
+
+impl fmt::Display for ChordParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChordParseError::Empty => write!(f, "empty key combination"),
+            ChordParseError::UnknownModifier(m) => write!(f, "unknown modifier '{}'", m),
+            ChordParseError::DuplicateModifier(m) => write!(f, "modifier '{}' is given more than once", m),
+            ChordParseError::UnknownKey(k) => write!(f, "unknown key '{}'", k),
+        }
+    }
+}
+
+impl std::error::Error for ChordParseError {}
+
+impl FromStr for KeyChord {
+    type Err = ChordParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.trim().is_empty() {
+            return Err(ChordParseError::Empty);
+        }
+
+        let mut parts: Vec<&str> = s.split('+').map(str::trim).collect();
+        let key_part = parts.pop().unwrap_or_default();
+        let key = str_to_key(key_part).ok_or_else(|| ChordParseError::UnknownKey(key_part.to_owned()))?;
+
+        let mut modifiers = ModifierSet::NONE;
+        for part in parts {
+            let (_, modifier, side) = MODIFIER_NAMES
+                .iter()
+                .find(|(name, _, _)| name.eq_ignore_ascii_case(part))
+                .ok_or_else(|| ChordParseError::UnknownModifier(part.to_owned()))?;
+            let slot = modifiers.get_mut(*modifier);
+            if *slot != ModSide::Off {
+                return Err(ChordParseError::DuplicateModifier(part.to_owned()));
+            }
+            *slot = *side;
+        }
+
+        Ok(KeyChord { modifiers, key })
+    }
+}
+
+impl fmt::Display for KeyChord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for name in self.modifiers.names() {
+            write!(f, "{}+", name)?;
+        }
+        match key_name(self.key) {
+            Some(name) => write!(f, "{}", name),
+            None => write!(f, "{:?}", self.key),
+        }
+    }
+}
+
+impl KeyChord {
+    pub fn matches(&self, key: Key, held: &HeldModifiers) -> bool {
+        self.key == key && held.satisfies(&self.modifiers)
+    }
+}
+
+// One or more chords pressed one after another, written as "Ctrl+Alt+S, N"
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct KeySequence(Vec<KeyChord>);
+
+impl KeySequence {
+    pub fn new(chords: Vec<KeyChord>) -> Option<Self> {
+        if chords.is_empty() {
+            None
+        } else {
+            Some(Self(chords))
+        }
+    }
+
+    pub fn chords(&self) -> &[KeyChord] {
+        &self.0
+    }
+}
+
+impl From<KeyChord> for KeySequence {
+    fn from(chord: KeyChord) -> Self {
+        Self(vec![chord])
+    }
+}
+
+impl FromStr for KeySequence {
+    type Err = ChordParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let chords = s
+            .split(',')
+            .map(str::parse)
+            .collect::<Result<Vec<KeyChord>, _>>()?;
+        Ok(Self(chords))
+    }
+}
+
+impl fmt::Display for KeySequence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, chord) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}", chord)?;
+        }
+        Ok(())
+    }
+}
+
+impl Serialize for KeySequence {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for KeySequence {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(de::Error::custom)
+    }
+}
+
+// Blank or unreadable keys in the settings file load as unbound instead of
+// rejecting the whole file.
+fn deserialize_keys<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<KeySequence>, D::Error> {
+    let s: Option<String> = Option::deserialize(deserializer)?;
+    Ok(s.and_then(|s| s.parse().ok()))
+}
+
+// One row per key a chord can use: the rdev key the listener sees, the name
+// written to the settings file and the egui key the bind recorder gets for it.
+// Modifier keys are left out since they can't be the main key of a chord.
+pub type KeyEntry = (Key, &'static str, Option<egui::Key>);
+
+pub const KEYS: &[KeyEntry] = &[
+    (Key::KeyA, "A", Some(egui::Key::A)),
+    (Key::KeyB, "B", Some(egui::Key::B)),
+    (Key::KeyC, "C", Some(egui::Key::C)),
+    (Key::KeyD, "D", Some(egui::Key::D)),
+    (Key::KeyE, "E", Some(egui::Key::E)),
+    (Key::KeyF, "F", Some(egui::Key::F)),
+    (Key::KeyG, "G", Some(egui::Key::G)),
+    (Key::KeyH, "H", Some(egui::Key::H)),
+    (Key::KeyI, "I", Some(egui::Key::I)),
+    (Key::KeyJ, "J", Some(egui::Key::J)),
+    (Key::KeyK, "K", Some(egui::Key::K)),
+    (Key::KeyL, "L", Some(egui::Key::L)),
+    (Key::KeyM, "M", Some(egui::Key::M)),
+    (Key::KeyN, "N", Some(egui::Key::N)),
+    (Key::KeyO, "O", Some(egui::Key::O)),
+    (Key::KeyP, "P", Some(egui::Key::P)),
+    (Key::KeyQ, "Q", Some(egui::Key::Q)),
+    (Key::KeyR, "R", Some(egui::Key::R)),
+    (Key::KeyS, "S", Some(egui::Key::S)),
+    (Key::KeyT, "T", Some(egui::Key::T)),
+    (Key::KeyU, "U", Some(egui::Key::U)),
+    (Key::KeyV, "V", Some(egui::Key::V)),
+    (Key::KeyW, "W", Some(egui::Key::W)),
+    (Key::KeyX, "X", Some(egui::Key::X)),
+    (Key::KeyY, "Y", Some(egui::Key::Y)),
+    (Key::KeyZ, "Z", Some(egui::Key::Z)),
+
+    (Key::Num0, "0", Some(egui::Key::Num0)),
+    (Key::Num1, "1", Some(egui::Key::Num1)),
+    (Key::Num2, "2", Some(egui::Key::Num2)),
+    (Key::Num3, "3", Some(egui::Key::Num3)),
+    (Key::Num4, "4", Some(egui::Key::Num4)),
+    (Key::Num5, "5", Some(egui::Key::Num5)),
+    (Key::Num6, "6", Some(egui::Key::Num6)),
+    (Key::Num7, "7", Some(egui::Key::Num7)),
+    (Key::Num8, "8", Some(egui::Key::Num8)),
+    (Key::Num9, "9", Some(egui::Key::Num9)),
+
+    (Key::Return, "Enter", Some(egui::Key::Enter)),
+    (Key::Escape, "Escape", Some(egui::Key::Escape)),
+    (Key::Space, "Space", Some(egui::Key::Space)),
+    (Key::Tab, "Tab", Some(egui::Key::Tab)),
+    (Key::Backspace, "Backspace", Some(egui::Key::Backspace)),
+    (Key::Delete, "Delete", Some(egui::Key::Delete)),
+    (Key::Insert, "Insert", Some(egui::Key::Insert)),
+    (Key::LeftArrow, "Left", Some(egui::Key::ArrowLeft)),
+    (Key::RightArrow, "Right", Some(egui::Key::ArrowRight)),
+    (Key::UpArrow, "Up", Some(egui::Key::ArrowUp)),
+    (Key::DownArrow, "Down", Some(egui::Key::ArrowDown)),
+    (Key::Home, "Home", Some(egui::Key::Home)),
+    (Key::End, "End", Some(egui::Key::End)),
+    (Key::PageUp, "PageUp", Some(egui::Key::PageUp)),
+    (Key::PageDown, "PageDown", Some(egui::Key::PageDown)),
+    (Key::Pause, "Pause", None),
+    (Key::PrintScreen, "PrintScreen", None),
+    (Key::ScrollLock, "ScrollLock", None),
+    (Key::CapsLock, "CapsLock", None),
+    (Key::NumLock, "NumLock", None),
+    (Key::Function, "Fn", None),
+
+    (Key::BackQuote, "Backquote", Some(egui::Key::Backtick)),
+    (Key::Minus, "Minus", Some(egui::Key::Minus)),
+    (Key::Equal, "Equals", Some(egui::Key::Equals)),
+    (Key::LeftBracket, "LeftBracket", Some(egui::Key::OpenBracket)),
+    (Key::RightBracket, "RightBracket", Some(egui::Key::CloseBracket)),
+    (Key::SemiColon, "Semicolon", Some(egui::Key::Semicolon)),
+    (Key::Quote, "Quote", Some(egui::Key::Quote)),
+    (Key::BackSlash, "Backslash", Some(egui::Key::Backslash)),
+    (Key::IntlBackslash, "IntlBackslash", None),
+    (Key::Comma, "Comma", Some(egui::Key::Comma)),
+    (Key::Dot, "Period", Some(egui::Key::Period)),
+    (Key::Slash, "Slash", Some(egui::Key::Slash)),
+
+    (Key::Kp0, "Numpad0", None),
+    (Key::Kp1, "Numpad1", None),
+    (Key::Kp2, "Numpad2", None),
+    (Key::Kp3, "Numpad3", None),
+    (Key::Kp4, "Numpad4", None),
+    (Key::Kp5, "Numpad5", None),
+    (Key::Kp6, "Numpad6", None),
+    (Key::Kp7, "Numpad7", None),
+    (Key::Kp8, "Numpad8", None),
+    (Key::Kp9, "Numpad9", None),
+    (Key::KpReturn, "NumpadEnter", None),
+    (Key::KpMinus, "NumpadMinus", None),
+    (Key::KpPlus, "NumpadPlus", None),
+    (Key::KpMultiply, "NumpadMultiply", None),
+    (Key::KpDivide, "NumpadDivide", None),
+    (Key::KpDelete, "NumpadDecimal", None),
+
+    (Key::F1, "F1", Some(egui::Key::F1)),
+    (Key::F2, "F2", Some(egui::Key::F2)),
+    (Key::F3, "F3", Some(egui::Key::F3)),
+    (Key::F4, "F4", Some(egui::Key::F4)),
+    (Key::F5, "F5", Some(egui::Key::F5)),
+    (Key::F6, "F6", Some(egui::Key::F6)),
+    (Key::F7, "F7", Some(egui::Key::F7)),
+    (Key::F8, "F8", Some(egui::Key::F8)),
+    (Key::F9, "F9", Some(egui::Key::F9)),
+    (Key::F10, "F10", Some(egui::Key::F10)),
+    (Key::F11, "F11", Some(egui::Key::F11)),
+    (Key::F12, "F12", Some(egui::Key::F12)),
+
+    (MOUSE_LEFT, "MouseLeft", None),
+    (MOUSE_RIGHT, "MouseRight", None),
+    (MOUSE_MIDDLE, "MouseMiddle", None),
+    (MOUSE_BACK, "MouseBack", None),
+    (MOUSE_FORWARD, "MouseForward", None),
+    (WHEEL_UP, "WheelUp", None),
+    (WHEEL_DOWN, "WheelDown", None),
+    (WHEEL_LEFT, "WheelLeft", None),
+    (WHEEL_RIGHT, "WheelRight", None),
+];
+
+// Keys rdev has no variant for arrive as Key::Unknown with the platform's own
+// code: virtual-key codes on Windows, X11 keycodes on Linux.
+#[cfg(target_os = "windows")]
+const PLATFORM_KEYS: &[KeyEntry] = &[
+    (Key::Unknown(124), "F13", Some(egui::Key::F13)),
+    (Key::Unknown(125), "F14", Some(egui::Key::F14)),
+    (Key::Unknown(126), "F15", Some(egui::Key::F15)),
+    (Key::Unknown(127), "F16", Some(egui::Key::F16)),
+    (Key::Unknown(128), "F17", Some(egui::Key::F17)),
+    (Key::Unknown(129), "F18", Some(egui::Key::F18)),
+    (Key::Unknown(130), "F19", Some(egui::Key::F19)),
+    (Key::Unknown(131), "F20", Some(egui::Key::F20)),
+    (Key::Unknown(132), "F21", Some(egui::Key::F21)),
+    (Key::Unknown(133), "F22", Some(egui::Key::F22)),
+    (Key::Unknown(134), "F23", Some(egui::Key::F23)),
+    (Key::Unknown(135), "F24", Some(egui::Key::F24)),
+
+    (Key::Unknown(173), "VolumeMute", None),
+    (Key::Unknown(174), "VolumeDown", None),
+    (Key::Unknown(175), "VolumeUp", None),
+    (Key::Unknown(176), "MediaNext", None),
+    (Key::Unknown(177), "MediaPrevious", None),
+    (Key::Unknown(178), "MediaStop", None),
+    (Key::Unknown(179), "MediaPlayPause", None),
+];
+
+#[cfg(target_os = "linux")]
+const PLATFORM_KEYS: &[KeyEntry] = &[
+    (Key::Unknown(191), "F13", Some(egui::Key::F13)),
+    (Key::Unknown(192), "F14", Some(egui::Key::F14)),
+    (Key::Unknown(193), "F15", Some(egui::Key::F15)),
+    (Key::Unknown(194), "F16", Some(egui::Key::F16)),
+    (Key::Unknown(195), "F17", Some(egui::Key::F17)),
+    (Key::Unknown(196), "F18", Some(egui::Key::F18)),
+    (Key::Unknown(197), "F19", Some(egui::Key::F19)),
+    (Key::Unknown(198), "F20", Some(egui::Key::F20)),
+    (Key::Unknown(199), "F21", Some(egui::Key::F21)),
+    (Key::Unknown(200), "F22", Some(egui::Key::F22)),
+    (Key::Unknown(201), "F23", Some(egui::Key::F23)),
+    (Key::Unknown(202), "F24", Some(egui::Key::F24)),
+
+    (Key::Unknown(121), "VolumeMute", None),
+    (Key::Unknown(122), "VolumeDown", None),
+    (Key::Unknown(123), "VolumeUp", None),
+    (Key::Unknown(171), "MediaNext", None),
+    (Key::Unknown(172), "MediaPlayPause", None),
+    (Key::Unknown(173), "MediaPrevious", None),
+    (Key::Unknown(174), "MediaStop", None),
+];
+
+#[cfg(not(any(target_os = "windows", target_os = "linux")))]
+const PLATFORM_KEYS: &[KeyEntry] = &[];
+
+// egui reports some keys by the symbol they type with Shift held (US layout).
+// These only matter when egui doesn't give us the physical key.
+const EGUI_SHIFTED: &[(egui::Key, Key)] = &[
+    (egui::Key::Colon, Key::SemiColon),
+    (egui::Key::Pipe, Key::BackSlash),
+    (egui::Key::Questionmark, Key::Slash),
+    (egui::Key::Exclamationmark, Key::Num1),
+    (egui::Key::OpenCurlyBracket, Key::LeftBracket),
+    (egui::Key::CloseCurlyBracket, Key::RightBracket),
+    (egui::Key::Plus, Key::Equal),
+];
+
+// Other spellings accepted when parsing, including egui and rdev names
+const KEY_ALIASES: &[(&str, Key)] = &[
+    ("RETURN", Key::Return),
+    ("MOUSE1", MOUSE_LEFT),
+    ("MOUSE2", MOUSE_RIGHT),
+    ("MOUSE3", MOUSE_MIDDLE),
+    ("MOUSE4", MOUSE_BACK),
+    ("MOUSE5", MOUSE_FORWARD),
+    ("ESC", Key::Escape),
+    ("INS", Key::Insert),
+    ("DEL", Key::Delete),
+    ("LEFTARROW", Key::LeftArrow),
+    ("RIGHTARROW", Key::RightArrow),
+    ("UPARROW", Key::UpArrow),
+    ("DOWNARROW", Key::DownArrow),
+    ("ARROWLEFT", Key::LeftArrow),
+    ("ARROWRIGHT", Key::RightArrow),
+    ("ARROWUP", Key::UpArrow),
+    ("ARROWDOWN", Key::DownArrow),
+    ("EQUAL", Key::Equal),
+    ("OPENBRACKET", Key::LeftBracket),
+    ("CLOSEBRACKET", Key::RightBracket),
+    ("BACKTICK", Key::BackQuote),
+    ("DOT", Key::Dot),
+    ("NUM0", Key::Num0),
+    ("NUM1", Key::Num1),
+    ("NUM2", Key::Num2),
+    ("NUM3", Key::Num3),
+    ("NUM4", Key::Num4),
+    ("NUM5", Key::Num5),
+    ("NUM6", Key::Num6),
+    ("NUM7", Key::Num7),
+    ("NUM8", Key::Num8),
+    ("NUM9", Key::Num9),
+    ("KP0", Key::Kp0),
+    ("KP1", Key::Kp1),
+    ("KP2", Key::Kp2),
+    ("KP3", Key::Kp3),
+    ("KP4", Key::Kp4),
+    ("KP5", Key::Kp5),
+    ("KP6", Key::Kp6),
+    ("KP7", Key::Kp7),
+    ("KP8", Key::Kp8),
+    ("KP9", Key::Kp9),
+];
+
+// Every key a chord can use on this platform
+pub fn all_keys() -> impl Iterator<Item = &'static KeyEntry> {
+    KEYS.iter().chain(PLATFORM_KEYS.iter())
+}
+
+pub fn key_name(key: Key) -> Option<&'static str> {
+    all_keys().find(|(k, _, _)| *k == key).map(|(_, name, _)| *name)
+}
+
+pub fn egui_to_key(key: egui::Key) -> Option<Key> {
+    all_keys()
+        .find(|(_, _, e)| *e == Some(key))
+        .map(|(k, _, _)| *k)
+        .or_else(|| EGUI_SHIFTED.iter().find(|(e, _)| *e == key).map(|(_, k)| *k))
+}
+
+// Look up a single key name. Accepts values like "A", "KeyA" or " keya ".
 pub fn str_to_key(s: &str) -> Option<Key> {
-    // Take last part after '+' and normalize. Accept values like "A" or "KeyA" or " KeyA "
-    let key_part = s.split('+').last().unwrap_or(s).trim();
-    let key_part = if key_part.to_uppercase().starts_with("KEY") {
-        // strip leading "Key" prefix
-        key_part[3..].trim()
-    } else {
-        key_part
+    let name = s.trim().to_uppercase();
+    let name = match name.strip_prefix("KEY") {
+        Some(rest) if rest.len() == 1 => rest.to_owned(),
+        _ => name,
     };
 
-    println!("Parsing key part: '{}'", key_part);
-
-    match key_part.to_uppercase().as_str() {
-        "A" => Some(Key::KeyA),
-        "B" => Some(Key::KeyB),
-        "C" => Some(Key::KeyC),
-        "D" => Some(Key::KeyD),
-        "E" => Some(Key::KeyE),
-        "F" => Some(Key::KeyF),
-        "G" => Some(Key::KeyG),
-        "H" => Some(Key::KeyH),
-        "I" => Some(Key::KeyI),
-        "J" => Some(Key::KeyJ),
-        "K" => Some(Key::KeyK),
-        "L" => Some(Key::KeyL),
-        "M" => Some(Key::KeyM),
-        "N" => Some(Key::KeyN),
-        "O" => Some(Key::KeyO),
-        "P" => Some(Key::KeyP),
-        "Q" => Some(Key::KeyQ),
-        "R" => Some(Key::KeyR),
-        "S" => Some(Key::KeyS),
-        "T" => Some(Key::KeyT),
-        "U" => Some(Key::KeyU),
-        "V" => Some(Key::KeyV),
-        "W" => Some(Key::KeyW),
-        "X" => Some(Key::KeyX),
-        "Y" => Some(Key::KeyY),
-        "Z" => Some(Key::KeyZ),
-
-        "ENTER" | "RETURN" => Some(Key::Return),
-        "ESC" | "ESCAPE" => Some(Key::Escape),
-        "SPACE" => Some(Key::Space),
-        "TAB" => Some(Key::Tab),
-        "SHIFT" => Some(Key::ShiftLeft),
-        "CTRL" | "CONTROL" => Some(Key::ControlLeft),
-        "ALT" => Some(Key::Alt),
-        "BACKSPACE" => Some(Key::Backspace),
-        "DELETE" => Some(Key::Delete),
-        "LEFT" => Some(Key::LeftArrow),
-        "RIGHT" => Some(Key::RightArrow),
-        "UP" => Some(Key::UpArrow),
-        "DOWN" => Some(Key::DownArrow),
-        "HOME" => Some(Key::Home),
-        "END" => Some(Key::End),
-        "PAGEUP" | "PageUpDetected" => Some(Key::PageUp),       // only god knows why 
-        "PAGEDOWN" | "PageDownDetected "=> Some(Key::PageDown), // egui uses "detected" for pgup/down
-        "PAUSE" => Some(Key::Pause),
-        "INS" | "INSERT" => Some(Key::Insert),
-        "DEL" => Some(Key::Delete),
-        "FN" => Some(Key::Function),
-        "SCROLLLOCk" => Some(Key::ScrollLock),
-        "PRINTSCREEN" => Some(Key::PrintScreen),
-        "EQUALS" => Some(Key::Equal),
-        "MINUS" => Some(Key::Minus),
-        "COMMA" => Some(Key::Comma),
-        "SLASH" => Some(Key::Slash),
-        "LEFTBRACKET" => Some(Key::LeftBracket),
-        "RIGHTBRACKET" => Some(Key::RightBracket),
-        "LEFTARROW" => Some(Key::LeftArrow),
-        "RIGHTARROW" => Some(Key::RightArrow),
-        "UPARROW" => Some(Key::UpArrow),
-        "DOWNARROW" => Some(Key::DownArrow),
-
-
-        "F1" => Some(Key::F1),
-        "F2" => Some(Key::F2),
-        "F3" => Some(Key::F3),
-        "F4" => Some(Key::F4),
-        "F5" => Some(Key::F5),
-        "F6" => Some(Key::F6),
-        "F7" => Some(Key::F7),
-        "F8" => Some(Key::F8),
-        "F9" => Some(Key::F9),
-        "F10" => Some(Key::F10),
-        "F11" => Some(Key::F11),
-        "F12" => Some(Key::F12),
-
-        _ => None,
-    }
-}
-
-/* 
-pub fn key_to_str(key: &Key) -> &'static str {
-    match *key {
-        Key::KeyA => "A",
-        Key::KeyB => "B",
-        Key::KeyC => "C",
-        Key::KeyD => "D",
-        Key::KeyE => "E",
-        Key::KeyF => "F",
-        Key::KeyG => "G",
-        Key::KeyH => "H",
-        Key::KeyI => "I",
-        Key::KeyJ => "J",
-        Key::KeyK => "K",
-        Key::KeyL => "L",
-        Key::KeyM => "M",
-        Key::KeyN => "N",
-        Key::KeyO => "O",
-        Key::KeyP => "P",
-        Key::KeyQ => "Q",
-        Key::KeyR => "R",
-        Key::KeyS => "S",
-        Key::KeyT => "T",
-        Key::KeyU => "U",
-        Key::KeyV => "V",
-        Key::KeyW => "W",
-        Key::KeyX => "X",
-        Key::KeyY => "Y",
-        Key::KeyZ => "Z",
-
-        Key::Return => "Enter",
-        Key::Escape => "Escape",
-        Key::Space => "Space",
-        Key::Tab => "Tab",
-        Key::ShiftLeft | Key::ShiftRight => "Shift",
-        Key::ControlLeft | Key::ControlRight => "Ctrl",
-        Key::Alt => "Alt",
-        Key::Backspace => "Backspace",
-        Key::Delete => "Delete",
-        Key::LeftArrow => "Left",
-        Key::RightArrow => "Right",
-        Key::UpArrow => "Up",
-        Key::DownArrow => "Down",
-
-        Key::F1 => "F1",
-        Key::F2 => "F2",
-        Key::F3 => "F3",
-        Key::F4 => "F4",
-        Key::F5 => "F5",
-        Key::F6 => "F6",
-        Key::F7 => "F7",
-        Key::F8 => "F8",
-        Key::F9 => "F9",
-        Key::F10 => "F10",
-        Key::F11 => "F11",
-        Key::F12 => "F12",
-
-        _ => "Unknown",
-    }
-}
-    */
+    all_keys()
+        .find(|(_, n, _)| n.to_uppercase() == name)
+        .map(|(k, _, _)| *k)
+        .or_else(|| KEY_ALIASES.iter().find(|(n, _)| *n == name).map(|(_, k)| *k))
+}
@@ -0,0 +1,298 @@
+// Global key capture straight from /dev/input. This works under Wayland, where the
+// X11 hook only sees keys typed into X11 windows, but needs read access to the
+// event devices (usually membership of the `input` group).
+use super::{Binding, ConsumeFilter, InputEvent, InputSource, SIDE_BUTTONS};
+use rdev::{Button, EventType, Key};
+use std::ffi::{c_long, c_ulong};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::os::fd::AsRawFd;
+use std::path::PathBuf;
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// struct input_event: a timeval, then type, code and value
+const INPUT_EVENT_SIZE: usize = 2 * size_of::<c_long>() + 8;
+const EV_SYN: u16 = 0x00;
+const EV_KEY: u16 = 0x01;
+const EV_REL: u16 = 0x02;
+const EV_ABS: u16 = 0x03;
+// Codes from here up are mouse, joystick and touch buttons
+const BTN_MISC: u16 = 0x100;
+const BTN_LEFT: u16 = 0x110;
+const BTN_RIGHT: u16 = 0x111;
+const BTN_MIDDLE: u16 = 0x112;
+const BTN_SIDE: u16 = 0x113;
+const BTN_EXTRA: u16 = 0x114;
+const REL_HWHEEL: u16 = 0x06;
+const REL_WHEEL: u16 = 0x08;
+// ...and keys start again here
+const KEY_OK: u16 = 0x160;
+const KEY_MAX: u16 = 0x2ff;
+const BUS_VIRTUAL: u16 = 0x06;
+
+// ioctl requests from linux/input.h and linux/uinput.h
+const EVIOCGRAB: c_ulong = 0x4004_4590;
+const UI_SET_EVBIT: c_ulong = 0x4004_5564;
+const UI_SET_KEYBIT: c_ulong = 0x4004_5565;
+const UI_DEV_CREATE: c_ulong = 0x5501;
+const UINPUT_NAME: &[u8] = b"SpotifyBinds virtual keyboard";
+
+fn eviocgbit(event_type: u16, len: usize) -> c_ulong {
+    (2 << 30) | ((len as c_ulong) << 16) | (0x45 << 8) | (0x20 + event_type as c_ulong)
+}
+
+// evdev codes are X11 keycodes minus 8. Keys are reported the same way the X11
+// hook reports them, so binds work with either backend.
+const X11_OFFSET: u32 = 8;
+
+#[rustfmt::skip]
+const X11_KEYCODES: &[(u32, Key)] = &[
+    (9, Key::Escape), (10, Key::Num1), (11, Key::Num2), (12, Key::Num3), (13, Key::Num4),
+    (14, Key::Num5), (15, Key::Num6), (16, Key::Num7), (17, Key::Num8), (18, Key::Num9),
+    (19, Key::Num0), (20, Key::Minus), (21, Key::Equal), (22, Key::Backspace), (23, Key::Tab),
+    (24, Key::KeyQ), (25, Key::KeyW), (26, Key::KeyE), (27, Key::KeyR), (28, Key::KeyT),
+    (29, Key::KeyY), (30, Key::KeyU), (31, Key::KeyI), (32, Key::KeyO), (33, Key::KeyP),
+    (34, Key::LeftBracket), (35, Key::RightBracket), (36, Key::Return), (37, Key::ControlLeft),
+    (38, Key::KeyA), (39, Key::KeyS), (40, Key::KeyD), (41, Key::KeyF), (42, Key::KeyG),
+    (43, Key::KeyH), (44, Key::KeyJ), (45, Key::KeyK), (46, Key::KeyL), (47, Key::SemiColon),
+    (48, Key::Quote), (49, Key::BackQuote), (50, Key::ShiftLeft), (51, Key::BackSlash),
+    (52, Key::KeyZ), (53, Key::KeyX), (54, Key::KeyC), (55, Key::KeyV), (56, Key::KeyB),
+    (57, Key::KeyN), (58, Key::KeyM), (59, Key::Comma), (60, Key::Dot), (61, Key::Slash),
+    (62, Key::ShiftRight), (63, Key::KpMultiply), (64, Key::Alt), (65, Key::Space),
+    (66, Key::CapsLock), (67, Key::F1), (68, Key::F2), (69, Key::F3), (70, Key::F4),
+    (71, Key::F5), (72, Key::F6), (73, Key::F7), (74, Key::F8), (75, Key::F9), (76, Key::F10),
+    (77, Key::NumLock), (78, Key::ScrollLock), (79, Key::Kp7), (80, Key::Kp8), (81, Key::Kp9),
+    (82, Key::KpMinus), (83, Key::Kp4), (84, Key::Kp5), (85, Key::Kp6), (86, Key::KpPlus),
+    (87, Key::Kp1), (88, Key::Kp2), (89, Key::Kp3), (90, Key::Kp0), (91, Key::KpDelete),
+    (94, Key::IntlBackslash), (95, Key::F11), (96, Key::F12), (104, Key::KpReturn),
+    (105, Key::ControlRight), (106, Key::KpDivide), (107, Key::PrintScreen), (108, Key::AltGr),
+    (110, Key::Home), (111, Key::UpArrow), (112, Key::PageUp), (113, Key::LeftArrow),
+    (114, Key::RightArrow), (115, Key::End), (116, Key::DownArrow), (117, Key::PageDown),
+    (118, Key::Insert), (119, Key::Delete), (127, Key::Pause), (133, Key::MetaLeft),
+    (134, Key::MetaRight),
+];
+
+pub fn key_from_code(code: u16) -> Key {
+    let keycode = code as u32 + X11_OFFSET;
+    X11_KEYCODES
+        .iter()
+        .find(|(c, _)| *c == keycode)
+        .map(|(_, key)| *key)
+        .unwrap_or(Key::Unknown(keycode))
+}
+
+fn ioctl(file: &File, request: c_ulong, arg: c_ulong) -> io::Result<()> {
+    // SAFETY: every request used here takes an int or a pointer to a buffer that outlives the call
+    let result = unsafe { libc::ioctl(file.as_raw_fd(), request as _, arg) };
+    if result < 0 { Err(io::Error::last_os_error()) } else { Ok(()) }
+}
+
+fn has_bit(bits: &[u8], n: u16) -> bool {
+    bits.get(n as usize / 8).is_some_and(|b| b & (1 << (n % 8)) != 0)
+}
+
+// Which event types (0) or codes of a type the device can send
+fn event_bits(device: &File, event_type: u16) -> io::Result<Vec<u8>> {
+    let mut bits = vec![0u8; KEY_MAX as usize / 8 + 1];
+    ioctl(device, eviocgbit(event_type, bits.len()), bits.as_mut_ptr() as c_ulong)?;
+    Ok(bits)
+}
+
+// Keyboards, including media key pads, but not mice, touchpads or tablets
+fn is_keyboard(device: &File) -> bool {
+    let (Ok(types), Ok(keys)) = (event_bits(device, 0), event_bits(device, EV_KEY)) else {
+        return false;
+    };
+    has_bit(&types, EV_KEY)
+        && !has_bit(&types, EV_REL)
+        && !has_bit(&types, EV_ABS)
+        && (1..BTN_MISC).any(|code| has_bit(&keys, code))
+}
+
+fn open_event_devices() -> io::Result<Vec<File>> {
+    let mut paths: Vec<PathBuf> = Vec::new();
+    for entry in fs::read_dir("/dev/input")? {
+        let path = entry?.path();
+        let is_event = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .is_some_and(|n| n.starts_with("event"));
+        if is_event {
+            paths.push(path);
+        }
+    }
+
+    let devices: Vec<File> = paths.iter().filter_map(|path| File::open(path).ok()).collect();
+    if devices.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "no readable devices in /dev/input (is this user in the `input` group?)",
+        ));
+    }
+    Ok(devices)
+}
+
+// Buttons the same way the X11 hook reports them
+fn button_from_code(code: u16) -> Option<Button> {
+    match code {
+        BTN_LEFT => Some(Button::Left),
+        BTN_RIGHT => Some(Button::Right),
+        BTN_MIDDLE => Some(Button::Middle),
+        BTN_SIDE => Some(Button::Unknown(SIDE_BUTTONS.0)),
+        BTN_EXTRA => Some(Button::Unknown(SIDE_BUTTONS.1)),
+        _ => None,
+    }
+}
+
+// Reads every event device we can open. Devices that never send keys just sit idle.
+pub struct EvdevInput {
+    devices: Vec<File>,
+}
+
+impl EvdevInput {
+    pub fn open() -> io::Result<Self> {
+        Ok(Self { devices: open_event_devices()? })
+    }
+}
+
+impl InputSource for EvdevInput {
+    type Error = io::Error;
+
+    fn run(self, sink: mpsc::Sender<InputEvent>) -> Result<(), Self::Error> {
+        let readers: Vec<_> = self
+            .devices
+            .into_iter()
+            .map(|device| {
+                let sink = sink.clone();
+                std::thread::spawn(move || read_device(device, sink, None))
+            })
+            .collect();
+        drop(sink);
+        join_readers(readers)
+    }
+}
+
+// Unplugging a device ends its reader; only give up once all are gone
+fn join_readers(readers: Vec<std::thread::JoinHandle<io::Result<()>>>) -> io::Result<()> {
+    let mut result = Ok(());
+    for reader in readers {
+        if let Ok(Err(e)) = reader.join() {
+            result = Err(e);
+        }
+    }
+    result
+}
+
+// Takes the keyboards for ourselves (EVIOCGRAB) and types everything that isn't
+// consumed back out through a virtual keyboard, which also needs write access to
+// /dev/uinput. Releasing the grab is left to the kernel when the devices close.
+// Mice are read without grabbing them so mouse and wheel binds keep working.
+pub struct EvdevGrab {
+    keyboards: Vec<File>,
+    pointers: Vec<File>,
+    uinput: File,
+    filter: ConsumeFilter,
+}
+
+impl EvdevGrab {
+    pub fn open(binds: &[Binding], sequence_timeout: Duration) -> io::Result<Self> {
+        // Look for keyboards before our virtual one exists, so we never grab it
+        let (keyboards, pointers): (Vec<File>, Vec<File>) = open_event_devices()?.into_iter().partition(is_keyboard);
+        if keyboards.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::NotFound, "no keyboards found in /dev/input"));
+        }
+
+        // Only grab once there is somewhere to send the keys, so typing never stops working
+        let uinput = create_uinput()?;
+        for keyboard in &keyboards {
+            ioctl(keyboard, EVIOCGRAB, 1)?;
+        }
+        Ok(Self { keyboards, pointers, uinput, filter: ConsumeFilter::new(binds, sequence_timeout) })
+    }
+}
+
+fn create_uinput() -> io::Result<File> {
+    let mut uinput = OpenOptions::new().write(true).open("/dev/uinput")?;
+    ioctl(&uinput, UI_SET_EVBIT, EV_SYN as c_ulong)?;
+    ioctl(&uinput, UI_SET_EVBIT, EV_KEY as c_ulong)?;
+    for code in (1..BTN_MISC).chain(KEY_OK..=KEY_MAX) {
+        ioctl(&uinput, UI_SET_KEYBIT, code as c_ulong)?;
+    }
+
+    // struct uinput_user_dev: name[80], struct input_id, ff_effects_max and four
+    // arrays of 64 absolute axis limits we don't use
+    let mut setup = vec![0u8; 80 + 8 + 4 + 4 * 64 * 4];
+    setup[..UINPUT_NAME.len()].copy_from_slice(UINPUT_NAME);
+    setup[80..82].copy_from_slice(&BUS_VIRTUAL.to_ne_bytes());
+    uinput.write_all(&setup)?;
+    ioctl(&uinput, UI_DEV_CREATE, 0)?;
+    Ok(uinput)
+}
+
+// Where a grabbed device's events go once we've seen them
+struct Forward {
+    filter: Arc<Mutex<ConsumeFilter>>,
+    uinput: Arc<File>,
+}
+
+impl InputSource for EvdevGrab {
+    type Error = io::Error;
+
+    fn run(self, sink: mpsc::Sender<InputEvent>) -> Result<(), Self::Error> {
+        let filter = Arc::new(Mutex::new(self.filter));
+        let uinput = Arc::new(self.uinput);
+        let keyboards = self.keyboards.into_iter().map(|device| {
+            let sink = sink.clone();
+            let forward = Forward { filter: filter.clone(), uinput: uinput.clone() };
+            std::thread::spawn(move || read_device(device, sink, Some(forward)))
+        });
+        let pointers = self.pointers.into_iter().map(|device| {
+            let sink = sink.clone();
+            std::thread::spawn(move || read_device(device, sink, None))
+        });
+        let readers: Vec<_> = keyboards.chain(pointers).collect();
+        drop(sink);
+        join_readers(readers)
+    }
+}
+
+fn read_device(mut device: File, sink: mpsc::Sender<InputEvent>, forward: Option<Forward>) -> io::Result<()> {
+    let mut buf = [0u8; INPUT_EVENT_SIZE];
+    let fields = INPUT_EVENT_SIZE - 8;
+    loop {
+        device.read_exact(&mut buf)?;
+        let kind = u16::from_ne_bytes([buf[fields], buf[fields + 1]]);
+        let code = u16::from_ne_bytes([buf[fields + 2], buf[fields + 3]]);
+        let value = i32::from_ne_bytes([buf[fields + 4], buf[fields + 5], buf[fields + 6], buf[fields + 7]]);
+
+        let event = match (kind, code) {
+            (EV_KEY, code) if code < BTN_MISC => {
+                let key = key_from_code(code);
+                // 0 is a release, 1 a press and 2 an auto-repeat, which the engine ignores anyway
+                Some(if value == 0 { EventType::KeyRelease(key) } else { EventType::KeyPress(key) })
+            }
+            (EV_KEY, code) => button_from_code(code)
+                .map(|button| if value == 0 { EventType::ButtonRelease(button) } else { EventType::ButtonPress(button) }),
+            (EV_REL, REL_WHEEL) => Some(EventType::Wheel { delta_x: 0, delta_y: value as i64 }),
+            (EV_REL, REL_HWHEEL) => Some(EventType::Wheel { delta_x: value as i64, delta_y: 0 }),
+            _ => None,
+        };
+
+        let mut consumed = false;
+        if let Some(event) = event {
+            let now = Instant::now();
+            if let Some(forward) = &forward {
+                consumed = forward.filter.lock().unwrap().consume(&event, now);
+            }
+            if sink.send((event, now)).is_err() {
+                return Ok(());
+            }
+        }
+
+        // Everything else, sync reports included, goes back out unchanged
+        if let Some(forward) = forward.as_ref().filter(|_| !consumed) {
+            (&*forward.uinput).write_all(&buf)?;
+        }
+    }
+}
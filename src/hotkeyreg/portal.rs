@@ -0,0 +1,309 @@
+// Global shortcuts through the XDG desktop portal (org.freedesktop.portal.GlobalShortcuts).
+// The compositor owns the actual keys here: we only suggest a trigger for each bind and
+// get told when a shortcut is activated or deactivated.
+use super::{is_mouse, key_name, Binding, InputEvent, InputSource, KeyChord, KeySequence, ModSide, ModifierSet};
+use rdev::{EventType, Key};
+use std::collections::HashMap;
+use std::sync::mpsc;
+use std::time::Instant;
+use zbus::blocking::{Connection, MessageIterator};
+use zbus::message::Type;
+use zbus::zvariant::{ObjectPath, OwnedObjectPath, OwnedValue, Value};
+use zbus::MatchRule;
+
+pub const PORTAL_DESTINATION: &str = "org.freedesktop.portal.Desktop";
+pub const PORTAL_PATH: &str = "/org/freedesktop/portal/desktop";
+pub const SHORTCUTS_INTERFACE: &str = "org.freedesktop.portal.GlobalShortcuts";
+pub const REQUEST_INTERFACE: &str = "org.freedesktop.portal.Request";
+pub const SESSION_INTERFACE: &str = "org.freedesktop.portal.Session";
+
+// Stand-in keys for shortcuts, well clear of any real keycode
+const SHORTCUT_KEY_BASE: u32 = 0x10000;
+
+pub fn shortcut_id(index: usize) -> String {
+    format!("bind-{}", index)
+}
+
+fn shortcut_index(id: &str) -> Option<usize> {
+    id.strip_prefix("bind-")?.parse().ok()
+}
+
+pub fn shortcut_key(index: usize) -> Key {
+    Key::Unknown(SHORTCUT_KEY_BASE + index as u32)
+}
+
+// The portal can only bind single keyboard chords
+fn portal_chord(keys: &KeySequence) -> Option<&KeyChord> {
+    match keys.chords() {
+        [chord] if !is_mouse(chord.key) => Some(chord),
+        _ => None,
+    }
+}
+
+// The shortcut each bind goes through, numbered after the first bind on its chord.
+// Binds sharing a chord share a shortcut, so the engine can still tell a tap from a
+// hold or a double tap on it.
+fn shortcut_slots(binds: &[Binding]) -> Vec<Option<usize>> {
+    let chords: Vec<Option<&KeyChord>> = binds.iter().map(|b| b.keys.as_ref().and_then(portal_chord)).collect();
+    chords.iter().map(|chord| chords.iter().position(|c| c.is_some() && c == chord)).collect()
+}
+
+// Each bind the portal can take has its keys replaced by its shortcut's stand-in key,
+// so the engine still handles its trigger and repeat. The rest are left unbound.
+pub fn portal_binds(binds: &[Binding]) -> Vec<Binding> {
+    binds
+        .iter()
+        .zip(shortcut_slots(binds))
+        .map(|(bind, slot)| {
+            let keys = slot.map(|slot| KeySequence::from(KeyChord { modifiers: ModifierSet::NONE, key: shortcut_key(slot) }));
+            Binding { keys, ..bind.clone() }
+        })
+        .collect()
+}
+
+// Bound sequences and mouse binds, which the portal has no way to take
+pub fn skipped_binds(binds: &[Binding]) -> impl Iterator<Item = &Binding> {
+    binds.iter().filter(|bind| bind.keys.as_ref().is_some_and(|keys| portal_chord(keys).is_none()))
+}
+
+// XKB keysym for each of our key names, apart from letters and digits which are the
+// lowercased name. Keys missing here (mouse buttons, Fn) have no keysym.
+const KEYSYMS: &[(&str, &str)] = &[
+    ("Enter", "Return"),
+    ("Escape", "Escape"),
+    ("Space", "space"),
+    ("Tab", "Tab"),
+    ("Backspace", "BackSpace"),
+    ("Delete", "Delete"),
+    ("Insert", "Insert"),
+    ("Left", "Left"),
+    ("Right", "Right"),
+    ("Up", "Up"),
+    ("Down", "Down"),
+    ("Home", "Home"),
+    ("End", "End"),
+    ("PageUp", "Prior"),
+    ("PageDown", "Next"),
+    ("Pause", "Pause"),
+    ("PrintScreen", "Print"),
+    ("ScrollLock", "Scroll_Lock"),
+    ("CapsLock", "Caps_Lock"),
+    ("NumLock", "Num_Lock"),
+    ("Backquote", "grave"),
+    ("Minus", "minus"),
+    ("Equals", "equal"),
+    ("LeftBracket", "bracketleft"),
+    ("RightBracket", "bracketright"),
+    ("Semicolon", "semicolon"),
+    ("Quote", "apostrophe"),
+    ("Backslash", "backslash"),
+    ("IntlBackslash", "less"),
+    ("Comma", "comma"),
+    ("Period", "period"),
+    ("Slash", "slash"),
+    ("Numpad0", "KP_0"),
+    ("Numpad1", "KP_1"),
+    ("Numpad2", "KP_2"),
+    ("Numpad3", "KP_3"),
+    ("Numpad4", "KP_4"),
+    ("Numpad5", "KP_5"),
+    ("Numpad6", "KP_6"),
+    ("Numpad7", "KP_7"),
+    ("Numpad8", "KP_8"),
+    ("Numpad9", "KP_9"),
+    ("NumpadEnter", "KP_Enter"),
+    ("NumpadMinus", "KP_Subtract"),
+    ("NumpadPlus", "KP_Add"),
+    ("NumpadMultiply", "KP_Multiply"),
+    ("NumpadDivide", "KP_Divide"),
+    ("NumpadDecimal", "KP_Decimal"),
+    ("F1", "F1"),
+    ("F2", "F2"),
+    ("F3", "F3"),
+    ("F4", "F4"),
+    ("F5", "F5"),
+    ("F6", "F6"),
+    ("F7", "F7"),
+    ("F8", "F8"),
+    ("F9", "F9"),
+    ("F10", "F10"),
+    ("F11", "F11"),
+    ("F12", "F12"),
+    ("F13", "F13"),
+    ("F14", "F14"),
+    ("F15", "F15"),
+    ("F16", "F16"),
+    ("F17", "F17"),
+    ("F18", "F18"),
+    ("F19", "F19"),
+    ("F20", "F20"),
+    ("F21", "F21"),
+    ("F22", "F22"),
+    ("F23", "F23"),
+    ("F24", "F24"),
+    ("MediaPlayPause", "XF86AudioPlay"),
+    ("MediaNext", "XF86AudioNext"),
+    ("MediaPrevious", "XF86AudioPrev"),
+    ("MediaStop", "XF86AudioStop"),
+    ("VolumeUp", "XF86AudioRaiseVolume"),
+    ("VolumeDown", "XF86AudioLowerVolume"),
+    ("VolumeMute", "XF86AudioMute"),
+];
+
+// A chord in the shortcuts spec's trigger format, e.g. "CTRL+SHIFT+p". Sides can't be
+// expressed, so side-specific modifiers are suggested as either side. Keys without a
+// keysym get no suggestion and the user picks one in the portal's dialog.
+pub fn preferred_trigger(chord: &KeyChord) -> Option<String> {
+    let name = key_name(chord.key)?;
+    let keysym = match KEYSYMS.iter().find(|(ours, _)| *ours == name) {
+        Some((_, keysym)) => keysym.to_string(),
+        None if name.len() == 1 => name.to_lowercase(),
+        None => return None,
+    };
+
+    let m = &chord.modifiers;
+    let mut parts: Vec<String> = [(m.ctrl, "CTRL"), (m.shift, "SHIFT"), (m.alt, "ALT"), (m.meta, "LOGO")]
+        .into_iter()
+        .filter(|(side, _)| *side != ModSide::Off)
+        .map(|(_, name)| name.to_owned())
+        .collect();
+    parts.push(keysym);
+    Some(parts.join("+"))
+}
+
+fn failure(message: &str) -> zbus::Error {
+    zbus::Error::Failure(message.to_owned())
+}
+
+// A bound portal session. Creating one may show the user a dialog to confirm the
+// shortcuts, so `connect` blocks until they answer.
+pub struct PortalInput {
+    session: OwnedObjectPath,
+    signals: MessageIterator,
+}
+
+impl PortalInput {
+    pub fn connect(binds: &[Binding]) -> zbus::Result<Self> {
+        Self::with_connection(Connection::session()?, binds)
+    }
+
+    pub fn with_connection(connection: Connection, binds: &[Binding]) -> zbus::Result<Self> {
+        // Subscribe before calling anything so no request response can slip past
+        let rule = MatchRule::builder()
+            .msg_type(Type::Signal)
+            .path_namespace(PORTAL_PATH)?
+            .build();
+        let mut signals = MessageIterator::for_match_rule(rule, &connection, None)?;
+
+        let options: HashMap<&str, Value> = HashMap::from([
+            ("handle_token", Value::from("spotifybinds_session")),
+            ("session_handle_token", Value::from("spotifybinds")),
+        ]);
+        let request: OwnedObjectPath = connection
+            .call_method(Some(PORTAL_DESTINATION), PORTAL_PATH, Some(SHORTCUTS_INTERFACE), "CreateSession", &(options,))?
+            .body()
+            .deserialize()?;
+        let results = await_response(&mut signals, &request)?;
+        let handle = results.get("session_handle").ok_or_else(|| failure("portal returned no session"))?;
+        // Older portals send the handle as a string rather than an object path
+        let session = match OwnedObjectPath::try_from(handle.try_clone()?) {
+            Ok(path) => path,
+            Err(_) => OwnedObjectPath::try_from(String::try_from(handle.try_clone()?)?)?,
+        };
+
+        let slots = shortcut_slots(binds);
+        let shortcuts: Vec<(String, HashMap<&str, Value>)> = binds
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| slots[*i] == Some(*i))
+            .filter_map(|(i, bind)| {
+                let chord = portal_chord(bind.keys.as_ref()?)?;
+                let labels: Vec<&str> = binds
+                    .iter()
+                    .zip(&slots)
+                    .filter(|(_, slot)| **slot == Some(i))
+                    .map(|(bind, _)| bind.action.label())
+                    .collect();
+                let mut details = HashMap::from([("description", Value::from(labels.join(", ")))]);
+                if let Some(trigger) = preferred_trigger(chord) {
+                    details.insert("preferred_trigger", Value::from(trigger));
+                }
+                Some((shortcut_id(i), details))
+            })
+            .collect();
+        let options: HashMap<&str, Value> = HashMap::from([("handle_token", Value::from("spotifybinds_bind"))]);
+        let request: OwnedObjectPath = connection
+            .call_method(
+                Some(PORTAL_DESTINATION),
+                PORTAL_PATH,
+                Some(SHORTCUTS_INTERFACE),
+                "BindShortcuts",
+                &(ObjectPath::from(&session), shortcuts, "", options),
+            )?
+            .body()
+            .deserialize()?;
+        await_response(&mut signals, &request)?;
+
+        Ok(Self { session, signals })
+    }
+}
+
+fn await_response(signals: &mut MessageIterator, request: &OwnedObjectPath) -> zbus::Result<HashMap<String, OwnedValue>> {
+    for message in signals {
+        let message = message?;
+        let header = message.header();
+        let is_response = header.interface().is_some_and(|i| i == REQUEST_INTERFACE)
+            && header.member().is_some_and(|m| m == "Response")
+            && header.path().is_some_and(|p| *p == **request);
+        if !is_response {
+            continue;
+        }
+        let (code, results): (u32, HashMap<String, OwnedValue>) = message.body().deserialize()?;
+        return match code {
+            0 => Ok(results),
+            1 => Err(failure("global shortcuts were cancelled")),
+            _ => Err(failure("global shortcuts were refused")),
+        };
+    }
+    Err(failure("lost the connection to the portal"))
+}
+
+impl InputSource for PortalInput {
+    type Error = zbus::Error;
+
+    // Activated and Deactivated become presses and releases of the shortcut's
+    // stand-in key. Ends when the portal closes the session.
+    fn run(self, sink: mpsc::Sender<InputEvent>) -> Result<(), Self::Error> {
+        for message in self.signals {
+            let message = message?;
+            let header = message.header();
+            let (Some(interface), Some(member)) = (header.interface(), header.member()) else {
+                continue;
+            };
+
+            if interface == SESSION_INTERFACE && member == "Closed" && header.path().is_some_and(|p| *p == *self.session) {
+                return Ok(());
+            }
+            if interface != SHORTCUTS_INTERFACE {
+                continue;
+            }
+            let pressed = match member.as_str() {
+                "Activated" => true,
+                "Deactivated" => false,
+                _ => continue,
+            };
+
+            let (session, id, _timestamp, _options): (OwnedObjectPath, String, u64, HashMap<String, OwnedValue>) =
+                message.body().deserialize()?;
+            let Some(index) = shortcut_index(&id).filter(|_| session == self.session) else {
+                continue;
+            };
+            let key = shortcut_key(index);
+            let event = if pressed { EventType::KeyPress(key) } else { EventType::KeyRelease(key) };
+            if sink.send((event, Instant::now())).is_err() {
+                return Ok(());
+            }
+        }
+        Ok(())
+    }
+}
@@ -1,5 +1,8 @@
 
+pub mod credentials;
 pub mod hotkeyreg;
+pub mod oauth;
+pub mod player;
 
 
 #[cfg(test)]
@@ -25,4 +28,729 @@ mod key_conversion_tests {
         assert_eq!(str_to_key("keya"), Some(Key::KeyA));
         assert_eq!(str_to_key("KEYA"), Some(Key::KeyA));
     }
+
+    #[test]
+    fn test_str_to_key_extended() {
+        assert_eq!(str_to_key("ScrollLock"), Some(Key::ScrollLock));
+        assert_eq!(str_to_key("PageDown"), Some(Key::PageDown));
+        assert_eq!(str_to_key("7"), Some(Key::Num7));
+        assert_eq!(str_to_key("Numpad7"), Some(Key::Kp7));
+        assert_eq!(str_to_key("Semicolon"), Some(Key::SemiColon));
+        assert_eq!(str_to_key("Backquote"), Some(Key::BackQuote));
+        assert_eq!(str_to_key("CapsLock"), Some(Key::CapsLock));
+    }
+}
+
+#[cfg(test)]
+mod key_table_tests {
+    use std::collections::HashSet;
+    use crate::hotkeyreg::{all_keys, egui_to_key, key_name, str_to_key};
+
+    // egui keys with no physical key rdev can report
+    fn unsupported(key: egui::Key) -> bool {
+        use egui::Key::*;
+        let platform_f_keys = cfg!(any(target_os = "windows", target_os = "linux"));
+        match key {
+            Copy | Cut | Paste | BrowserBack => true,
+            F25 | F26 | F27 | F28 | F29 | F30 | F31 | F32 | F33 | F34 | F35 => true,
+            F13 | F14 | F15 | F16 | F17 | F18 | F19 | F20 | F21 | F22 | F23 | F24 => !platform_f_keys,
+            _ => false,
+        }
+    }
+
+    #[test]
+    fn test_every_egui_key_resolves() {
+        for key in egui::Key::ALL {
+            if unsupported(*key) {
+                continue;
+            }
+            let rdev_key = egui_to_key(*key);
+            assert!(rdev_key.is_some(), "egui key {:?} has no rdev key", key);
+            assert!(key_name(rdev_key.unwrap()).is_some(), "egui key {:?} has no name", key);
+        }
+    }
+
+    #[test]
+    fn test_key_table_is_unique() {
+        let mut keys = HashSet::new();
+        let mut names = HashSet::new();
+        for (key, name, _) in all_keys() {
+            assert!(keys.insert(*key), "{:?} listed twice", key);
+            assert!(names.insert(name.to_uppercase()), "{} listed twice", name);
+            assert_eq!(str_to_key(name), Some(*key));
+        }
+    }
+
+    // evdev and the X11 hook must report the same keys for binds to work with both
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_evdev_codes_match_key_names() {
+        use crate::hotkeyreg::evdev::key_from_code;
+        use rdev::Key;
+        let modifiers = [
+            (29, Key::ControlLeft), (97, Key::ControlRight), (56, Key::Alt), (100, Key::AltGr),
+            (125, Key::MetaLeft), (126, Key::MetaRight),
+        ];
+        for (code, key) in modifiers {
+            assert_eq!(key_from_code(code), key);
+        }
+        let named = [
+            (1, "Escape"), (30, "A"), (2, "1"), (57, "Space"), (96, "NumpadEnter"),
+            (183, "F13"), (194, "F24"), (113, "VolumeMute"), (164, "MediaPlayPause"),
+        ];
+        for (code, name) in named {
+            assert_eq!(Some(key_from_code(code)), str_to_key(name), "evdev code {} should be {}", code, name);
+        }
+    }
+}
+
+#[cfg(test)]
+mod chord_tests {
+    use rdev::Key;
+    use crate::hotkeyreg::{KeyChord, ModifierSet, ModSide, HeldModifiers, ChordParseError, all_keys};
+
+    #[test]
+    fn test_chord_round_trip_every_key() {
+        for (key, _, _) in all_keys() {
+            for modifiers in ModifierSet::all() {
+                let chord = KeyChord { modifiers, key: *key };
+                let text = chord.to_string();
+                assert_eq!(text.parse::<KeyChord>(), Ok(chord), "round trip failed for '{}'", text);
+            }
+        }
+    }
+
+    #[test]
+    fn test_chord_parse() {
+        let chord: KeyChord = "Ctrl+Shift+P".parse().unwrap();
+        assert_eq!(chord.key, Key::KeyP);
+        assert_eq!(chord.modifiers, ModifierSet { ctrl: ModSide::Either, shift: ModSide::Either, ..ModifierSet::NONE });
+
+        // modifier order and spacing don't matter
+        assert_eq!("alt + ctrl + ArrowUp".parse::<KeyChord>().unwrap().to_string(), "Ctrl+Alt+Up");
+    }
+
+    #[test]
+    fn test_chord_parse_sides() {
+        let chord: KeyChord = "RightCtrl+Space".parse().unwrap();
+        assert_eq!(chord.modifiers, ModifierSet { ctrl: ModSide::Right, ..ModifierSet::NONE });
+
+        let chord: KeyChord = "Win+Right".parse().unwrap();
+        assert_eq!(chord.modifiers.meta, ModSide::Either);
+        assert_eq!(chord.to_string(), "Super+Right");
+
+        assert_eq!("RightAlt+P".parse::<KeyChord>().unwrap().to_string(), "AltGr+P");
+    }
+
+    #[test]
+    fn test_held_modifiers() {
+        let right_ctrl: KeyChord = "RightCtrl+Space".parse().unwrap();
+        let any_ctrl: KeyChord = "Ctrl+Space".parse().unwrap();
+        let mut held = HeldModifiers::default();
+
+        assert!(!held.satisfies(&any_ctrl.modifiers));
+        assert!(held.update(Key::ControlLeft, true));
+        assert!(held.satisfies(&any_ctrl.modifiers));
+        assert!(!held.satisfies(&right_ctrl.modifiers));
+
+        held.update(Key::ControlLeft, false);
+        held.update(Key::ControlRight, true);
+        assert!(held.satisfies(&right_ctrl.modifiers));
+
+        // an extra modifier held stops the chord from matching
+        held.update(Key::MetaLeft, true);
+        assert!(!held.satisfies(&right_ctrl.modifiers));
+
+        assert!(!held.update(Key::KeyA, true));
+    }
+
+    #[test]
+    fn test_chord_parse_errors() {
+        assert_eq!("".parse::<KeyChord>(), Err(ChordParseError::Empty));
+        assert_eq!("Ctrl+".parse::<KeyChord>(), Err(ChordParseError::UnknownKey("".to_owned())));
+        assert_eq!("Hyper+A".parse::<KeyChord>(), Err(ChordParseError::UnknownModifier("Hyper".to_owned())));
+        assert_eq!("Ctrl+RightCtrl+A".parse::<KeyChord>(), Err(ChordParseError::DuplicateModifier("RightCtrl".to_owned())));
+        // a key named like a modifier is not a modifier
+        assert_eq!("Alt".parse::<KeyChord>(), Err(ChordParseError::UnknownKey("Alt".to_owned())));
+    }
+}
+
+#[cfg(test)]
+mod sequence_tests {
+    use std::time::{Duration, Instant};
+    use rdev::Key;
+    use crate::hotkeyreg::{Binding, HeldModifiers, KeyEvent, KeySequence, SequenceMatcher};
+
+    fn bind(keys: &str, action: KeyEvent) -> Binding {
+        Binding { keys: Some(keys.parse().unwrap()), ..Binding::new(action) }
+    }
+
+    fn ctrl_alt() -> HeldModifiers {
+        let mut held = HeldModifiers::default();
+        held.update(Key::ControlLeft, true);
+        held.update(Key::Alt, true);
+        held
+    }
+
+    fn press(matcher: &mut SequenceMatcher, key: Key, held: &HeldModifiers, now: Instant) -> Vec<KeyEvent> {
+        matcher
+            .press(key, held, now)
+            .into_iter()
+            .map(|i| matcher.binding(i).action.clone())
+            .collect()
+    }
+
+    #[test]
+    fn test_sequence_round_trip() {
+        let keys: KeySequence = "ctrl+alt+s,n".parse().unwrap();
+        assert_eq!(keys.to_string(), "Ctrl+Alt+S, N");
+        assert_eq!(keys.to_string().parse::<KeySequence>(), Ok(keys));
+    }
+
+    #[test]
+    fn test_sequence_fires_after_last_step() {
+        let binds = vec![bind("Ctrl+Alt+S, N", KeyEvent::Next), bind("Ctrl+Alt+S, P", KeyEvent::Previous)];
+        let mut matcher = SequenceMatcher::new(&binds, Duration::from_secs(1));
+        let now = Instant::now();
+
+        assert!(press(&mut matcher, Key::KeyS, &ctrl_alt(), now).is_empty());
+        assert!(matcher.is_pending());
+        let fired = press(&mut matcher, Key::KeyN, &HeldModifiers::default(), now + Duration::from_millis(300));
+        assert_eq!(fired, vec![KeyEvent::Next]);
+        assert!(!matcher.is_pending());
+    }
+
+    #[test]
+    fn test_sequence_times_out() {
+        let binds = vec![bind("Ctrl+Alt+S, N", KeyEvent::Next)];
+        let mut matcher = SequenceMatcher::new(&binds, Duration::from_secs(1));
+        let now = Instant::now();
+
+        press(&mut matcher, Key::KeyS, &ctrl_alt(), now);
+        let fired = press(&mut matcher, Key::KeyN, &HeldModifiers::default(), now + Duration::from_secs(2));
+        assert!(fired.is_empty());
+        assert!(!matcher.is_pending());
+    }
+
+    #[test]
+    fn test_wrong_key_restarts_sequence() {
+        let binds = vec![bind("Ctrl+Alt+S, N", KeyEvent::Next), bind("M", KeyEvent::Mute)];
+        let mut matcher = SequenceMatcher::new(&binds, Duration::from_secs(1));
+        let now = Instant::now();
+
+        press(&mut matcher, Key::KeyS, &ctrl_alt(), now);
+        // M isn't the next step, but it is a binding of its own
+        assert_eq!(press(&mut matcher, Key::KeyM, &HeldModifiers::default(), now), vec![KeyEvent::Mute]);
+        assert!(press(&mut matcher, Key::KeyN, &HeldModifiers::default(), now).is_empty());
+    }
+
+    #[test]
+    fn test_single_chord_fires_immediately() {
+        let binds = vec![bind("Ctrl+Alt+S", KeyEvent::Toggle), bind("Ctrl+Alt+S, N", KeyEvent::Next)];
+        let mut matcher = SequenceMatcher::new(&binds, Duration::from_secs(1));
+
+        assert_eq!(press(&mut matcher, Key::KeyS, &ctrl_alt(), Instant::now()), vec![KeyEvent::Toggle]);
+        assert!(!matcher.is_pending());
+    }
+}
+
+#[cfg(test)]
+mod trigger_tests {
+    use std::time::{Duration, Instant};
+    use rdev::{EventType, Key};
+    use crate::hotkeyreg::{Binding, Fired, KeyEvent, Trigger, TriggerEngine, DEFAULT_SEQUENCE_TIMEOUT, MULTI_TAP_WINDOW};
+
+    fn bind(keys: &str, action: KeyEvent, trigger: Trigger) -> Binding {
+        Binding { keys: Some(keys.parse().unwrap()), trigger, ..Binding::new(action) }
+    }
+
+    // Tap toggles, hold 500ms skips, double tap goes back
+    fn f9_engine() -> TriggerEngine {
+        let binds = vec![
+            bind("F9", KeyEvent::Toggle, Trigger::Tap(1)),
+            bind("F9", KeyEvent::Next, Trigger::Hold(500)),
+            bind("F9", KeyEvent::Previous, Trigger::Tap(2)),
+        ];
+        TriggerEngine::new(&binds, DEFAULT_SEQUENCE_TIMEOUT)
+    }
+
+    fn ms(n: u64) -> Duration {
+        Duration::from_millis(n)
+    }
+
+    fn actions(fired: Vec<Fired>) -> Vec<KeyEvent> {
+        fired.into_iter().map(|f| f.action).collect()
+    }
+
+    #[test]
+    fn test_single_tap_waits_for_tap_window() {
+        let mut engine = f9_engine();
+        let t = Instant::now();
+
+        assert!(engine.handle(EventType::KeyPress(Key::F9), t).is_empty());
+        assert!(engine.handle(EventType::KeyRelease(Key::F9), t + ms(80)).is_empty());
+        assert_eq!(engine.next_deadline(), Some(t + ms(80) + MULTI_TAP_WINDOW));
+        assert_eq!(actions(engine.poll(t + ms(80) + MULTI_TAP_WINDOW)), vec![KeyEvent::Toggle]);
+        assert_eq!(engine.next_deadline(), None);
+    }
+
+    #[test]
+    fn test_double_tap() {
+        let mut engine = f9_engine();
+        let t = Instant::now();
+
+        engine.handle(EventType::KeyPress(Key::F9), t);
+        engine.handle(EventType::KeyRelease(Key::F9), t + ms(80));
+        engine.handle(EventType::KeyPress(Key::F9), t + ms(200));
+        let fired = engine.handle(EventType::KeyRelease(Key::F9), t + ms(260));
+        assert_eq!(actions(fired), vec![KeyEvent::Previous]);
+        assert!(engine.poll(t + ms(2000)).is_empty());
+    }
+
+    #[test]
+    fn test_hold_fires_while_held() {
+        let mut engine = f9_engine();
+        let t = Instant::now();
+
+        engine.handle(EventType::KeyPress(Key::F9), t);
+        assert_eq!(engine.next_deadline(), Some(t + ms(500)));
+        assert_eq!(actions(engine.poll(t + ms(500))), vec![KeyEvent::Next]);
+        // OS key repeat doesn't restart the hold
+        assert!(engine.handle(EventType::KeyPress(Key::F9), t + ms(600)).is_empty());
+        // releasing after a hold is not a tap
+        assert!(engine.handle(EventType::KeyRelease(Key::F9), t + ms(700)).is_empty());
+        assert!(engine.poll(t + ms(2000)).is_empty());
+    }
+
+    #[test]
+    fn test_press_and_release_triggers() {
+        let binds = vec![
+            bind("Ctrl+P", KeyEvent::Play, Trigger::Press),
+            bind("Ctrl+P", KeyEvent::Pause, Trigger::Release),
+        ];
+        let mut engine = TriggerEngine::new(&binds, DEFAULT_SEQUENCE_TIMEOUT);
+        let t = Instant::now();
+
+        assert!(engine.handle(EventType::KeyPress(Key::ControlLeft), t).is_empty());
+        assert_eq!(actions(engine.handle(EventType::KeyPress(Key::KeyP), t)), vec![KeyEvent::Play]);
+        assert_eq!(actions(engine.handle(EventType::KeyRelease(Key::KeyP), t + ms(50))), vec![KeyEvent::Pause]);
+    }
+
+    #[test]
+    fn test_other_key_ends_pending_tap() {
+        let binds = vec![
+            bind("F9", KeyEvent::Toggle, Trigger::Tap(1)),
+            bind("F9", KeyEvent::Previous, Trigger::Tap(2)),
+            bind("F10", KeyEvent::Mute, Trigger::Press),
+        ];
+        let mut engine = TriggerEngine::new(&binds, DEFAULT_SEQUENCE_TIMEOUT);
+        let t = Instant::now();
+
+        engine.handle(EventType::KeyPress(Key::F9), t);
+        engine.handle(EventType::KeyRelease(Key::F9), t + ms(50));
+        let fired = engine.handle(EventType::KeyPress(Key::F10), t + ms(100));
+        assert_eq!(actions(fired), vec![KeyEvent::Toggle, KeyEvent::Mute]);
+    }
+}
+
+#[cfg(test)]
+mod repeat_tests {
+    use std::time::{Duration, Instant};
+    use rdev::{EventType, Key};
+    use crate::hotkeyreg::{Binding, Fired, KeyEvent, RepeatPolicy, TriggerEngine, DEFAULT_SEQUENCE_TIMEOUT, MIN_REPEAT_MS};
+
+    fn ms(n: u64) -> Duration {
+        Duration::from_millis(n)
+    }
+
+    fn actions(fired: Vec<Fired>) -> Vec<KeyEvent> {
+        fired.into_iter().map(|f| f.action).collect()
+    }
+
+    #[test]
+    fn test_os_repeats_are_ignored() {
+        let binds = vec![Binding { keys: Some("F8".parse().unwrap()), ..Binding::new(KeyEvent::Toggle) }];
+        let mut engine = TriggerEngine::new(&binds, DEFAULT_SEQUENCE_TIMEOUT);
+        let t = Instant::now();
+
+        assert_eq!(actions(engine.handle(EventType::KeyPress(Key::F8), t)), vec![KeyEvent::Toggle]);
+        assert!(engine.handle(EventType::KeyPress(Key::F8), t + ms(30)).is_empty());
+        assert!(engine.handle(EventType::KeyPress(Key::F8), t + ms(60)).is_empty());
+        assert_eq!(engine.next_deadline(), None);
+    }
+
+    #[test]
+    fn test_repeat_accelerates_until_release() {
+        let binds = vec![Binding {
+            keys: Some("Up".parse().unwrap()),
+            repeat: Some(RepeatPolicy::Repeat { interval_ms: 200, min_interval_ms: 150 }),
+            ..Binding::new(KeyEvent::Volup(5))
+        }];
+        let mut engine = TriggerEngine::new(&binds, DEFAULT_SEQUENCE_TIMEOUT);
+        let t = Instant::now();
+
+        assert_eq!(actions(engine.handle(EventType::KeyPress(Key::UpArrow), t)), vec![KeyEvent::Volup(5)]);
+        // OS repeats in between don't add anything
+        assert!(engine.handle(EventType::KeyPress(Key::UpArrow), t + ms(100)).is_empty());
+        assert_eq!(engine.next_deadline(), Some(t + ms(200)));
+        assert_eq!(actions(engine.poll(t + ms(200))), vec![KeyEvent::Volup(5)]);
+        // 200ms * 0.8 = 160ms
+        assert_eq!(engine.next_deadline(), Some(t + ms(360)));
+        assert_eq!(actions(engine.poll(t + ms(360))), vec![KeyEvent::Volup(5)]);
+        // clamped to the minimum
+        assert_eq!(engine.next_deadline(), Some(t + ms(510)));
+
+        assert!(engine.handle(EventType::KeyRelease(Key::UpArrow), t + ms(400)).is_empty());
+        assert_eq!(engine.next_deadline(), None);
+    }
+
+    #[test]
+    fn test_zero_intervals_are_clamped() {
+        let binds = vec![Binding {
+            keys: Some("Up".parse().unwrap()),
+            repeat: Some(RepeatPolicy::Repeat { interval_ms: 0, min_interval_ms: 0 }),
+            ..Binding::new(KeyEvent::Volup(5))
+        }];
+        let mut engine = TriggerEngine::new(&binds, DEFAULT_SEQUENCE_TIMEOUT);
+        let t = Instant::now();
+
+        engine.handle(EventType::KeyPress(Key::UpArrow), t);
+        assert_eq!(engine.next_deadline(), Some(t + ms(MIN_REPEAT_MS)));
+        // a poll a second late catches up and returns instead of spinning forever
+        let caught_up = engine.poll(t + ms(1000));
+        assert!(!caught_up.is_empty() && caught_up.len() <= 1000 / MIN_REPEAT_MS as usize);
+        assert!(engine.next_deadline().unwrap() > t + ms(1000));
+    }
+}
+
+#[cfg(test)]
+mod consume_tests {
+    use std::time::{Duration, Instant};
+    use rdev::{EventType, Key};
+    use crate::hotkeyreg::{Binding, ConsumeFilter, KeyEvent, DEFAULT_SEQUENCE_TIMEOUT};
+
+    fn bind(keys: &str, consume: bool) -> Binding {
+        Binding { keys: Some(keys.parse().unwrap()), consume, ..Binding::new(KeyEvent::Toggle) }
+    }
+
+    // Feed events 10ms apart and report which ones were held back
+    fn run(filter: &mut ConsumeFilter, events: &[EventType]) -> Vec<bool> {
+        let start = Instant::now();
+        events
+            .iter()
+            .enumerate()
+            .map(|(i, e)| filter.consume(e, start + Duration::from_millis(10 * i as u64)))
+            .collect()
+    }
+
+    #[test]
+    fn test_only_the_matched_key_is_consumed() {
+        use EventType::*;
+        let mut filter = ConsumeFilter::new(&[bind("Ctrl+Shift+Space", true), bind("F9", false)], DEFAULT_SEQUENCE_TIMEOUT);
+        let consumed = run(&mut filter, &[
+            KeyPress(Key::ControlLeft),
+            KeyPress(Key::ShiftLeft),
+            KeyPress(Key::Space),
+            KeyPress(Key::Space), // auto-repeat
+            KeyRelease(Key::Space),
+            KeyRelease(Key::ShiftLeft),
+            KeyRelease(Key::ControlLeft),
+            KeyPress(Key::Space),
+            KeyRelease(Key::Space),
+            KeyPress(Key::F9),
+            KeyRelease(Key::F9),
+        ]);
+        assert_eq!(consumed, vec![false, false, true, true, true, false, false, false, false, false, false]);
+    }
+
+    #[test]
+    fn test_sequence_steps_are_consumed() {
+        use EventType::*;
+        let mut filter = ConsumeFilter::new(&[bind("Ctrl+K, N", true)], DEFAULT_SEQUENCE_TIMEOUT);
+        let consumed = run(&mut filter, &[
+            KeyPress(Key::ControlLeft),
+            KeyPress(Key::KeyK),
+            KeyRelease(Key::KeyK),
+            KeyRelease(Key::ControlLeft),
+            KeyPress(Key::KeyN),
+            KeyRelease(Key::KeyN),
+            // N on its own isn't bound
+            KeyPress(Key::KeyN),
+            KeyRelease(Key::KeyN),
+        ]);
+        assert_eq!(consumed, vec![false, true, true, false, true, true, false, false]);
+    }
+}
+
+
+#[cfg(test)]
+mod action_tests {
+    use crate::hotkeyreg::{Binding, KeyEvent, DEFAULT_SEEK_SECS, DEFAULT_VOLUME_STEP};
+
+    fn action(json: &str) -> KeyEvent {
+        serde_json::from_str::<Binding>(&format!(r#"{{"keys": "F9", "action": {}}}"#, json)).unwrap().action
+    }
+
+    #[test]
+    fn arguments_are_stored_with_the_binding() {
+        let bind = Binding { keys: Some("F9".parse().unwrap()), ..Binding::new(KeyEvent::Seek(-30)) };
+        let json = serde_json::to_string(&bind).unwrap();
+        assert!(json.contains(r#""action":{"Seek":-30}"#), "{}", json);
+        assert_eq!(serde_json::from_str::<Binding>(&json).unwrap(), bind);
+        assert_eq!(action(r#"{"Volup": 20}"#), KeyEvent::Volup(20));
+        assert_eq!(action(r#""Toggle""#), KeyEvent::Toggle);
+    }
+
+    #[test]
+    fn bare_legacy_actions_get_default_arguments() {
+        assert_eq!(action(r#""Volup""#), KeyEvent::Volup(DEFAULT_VOLUME_STEP));
+        assert_eq!(action(r#""SeekBack""#), KeyEvent::Seek(-DEFAULT_SEEK_SECS));
+        assert!(serde_json::from_str::<Binding>(r#"{"keys": "F9", "action": "Dance"}"#).is_err());
+    }
+}
+
+
+#[cfg(test)]
+mod uri_tests {
+    use crate::hotkeyreg::{KeyEvent, PlayTarget, PlaylistTarget, SpotifyItem, SpotifyUri, UriParseError};
+
+    const ID: &str = "37i9dQZF1DXc8kgYqQLMfH";
+
+    #[test]
+    fn uris_and_share_links_parse_to_the_same_item() {
+        let expected = SpotifyUri { item: SpotifyItem::Playlist, id: ID.to_owned() };
+        for input in [
+            format!("spotify:playlist:{}", ID),
+            format!("https://open.spotify.com/playlist/{}?si=abc123", ID),
+            format!("open.spotify.com/intl-de/playlist/{}", ID),
+            format!("  https://open.spotify.com/playlist/{}/  ", ID),
+        ] {
+            assert_eq!(input.parse::<SpotifyUri>(), Ok(expected.clone()), "{}", input);
+        }
+        assert_eq!(expected.to_string(), format!("spotify:playlist:{}", ID));
+    }
+
+    #[test]
+    fn uri_parse_errors() {
+        assert_eq!("".parse::<SpotifyUri>(), Err(UriParseError::Empty));
+        assert!(matches!("https://example.com/playlist/x".parse::<SpotifyUri>(), Err(UriParseError::NotSpotify(_))));
+        assert_eq!(
+            format!("spotify:user:{}", ID).parse::<SpotifyUri>(),
+            Err(UriParseError::UnknownItem("user".to_owned()))
+        );
+        assert_eq!("spotify:album:short".parse::<SpotifyUri>(), Err(UriParseError::InvalidId("short".to_owned())));
+    }
+
+    #[test]
+    fn actions_store_uris_in_spotify_form() {
+        let link = format!("https://open.spotify.com/album/{}?si=x", ID);
+        let mut play = KeyEvent::PlayUri(PlayTarget { uri: link.clone(), ..Default::default() });
+        assert_eq!(play.normalize_uri(), Ok(()));
+        assert_eq!(play, KeyEvent::PlayUri(PlayTarget { uri: format!("spotify:album:{}", ID), ..Default::default() }));
+
+        // only playlists can be added to
+        let mut add = KeyEvent::AddToPlaylist(PlaylistTarget { uri: link.clone(), ..Default::default() });
+        assert_eq!(add.normalize_uri(), Err(UriParseError::NotPlaylist(link)));
+        let mut unset = KeyEvent::AddToPlaylist(PlaylistTarget::default());
+        assert_eq!(unset.normalize_uri(), Ok(()));
+    }
+}
+
+#[cfg(test)]
+mod redirect_tests {
+    use crate::oauth::{percent_decode, AuthError, RedirectTarget};
+
+    fn target(host: &str, port: u16, path: &str) -> RedirectTarget {
+        RedirectTarget { host: host.to_owned(), port, path: path.to_owned() }
+    }
+
+    #[test]
+    fn loopback_redirects_parse() {
+        assert_eq!("http://127.0.0.1:8888/callback".parse(), Ok(target("127.0.0.1", 8888, "/callback")));
+        assert_eq!("http://localhost:8080".parse(), Ok(target("localhost", 8080, "/")));
+        assert_eq!("http://[::1]:9000/cb?x=1".parse(), Ok(target("::1", 9000, "/cb")));
+        assert_eq!("http://127.0.0.1/callback".parse(), Ok(target("127.0.0.1", 80, "/callback")));
+    }
+
+    #[test]
+    fn redirects_we_cant_serve_are_rejected() {
+        for uri in ["https://127.0.0.1:8888/callback", "http://example.com:8888/callback", "http://127.0.0.1:port/", ""] {
+            assert!(matches!(uri.parse::<RedirectTarget>(), Err(AuthError::InvalidRedirect(_))), "{}", uri);
+        }
+    }
+
+    #[test]
+    fn query_values_are_decoded() {
+        assert_eq!(percent_decode("access_denied"), "access_denied");
+        assert_eq!(percent_decode("user+said%20no%21"), "user said no!");
+        assert_eq!(percent_decode("100%"), "100%");
+        assert_eq!(percent_decode("%zz%C3%A9"), "%zzé");
+    }
+}
+
+#[cfg(test)]
+mod init_state_tests {
+    use crate::oauth::{AuthError, AuthProgress, InitError, InitState};
+
+    #[test]
+    fn a_login_goes_through_each_step() {
+        let mut state = InitState::new(true);
+        assert!(state.can_start());
+        state = InitState::Connecting;
+        assert!(state.is_running() && !state.can_start());
+
+        state.progress(AuthProgress::WaitingForBrowser);
+        assert_eq!(state, InitState::AwaitingBrowser(None));
+        state.progress(AuthProgress::OpenManually("http://link".to_owned()));
+        assert_eq!(state, InitState::AwaitingBrowser(Some("http://link".to_owned())));
+        state.progress(AuthProgress::Exchanging);
+        assert_eq!(state, InitState::ExchangingToken);
+        state.finish(Ok("client"));
+        assert_eq!(state.client(), Some(&"client"));
+        assert!(!state.is_running() && !state.can_start());
+    }
+
+    #[test]
+    fn logins_start_by_loading_credentials() {
+        let mut state: InitState<&str> = InitState::LoadingCredentials;
+        assert!(state.is_running() && !state.can_start());
+        state.credentials_loaded();
+        assert_eq!(state, InitState::Connecting);
+
+        // a locked keyring can be given up on
+        state = InitState::LoadingCredentials;
+        assert!(state.cancel());
+        state.credentials_loaded();
+        assert_eq!(state, InitState::Idle);
+    }
+
+    #[test]
+    fn failures_can_be_retried() {
+        let mut state: InitState<()> = InitState::Connecting;
+        state.finish(Err(InitError::TokenExchange("invalid_client".to_owned())));
+        assert_eq!(state, InitState::Failed(InitError::TokenExchange("invalid_client".to_owned())));
+        assert!(state.can_start());
+        assert!(state.describe().contains("invalid_client"));
+    }
+
+    #[test]
+    fn cancelling_drops_late_messages() {
+        let mut state: InitState<&str> = InitState::AwaitingBrowser(None);
+        assert!(state.cancel());
+        assert_eq!(state, InitState::Idle);
+        assert!(!state.cancel());
+
+        // the aborted login's last words change nothing
+        state.progress(AuthProgress::Exchanging);
+        state.finish(Ok("client"));
+        assert_eq!(state, InitState::Idle);
+    }
+
+    #[test]
+    fn credentials_decide_whether_a_login_can_start() {
+        let mut state: InitState<()> = InitState::new(false);
+        assert_eq!(state, InitState::NoCredentials);
+        assert!(!state.can_start());
+        state.credentials_changed(true);
+        assert_eq!(state, InitState::Idle);
+        state.credentials_changed(false);
+        assert_eq!(state, InitState::NoCredentials);
+
+        // a failure stays on screen until it's retried
+        let denied = InitError::Login(AuthError::Denied("access_denied".to_owned()));
+        state = InitState::Failed(denied.clone());
+        state.credentials_changed(true);
+        assert_eq!(state, InitState::Failed(denied));
+    }
+}
+
+#[cfg(test)]
+mod credential_validation_tests {
+    use crate::credentials::CredentialError;
+    use crate::oauth::{validate_credentials, AuthError, AuthMode, CredentialField, InitError};
+
+    const ID: &str = "0123456789abcdef0123456789abcdef";
+    const SECRET: &str = "fedcba9876543210fedcba9876543210";
+    const REDIRECT: &str = "http://127.0.0.1:8888/callback";
+
+    fn check(id: &str, secret: &str, redirect: &str) -> Result<(), InitError> {
+        validate_credentials(AuthMode::ClientSecret, id, secret, redirect)
+    }
+
+    #[test]
+    fn complete_credentials_pass() {
+        assert_eq!(check(ID, SECRET, REDIRECT), Ok(()));
+        assert_eq!(validate_credentials(AuthMode::Pkce, ID, "", REDIRECT), Ok(()));
+    }
+
+    #[test]
+    fn empty_fields_are_named() {
+        assert_eq!(check("", SECRET, REDIRECT), Err(InitError::Missing(CredentialField::ClientId)));
+        assert_eq!(check(ID, "", REDIRECT), Err(InitError::Missing(CredentialField::ClientSecret)));
+        assert_eq!(check(ID, SECRET, ""), Err(InitError::Missing(CredentialField::RedirectUri)));
+        assert_eq!(check(ID, SECRET, "  "), Err(InitError::Missing(CredentialField::RedirectUri)));
+    }
+
+    #[test]
+    fn whitespace_is_caught() {
+        let spaced = "0123456789abcdef 123456789abcdef";
+        assert_eq!(check(spaced, SECRET, REDIRECT), Err(InitError::Whitespace(CredentialField::ClientId)));
+        assert_eq!(check(ID, &format!("{}\n", &SECRET[1..]), REDIRECT), Err(InitError::Whitespace(CredentialField::ClientSecret)));
+    }
+
+    #[test]
+    fn lengths_are_checked() {
+        assert_eq!(check(&ID[..31], SECRET, REDIRECT), Err(InitError::WrongLength(CredentialField::ClientId, 31)));
+        let long = format!("{}0", SECRET);
+        assert_eq!(check(ID, &long, REDIRECT), Err(InitError::WrongLength(CredentialField::ClientSecret, 33)));
+    }
+
+    #[test]
+    fn pkce_ignores_the_secret() {
+        for secret in ["", "has spaces", "short"] {
+            assert_eq!(validate_credentials(AuthMode::Pkce, ID, secret, REDIRECT), Ok(()), "{:?}", secret);
+        }
+        assert_eq!(validate_credentials(AuthMode::Pkce, "", "", REDIRECT), Err(InitError::Missing(CredentialField::ClientId)));
+    }
+
+    #[test]
+    fn redirects_must_be_servable() {
+        for uri in ["https://127.0.0.1:8888/callback", "http://example.com/callback", "http://127.0.0.1:port/"] {
+            assert!(matches!(check(ID, SECRET, uri), Err(InitError::InvalidRedirect(_))), "{}", uri);
+        }
+    }
+
+    #[test]
+    fn login_failures_map_onto_init_errors() {
+        let exchange = InitError::from(AuthError::Exchange("invalid_grant".to_owned()));
+        assert_eq!(exchange, InitError::TokenExchange("invalid_grant".to_owned()));
+        let redirect = InitError::from(AuthError::InvalidRedirect("no".to_owned()));
+        assert_eq!(redirect, InitError::InvalidRedirect("no".to_owned()));
+        assert_eq!(InitError::from(AuthError::TimedOut), InitError::Login(AuthError::TimedOut));
+        assert_eq!(InitError::from(CredentialError::Locked), InitError::Store(CredentialError::Locked));
+    }
+
+    #[test]
+    fn messages_say_what_to_fix() {
+        let cases = [
+            (InitError::Missing(CredentialField::ClientId), "client id is empty"),
+            (InitError::Missing(CredentialField::ClientSecret), "PKCE"),
+            (InitError::Whitespace(CredentialField::ClientSecret), "client secret contains spaces"),
+            (InitError::WrongLength(CredentialField::ClientId, 31), "31 characters long but should be 32"),
+            (InitError::InvalidRedirect("it has to start with http://".to_owned()), "start with http://"),
+            (InitError::CorruptSettings("expected value".to_owned()), "token.json is damaged"),
+            (InitError::Settings("permission denied".to_owned()), "permission denied"),
+            (InitError::Store(CredentialError::Locked), "Unlock your keyring"),
+            (InitError::AuthorizeUrl("rng".to_owned()), "login link"),
+            (InitError::Login(AuthError::TimedOut), "within 5 minutes"),
+            (InitError::Login(AuthError::Denied("access_denied".to_owned())), "accept the permissions"),
+            (InitError::Login(AuthError::Bind("127.0.0.1:8888: in use".to_owned())), "Pick a port"),
+            (InitError::Login(AuthError::StateMismatch), "Retry"),
+            (InitError::TokenExchange("invalid_client".to_owned()), "match your app"),
+        ];
+        for (error, expected) in cases {
+            let message = error.to_string();
+            assert!(message.contains(expected), "{:?}: {}", error, message);
+        }
+    }
 }
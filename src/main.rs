@@ -3,17 +3,20 @@ use eframe::egui;
 
 use egui_notify::Toasts;
 use std::time::Duration;
-use std::os::windows::ffi::OsStrExt;
-use std::ffi::OsStr;
 use std::path::PathBuf;
-use windows::core::PCWSTR;
-use windows::Win32::UI::WindowsAndMessaging::*;
-use windows::Win32::Foundation::{HWND, BOOL, LPARAM};
 use auto_launch::AutoLaunch;
 use std::env;
 
+mod credentials;
+use credentials::*;
 mod hotkeyreg;
 use hotkeyreg::*;
+mod oauth;
+use oauth::*;
+mod player;
+use player::*;
+mod platform;
+use platform::*;
 include!("iconhandler.rs");
 include!("spotifyfunctions.rs");
 
@@ -53,45 +56,35 @@ fn main() -> eframe::Result {
         options.viewport = options.viewport.with_visible(false);
     }
 
-    eframe::run_native("SpotifyBinds", options, Box::new(|_cc| {
+    eframe::run_native("SpotifyBinds", options, Box::new(|cc| {
             let mut app = Appinfo::default();
 
-            // Load token data
-            if let Ok(instance) = MyToken::from_json() {
+            // Load token data. The client secret is read from the keyring by the login.
+            let loaded = MyToken::from_file();
+            if let Ok(instance) = &loaded {
                 app.clientId = instance.RSPOTIFY_CLIENT_ID.clone();
                 app.clientSecret = instance.RSPOTIFY_CLIENT_SECRET.clone();
                 app.redirectUri = instance.RSPOTIFY_REDIRECT_URI.clone();
             }
 
-            println!("Attempting initializiation");
-            if !app.clientId.is_empty() && !app.clientSecret.is_empty() {
-                if let Some(spotify) = tokio::task::block_in_place(|| {
-                    tokio::runtime::Handle::current().block_on(spotifyinit())
-                }) {
-                    app.spotify = Some(spotify); //do we have a working spotify connection
-                    app.spotifyinitialized = true;
-                    
-                    println!("Spotify client initialized!");
-                } else { //if not, continue with blank template
-                    app.spotify=None; //this is redundant since default handles this anyway, but its helpful for debug
-                }
-            }
 
 
             
             // load saved binds (if any) and populate UI fields
             if let Ok(b) = AppSettings::load() {
-                app.settings = b.clone();
-                if !b.toggle.is_empty() { app.toggleplayback = b.toggle; }
-                if !b.play.is_empty() { app.play = b.play; }
-                if !b.pause.is_empty() { app.pause = b.pause; }
-                if !b.next.is_empty() { app.next = b.next; }
-                if !b.previous.is_empty() { app.previous = b.previous; }
-                if !b.volup.is_empty() { app.volup = b.volup; }
-                if !b.voldown.is_empty() { app.voldown = b.voldown; }
-                if !b.mute.is_empty() { app.mute = b.mute; }
-                if b.volstepup != 0 { app.volstepup = b.volstepup; }
-                if b.volstepdown != 0 { app.volstepdown = b.volstepdown; }
+                app.settings = b;
+            }
+
+            println!("Attempting initializiation");
+            app.init = match loaded {
+                // the secret isn't known yet, so the client id decides
+                Ok(_) => InitState::new(!app.clientId.is_empty()),
+                // shown in place of the login status, with Retry
+                Err(e) => InitState::Failed(e),
+            };
+            if matches!(app.init, InitState::Idle) {
+                // Runs in the background, since a login may wait on the browser
+                app.start_login(&cc.egui_ctx);
             }
 
             let exe_path = env::current_exe()
@@ -116,65 +109,18 @@ fn main() -> eframe::Result {
                 let _ = autolaunch.enable();
             }
 
-            if (app.settings.start_on_login || app.settings.start_in_bg || autolaunch.is_enabled().unwrap_or(false)) && app.spotifyinitialized {
-                app.alreadystarted = true;
-                if let Some(ref spotify) = app.spotify {
-                            let spotify_clone = spotify.clone();
-                            let toggle_key = app.toggleplayback.clone();
-                            let play_key = app.play.clone();
-                            let pause_key = app.pause.clone();
-                            let next_key = app.next.clone();
-                            let previous_key = app.previous.clone();
-                            let volup_key = app.volup.clone();
-                            let voldown_key = app.voldown.clone();
-                            let mute_key = app.mute.clone();
-                            let incamt = app.volstepup;
-                            let decamt = app.volstepdown;
-                            
-
-                            // Create a tokio unbounded channel for the async spotify worker
-                            let (tx_tokio, mut rx_tokio) = tokio::sync::mpsc::unbounded_channel::<KeyEvent>();
-
-                            // Start the blocking rdev listener on its own OS thread and pass the
-                            // tokio sender directly so it can forward events without a bridge.
-                            let toggle = toggle_key.clone();
-                            let play = play_key.clone();
-                            let pause = pause_key.clone();
-                            let next = next_key.clone();
-                            let previous = previous_key.clone();
-                            let volup = volup_key.clone();
-                            let voldown = voldown_key.clone();
-                            let mute = mute_key.clone();
-                            
-                            let tx_clone = tx_tokio.clone();
-                            std::thread::spawn(move || {
-                                listenforkey_send(tx_clone, toggle, play, pause, next, previous, volup, voldown, mute);
-                            });
-
-                            // Spawn the spotify worker on the tokio runtime. It owns the AuthCodeSpotify.
-                            tokio::spawn(async move {
-                                let client = SpotifyClient { spotify: spotify_clone };
-                                while let Some(ev) = rx_tokio.recv().await {
-                                    match ev {
-                                        KeyEvent::Toggle => { let _ = client.toggle_playback(None).await; }
-                                        KeyEvent::Play => { let _ = client.play(None).await; }
-                                        KeyEvent::Pause => { let _ = client.pause(None).await; }
-                                        KeyEvent::Next => { let _ = client.next_track(None).await; }
-                                        KeyEvent::Previous => { let _ = client.previous_track(None).await; }
-                                        KeyEvent::Volup => { let _ = client.volup(None, incamt).await; }
-                                        KeyEvent::Voldown => { let _ = client.voldown(None, decamt).await; }
-                                        KeyEvent::Mute => { let _ = client.mute(None).await; }
-                                    }
-                                }
-                            });
+            if app.settings.start_on_login || app.settings.start_in_bg || autolaunch.is_enabled().unwrap_or(false) {
+                if app.can_start() {
+                            app.alreadystarted = true;
+                            app.notices = Some(spawn_bind_worker(app.init.client().cloned(), &app.settings, cc.egui_ctx.clone()));
 
                             (app.toasts.success("Started! Running in background."));
-                        } else {
-                            (app.toasts.info("Spotify client not initialized."));
+                        } else if app.init.is_running() {
+                            app.start_after_login = true;
                         }
             }
 
-            let tray = icon();
+            let tray = platform::tray();
             
             std::thread::spawn(move || {
                 
@@ -195,7 +141,7 @@ fn main() -> eframe::Result {
                     }
                 }
             });
-            app.tray_icon = Some(tray);
+            app.tray_icon = tray;
             
             Ok(Box::new(app))
         }),
@@ -205,25 +151,287 @@ fn main() -> eframe::Result {
 
 }
 
+// Messages from a login running in the background
+enum LoginNotice {
+    Loaded(String), // the stored client secret, read before logging in
+    Progress(AuthProgress),
+    Done(Result<Box<SpotifyAuth>, InitError>),
+}
+
+// A login running in the background, which then stays around saving the refreshed
+// token. It's aborted to cancel it or when another login replaces it, since otherwise
+// it would keep waiting on the browser or saving a stale client's token.
+struct LoginTask {
+    notices: std::sync::mpsc::Receiver<LoginNotice>,
+    task: tokio::task::JoinHandle<()>,
+}
+
+// Log in to Spotify on the tokio runtime, reporting back to the UI as it goes.
+fn spawn_login(ctx: egui::Context, mode: AuthMode) -> LoginTask {
+    let (tx, rx) = std::sync::mpsc::channel();
+    let task = tokio::spawn(async move {
+        let progress_tx = tx.clone();
+        let progress_ctx = ctx.clone();
+        let progress = move |step| {
+            let _ = progress_tx.send(LoginNotice::Progress(step));
+            progress_ctx.request_repaint();
+        };
+        let spotify = async {
+            // The keyring may have to be unlocked first, so the UI thread never waits on it
+            let token = tokio::task::spawn_blocking(MyToken::from_json)
+                .await
+                .map_err(|e| InitError::Store(CredentialError::Io(e.to_string())))??;
+            let _ = tx.send(LoginNotice::Loaded(token.RSPOTIFY_CLIENT_SECRET.clone()));
+            ctx.request_repaint();
+            spotifyinit(token, mode, progress).await
+        }
+        .await;
+        let client = spotify.as_ref().ok().cloned();
+        let _ = tx.send(LoginNotice::Done(spotify.map(Box::new)));
+        ctx.request_repaint();
+        // Keep storing the token as it's refreshed, until the next login replaces this one
+        if let Some(client) = client {
+            client.keep_token_saved().await;
+        }
+    });
+    LoginTask { notices: rx, task }
+}
 
-    // Which bind (if any) the UI is currently recording
-    #[derive(PartialEq, Eq, Clone, Copy)]
-    enum RecordingTarget {
-        Toggle,
-        Next,
-        Previous,
-        Play,
-        Pause,
-        Volup,
-        Voldown,
-        Mute,
+// Results of worker actions the user should hear about, shown as toasts
+enum WorkerNotice {
+    Success(String),
+    Error(String),
+}
+
+// Start the key listener and the player worker for the given bind table.
+fn spawn_bind_worker(
+    spotify: Option<SpotifyAuth>,
+    settings: &AppSettings,
+    ctx: egui::Context,
+) -> std::sync::mpsc::Receiver<WorkerNotice> {
+    let binds = settings.binds.clone();
+    let sequence_timeout = Duration::from_millis(settings.sequence_timeout_ms);
+    let preferred_device = Some(settings.preferred_device.clone()).filter(|name| !name.is_empty());
+    let volumes = VolumeState { premute: settings.mute_volumes.clone() };
+    let kind = settings.player_backend;
+    let (notice_tx, notice_rx) = std::sync::mpsc::channel();
+
+    // Create a tokio unbounded channel for the async player worker
+    let (tx_tokio, rx_tokio) = tokio::sync::mpsc::unbounded_channel::<Fired>();
+
+    // Start the blocking rdev listener on its own OS thread and pass the
+    // tokio sender directly so it can forward events without a bridge.
+    let warn_tx = notice_tx.clone();
+    let warn_ctx = ctx.clone();
+    std::thread::spawn(move || {
+        let warn = |message| notify(&warn_tx, &warn_ctx, WorkerNotice::Error(message));
+        listenforkey_send(tx_tokio, binds, sequence_timeout, warn);
+    });
+
+    // Spawn the player worker on the tokio runtime. It owns the backend.
+    tokio::spawn(async move {
+        match kind {
+            PlayerKind::WebApi => match spotify {
+                Some(SpotifyAuth::Secret(spotify)) => {
+                    let client = Player { backend: SpotifyClient { spotify }, preferred_device };
+                    run_bind_worker(client, rx_tokio, volumes, notice_tx, ctx).await;
+                }
+                Some(SpotifyAuth::Pkce(spotify)) => {
+                    let client = Player { backend: SpotifyClient { spotify }, preferred_device };
+                    run_bind_worker(client, rx_tokio, volumes, notice_tx, ctx).await;
+                }
+                None => notify(&notice_tx, &ctx, WorkerNotice::Error("Spotify client not initialized.".to_owned())),
+            },
+            #[cfg(target_os = "linux")]
+            PlayerKind::Mpris => match mpris::MprisPlayer::connect().await {
+                Ok(backend) => {
+                    let client = Player { backend, preferred_device };
+                    run_bind_worker(client, rx_tokio, volumes, notice_tx, ctx).await;
+                }
+                Err(e) => notify(&notice_tx, &ctx, WorkerNotice::Error(format!("Couldn't connect to D-Bus: {}", e))),
+            },
+            #[cfg(not(target_os = "linux"))]
+            PlayerKind::Mpris => {
+                notify(&notice_tx, &ctx, WorkerNotice::Error("MPRIS is only available on Linux.".to_owned()))
+            }
+        }
+    });
+
+    notice_rx
+}
+
+fn notify(notice_tx: &std::sync::mpsc::Sender<WorkerNotice>, ctx: &egui::Context, notice: WorkerNotice) {
+    let _ = notice_tx.send(notice);
+    ctx.request_repaint();
+}
+
+// Device names for the pickers, from whichever backend is selected
+async fn find_devices(kind: PlayerKind, spotify: Option<SpotifyAuth>) -> BackendResult<Vec<String>> {
+    match kind {
+        PlayerKind::WebApi => match spotify {
+            Some(SpotifyAuth::Secret(spotify)) => {
+                Player { backend: SpotifyClient { spotify }, preferred_device: None }.device_names().await
+            }
+            Some(SpotifyAuth::Pkce(spotify)) => {
+                Player { backend: SpotifyClient { spotify }, preferred_device: None }.device_names().await
+            }
+            None => Err(BackendError::Api("Spotify client not initialized.".to_owned())),
+        },
+        #[cfg(target_os = "linux")]
+        PlayerKind::Mpris => {
+            let backend = mpris::MprisPlayer::connect().await.map_err(|e| BackendError::Api(e.to_string()))?;
+            Player { backend, preferred_device: None }.device_names().await
+        }
+        #[cfg(not(target_os = "linux"))]
+        PlayerKind::Mpris => Err(BackendError::Unsupported("MPRIS is only available on Linux.")),
     }
+}
+
+// Save or forget credentials off the UI thread, since the keyring may wait on being
+// unlocked. The result is a message for a toast.
+fn spawn_credential_update(
+    ctx: egui::Context,
+    update: impl FnOnce() -> Result<String, String> + Send + 'static,
+) -> std::sync::mpsc::Receiver<Result<String, String>> {
+    let (tx, rx) = std::sync::mpsc::channel();
+    tokio::task::spawn_blocking(move || {
+        let _ = tx.send(update());
+        ctx.request_repaint();
+    });
+    rx
+}
+
+// Look devices up on the tokio runtime so the UI keeps drawing while the request runs
+fn spawn_device_lookup(
+    ctx: egui::Context,
+    kind: PlayerKind,
+    spotify: Option<SpotifyAuth>,
+) -> std::sync::mpsc::Receiver<BackendResult<Vec<String>>> {
+    let (tx, rx) = std::sync::mpsc::channel();
+    tokio::spawn(async move {
+        let _ = tx.send(find_devices(kind, spotify).await);
+        ctx.request_repaint();
+    });
+    rx
+}
+
+// Run fired actions against the player until the listener goes away
+async fn run_bind_worker<B: PlayerBackend + Sync>(
+    client: Player<B>,
+    mut rx_tokio: tokio::sync::mpsc::UnboundedReceiver<Fired>,
+    mut volumes: VolumeState,
+    notice_tx: std::sync::mpsc::Sender<WorkerNotice>,
+    ctx: egui::Context,
+) {
+    let volume_step = |ev: &KeyEvent| match ev {
+        KeyEvent::Volup(step) => *step as i32,
+        KeyEvent::Voldown(step) => -(*step as i32),
+        _ => 0,
+    };
+
+    let mut devices = DeviceIds::default();
+    let mut next = None;
+    loop {
+        let fired = match next.take() {
+            Some(fired) => fired,
+            None => match rx_tokio.recv().await {
+                Some(fired) => fired,
+                None => break,
+            },
+        };
+        // Bindings name their device. If it's gone the action goes to the active
+        // device instead.
+        let device = match &fired.device {
+            Some(name) => match devices.get(&client, name).await {
+                Ok(Some(id)) => Some(id),
+                _ => {
+                    println!("Device '{}' is not available", name);
+                    None
+                }
+            },
+            None => None,
+        };
+        let device_id = device.as_deref();
+        let result = match fired.action {
+            KeyEvent::Toggle => client.toggle_playback(device_id).await,
+            KeyEvent::Play => client.play(device_id).await,
+            KeyEvent::Pause => client.pause(device_id).await,
+            KeyEvent::Next => client.next_track(device_id).await,
+            KeyEvent::Previous => client.previous_track(device_id).await,
+            KeyEvent::Volup(_) | KeyEvent::Voldown(_) => {
+                // Fold volume presses for the same device that queued up while the
+                // last request was in flight into a single change
+                let mut delta = volume_step(&fired.action);
+                while let Ok(queued) = rx_tokio.try_recv() {
+                    if matches!(queued.action, KeyEvent::Volup(_) | KeyEvent::Voldown(_)) && queued.device == fired.device {
+                        delta += volume_step(&queued.action);
+                    } else {
+                        next = Some(queued);
+                        break;
+                    }
+                }
+                client.change_volume(device_id, delta).await
+            }
+            KeyEvent::Mute => match client.toggle_mute(device_id, &mut volumes).await {
+                Ok(true) => {
+                    // Saving waits on the file and on any save from the UI, so keep it off the runtime
+                    let premute = volumes.premute.clone();
+                    let _ = tokio::task::spawn_blocking(move || AppSettings::save_mute_volumes(&premute)).await;
+                    Ok(())
+                }
+                result => result.map(|_| ()),
+            },
+            KeyEvent::SetVolume(percent) => client.set_volume(device_id, percent).await,
+            KeyEvent::Seek(secs) => client.seek_relative(device_id, secs as i64 * 1000).await,
+            KeyEvent::Restart => client.restart_track(device_id).await,
+            KeyEvent::CycleShuffle => client.cycle_shuffle(device_id).await,
+            KeyEvent::CycleRepeat => client.cycle_repeat(device_id).await,
+            KeyEvent::ToggleLike => client.toggle_like().await,
+            KeyEvent::PlayUri(target) => client.play_target(device_id, &target).await,
+            KeyEvent::AddToPlaylist(target) => {
+                let notice = match client.add_to_playlist(&target).await {
+                    Ok(message) => WorkerNotice::Success(message),
+                    Err(e) => WorkerNotice::Error(format!("Couldn't add to playlist: {}", e)),
+                };
+                notify(&notice_tx, &ctx, notice);
+                Ok(())
+            }
+            KeyEvent::TransferTo(name) => client.transfer_to(&name).await,
+            KeyEvent::CycleDevice => client.cycle_device().await,
+        };
+        // The device may have reconnected under a new id, so it's looked up again next time
+        if let (Err(_), Some(name)) = (&result, &fired.device) {
+            devices.forget(name);
+        }
+    }
+}
+
 
     #[derive(Serialize, Deserialize, Default, Clone)]
+    #[serde(default)]
     struct AppSettings {
         start_on_login: bool,
         start_minimized: bool,
         start_in_bg: bool,
+        binds: Vec<Binding>,
+        sequence_timeout_ms: u64,
+        preferred_device: String, // device name, empty for none
+        player_backend: PlayerKind,
+        auth_mode: AuthMode,
+        mute_volumes: std::collections::HashMap<String, u32>, // written by the worker, see `save`
+
+        // Settings files written before the bind table kept one field per action.
+        // They are only read so `load` can migrate them into `binds`.
+        #[serde(flatten, skip_serializing)]
+        legacy: LegacyBinds,
+        // Likewise for the global amounts used before actions carried their own
+        #[serde(flatten, skip_serializing)]
+        legacy_amounts: LegacyAmounts,
+    }
+
+    #[derive(Serialize, Deserialize, Default, Clone)]
+    #[serde(default)]
+    struct LegacyBinds {
         toggle: String,
         play: String,
         pause: String,
@@ -232,12 +440,54 @@ fn main() -> eframe::Result {
         volup: String,
         voldown: String,
         mute: String,
+    }
+
+    #[derive(Serialize, Deserialize, Default, Clone)]
+    #[serde(default)]
+    struct LegacyAmounts {
         volstepup: u32,
         volstepdown: u32,
-        
+        seek_secs: u32,
+        set_volume_percent: u32,
+    }
+
+    impl LegacyAmounts {
+        // Files that still have these were written before actions had arguments, so
+        // every bind took its amount from here
+        fn apply(&self, bind: &mut Binding) {
+            match &mut bind.action {
+                KeyEvent::Volup(step) if self.volstepup != 0 => *step = self.volstepup,
+                KeyEvent::Voldown(step) if self.volstepdown != 0 => *step = self.volstepdown,
+                KeyEvent::Seek(secs) if self.seek_secs != 0 => *secs = secs.signum() * self.seek_secs as i32,
+                KeyEvent::SetVolume(percent) if self.set_volume_percent != 0 => *percent = self.set_volume_percent,
+                _ => {}
+            }
+        }
+    }
+
+    impl LegacyBinds {
+        fn into_binds(self) -> Vec<Binding> {
+            [
+                (self.toggle, KeyEvent::Toggle),
+                (self.next, KeyEvent::Next),
+                (self.previous, KeyEvent::Previous),
+                (self.play, KeyEvent::Play),
+                (self.pause, KeyEvent::Pause),
+                (self.volup, KeyEvent::Volup(DEFAULT_VOLUME_STEP)),
+                (self.voldown, KeyEvent::Voldown(DEFAULT_VOLUME_STEP)),
+                (self.mute, KeyEvent::Mute),
+            ]
+            .into_iter()
+            .map(|(keys, action)| Binding { keys: keys.parse().ok(), ..Binding::new(action) })
+            .collect()
+        }
     }
 
 
+    // The UI and the worker both rewrite the settings file. Each save loads it, changes
+    // its own part and writes it back, so saves take turns or one would undo the other.
+    static SETTINGS_WRITE: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
     impl AppSettings {
         fn path() -> PathBuf {
             // Use executable directory for persistence so autostart with a
@@ -252,16 +502,48 @@ fn main() -> eframe::Result {
 
         fn load() -> Result<Self, std::io::Error> {
             let p = Self::path();
-            if p.exists() {
+            let mut cfg = if p.exists() {
                 let s = std::fs::read_to_string(p)?;
-                let cfg: Self = serde_json::from_str(&s)?;
-                Ok(cfg)
+                serde_json::from_str::<Self>(&s)?
             } else {
-                Ok(Self::default())
+                Self::default()
+            };
+
+            if cfg.binds.is_empty() {
+                let legacy = std::mem::take(&mut cfg.legacy).into_binds();
+                cfg.binds = if legacy.iter().all(Binding::is_unbound) {
+                    default_binds()
+                } else {
+                    legacy
+                };
+            }
+            if cfg.sequence_timeout_ms == 0 {
+                cfg.sequence_timeout_ms = DEFAULT_SEQUENCE_TIMEOUT.as_millis() as u64;
             }
+            let amounts = std::mem::take(&mut cfg.legacy_amounts);
+            cfg.binds.iter_mut().for_each(|bind| amounts.apply(bind));
+            Ok(cfg)
         }
 
+        // The worker owns the mute volumes and saves them itself, so the UI's copy,
+        // loaded at startup, never overwrites them
         fn save(&self) -> Result<(), std::io::Error> {
+            let _lock = SETTINGS_WRITE.lock().unwrap_or_else(|e| e.into_inner());
+            let mut cfg = self.clone();
+            if let Ok(saved) = Self::load() {
+                cfg.mute_volumes = saved.mute_volumes;
+            }
+            cfg.write()
+        }
+
+        fn save_mute_volumes(volumes: &std::collections::HashMap<String, u32>) -> Result<(), std::io::Error> {
+            let _lock = SETTINGS_WRITE.lock().unwrap_or_else(|e| e.into_inner());
+            let mut cfg = Self::load()?;
+            cfg.mute_volumes = volumes.clone();
+            cfg.write()
+        }
+
+        fn write(&self) -> Result<(), std::io::Error> {
             let s = serde_json::to_string_pretty(self).unwrap();
             std::fs::write(Self::path(), s)
         }
@@ -272,27 +554,23 @@ fn main() -> eframe::Result {
     // Our application initial state:
     struct Appinfo {
         toasts: Toasts, //notifications
-        recording_target: Option<RecordingTarget>,
+        recording_target: Option<usize>, // index into settings.binds
+        chord_text: String, // typed keys while recording, for side-specific modifiers
+        recorded_steps: Vec<KeyChord>, // chords captured so far for a sequence
+        last_step: Option<std::time::Instant>,
         clientId: String,
         clientSecret: String,
         redirectUri: String,
 
-        toggleplayback: String,
-        play: String,
-        pause: String,
-        next: String,
-        previous: String,
-        volup: String,
-        voldown: String,
-        mute: String,
-
-        volstepup: u32,
-        volstepdown: u32,
-
-        spotify: Option<AuthCodeSpotify>,
+        init: InitState<SpotifyAuth>, // the client once logged in
+        notices: Option<std::sync::mpsc::Receiver<WorkerNotice>>, // set once the worker is running
+        login: Option<LoginTask>, // the latest login, which keeps saving the token once it succeeds
+        start_after_login: bool, // autostart is waiting on the login
+        devices: Vec<String>, // device names from the last "Find devices"
+        device_lookup: Option<std::sync::mpsc::Receiver<BackendResult<Vec<String>>>>, // set while "Find devices" runs
+        credential_update: Option<std::sync::mpsc::Receiver<Result<String, String>>>, // set while credentials are saved
         settings: AppSettings,
         tray_icon: Option<TrayIcon>,
-        spotifyinitialized: bool,
         alreadystarted: bool,
         firstframe: bool,
     }
@@ -302,26 +580,22 @@ fn main() -> eframe::Result {
                 Self {
                     toasts: Toasts::default(),
                     recording_target: None,
+                    chord_text: String::new(),
+                    recorded_steps: Vec::new(),
+                    last_step: None,
                     clientId: "".to_owned(),
                     clientSecret: "".to_owned(),
                     redirectUri: "".to_owned(),
 
-                    toggleplayback: "           ".to_owned(),
-                    play: "           ".to_owned(),
-                    pause: "           ".to_owned(),
-                    next: "           ".to_owned(),
-                    previous: "           ".to_owned(),
-                    volup: "           ".to_owned(),
-                    voldown: "           ".to_owned(),
-                    mute: "           ".to_owned(),
-
-                    volstepup: 0,
-                    volstepdown: 0,
-
-                    spotify: None,
+                    init: InitState::NoCredentials,
+                    notices: None,
+                    login: None,
+                    start_after_login: false,
+                    devices: Vec::new(),
+                    device_lookup: None,
+                    credential_update: None,
                     settings: AppSettings::default(),
                     tray_icon: None,
-                    spotifyinitialized: false,
                     alreadystarted: false,
                     firstframe: true,
                 }
@@ -330,8 +604,137 @@ fn main() -> eframe::Result {
 
     
 
+    // Combo box for a device name. Lists the devices last found, plus the current
+    // choice in case that device is offline right now.
+    fn device_picker(ui: &mut egui::Ui, id_salt: impl std::hash::Hash, name: &mut String, none_label: &str, devices: &[String]) -> bool {
+        let mut changed = false;
+        let selected = if name.is_empty() { none_label.to_owned() } else { name.clone() };
+        egui::ComboBox::from_id_salt(id_salt).selected_text(selected).show_ui(ui, |ui| {
+            changed |= ui.selectable_value(name, String::new(), none_label).changed();
+            let offline = (!name.is_empty() && !devices.contains(name)).then(|| name.clone());
+            for device in devices.iter().cloned().chain(offline) {
+                let label = device.clone();
+                changed |= ui.selectable_value(name, device, label).changed();
+            }
+        });
+        changed
+    }
+
+    impl Appinfo {
+        // The Web API needs an initialized client, MPRIS only needs the session bus
+        fn can_start(&self) -> bool {
+            self.init.client().is_some() || self.settings.player_backend == PlayerKind::Mpris
+        }
+
+        // The secret may only be in the keyring, which isn't read until a login runs,
+        // so the client id decides. A login missing its secret fails saying so.
+        fn has_credentials(&self) -> bool {
+            !self.clientId.is_empty()
+        }
+
+        fn start_login(&mut self, ctx: &egui::Context) {
+            if let Some(login) = self.login.take() {
+                login.task.abort();
+            }
+            self.login = Some(spawn_login(ctx.clone(), self.settings.auth_mode));
+            self.init = InitState::LoadingCredentials;
+        }
+
+        fn cancel_login(&mut self) {
+            if let Some(login) = self.login.take() {
+                login.task.abort();
+            }
+            self.start_after_login = false;
+            self.init.cancel();
+        }
+
+        fn poll_credential_update(&mut self) {
+            let Some(update) = &self.credential_update else {
+                return;
+            };
+            let result = match update.try_recv() {
+                Ok(result) => result,
+                Err(std::sync::mpsc::TryRecvError::Empty) => return,
+                Err(std::sync::mpsc::TryRecvError::Disconnected) => Err("Saving credentials stopped".to_owned()),
+            };
+            self.credential_update = None;
+            match result {
+                Ok(message) => {
+                    (self.toasts.success(message));
+                    let has_credentials = self.has_credentials();
+                    self.init.credentials_changed(has_credentials);
+                }
+                Err(message) => { (self.toasts.error(message)); }
+            }
+        }
+
+        fn poll_devices(&mut self) {
+            let Some(lookup) = &self.device_lookup else {
+                return;
+            };
+            let result = match lookup.try_recv() {
+                Ok(result) => result,
+                Err(std::sync::mpsc::TryRecvError::Empty) => return,
+                Err(std::sync::mpsc::TryRecvError::Disconnected) => Err(BackendError::Api("the lookup stopped".to_owned())),
+            };
+            self.device_lookup = None;
+            match result {
+                Ok(names) if names.is_empty() => { (self.toasts.info("No devices found. Open Spotify on one first.")); }
+                Ok(names) => self.devices = names,
+                Err(e) => { (self.toasts.error(format!("Couldn't list devices: {}", e))); }
+            }
+        }
+
+        fn poll_login(&mut self, ctx: &egui::Context) {
+            let Some(login) = &self.login else {
+                return;
+            };
+            let notices: Vec<LoginNotice> = login.notices.try_iter().collect();
+            for notice in notices {
+                if let LoginNotice::Progress(step) = &notice {
+                    self.init.progress(step.clone());
+                }
+                match notice {
+                    LoginNotice::Loaded(secret) => {
+                        self.init.credentials_loaded();
+                        // Only fill in what hasn't been typed over since startup
+                        if self.clientSecret.is_empty() {
+                            self.clientSecret = secret;
+                        }
+                    }
+                    LoginNotice::Progress(AuthProgress::WaitingForBrowser) => {
+                        (self.toasts.info("Log in to Spotify in your browser to continue."));
+                    }
+                    LoginNotice::Progress(AuthProgress::OpenManually(url)) => {
+                        println!("Open this link to log in to Spotify: {}", url);
+                        ctx.copy_text(url);
+                        (self.toasts.info("Couldn't open a browser. The login link was copied, open it to continue."));
+                    }
+                    LoginNotice::Progress(AuthProgress::Exchanging) => {}
+                    LoginNotice::Done(Ok(spotify)) => {
+                        self.init.finish(Ok(*spotify)); //do we have a working spotify connection
+                        (self.toasts.success("Spotify client initialized!"));
+                        if std::mem::take(&mut self.start_after_login) && !self.alreadystarted {
+                            self.alreadystarted = true;
+                            self.notices = Some(spawn_bind_worker(self.init.client().cloned(), &self.settings, ctx.clone()));
+                            (self.toasts.success("Started! Running in background."));
+                        }
+                    }
+                    LoginNotice::Done(Err(e)) => {
+                        self.login = None;
+                        self.start_after_login = false;
+                        println!("Failed to initialize spotify client: {:?}", e);
+                        (self.toasts.error(e.to_string()));
+                        self.init.finish(Err(e));
+                    }
+                }
+            }
+        }
+    }
+
     impl eframe::App for Appinfo {
         fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+            platform::register_context(ctx);
 
             if self.firstframe && self.settings.start_in_bg {
                 self.firstframe = false;
@@ -350,9 +753,37 @@ fn main() -> eframe::Result {
                 ui.heading("SpotifyBinds");
 
                 //notifications
+                if let Some(notices) = &self.notices {
+                    for notice in notices.try_iter() {
+                        match notice {
+                            WorkerNotice::Success(message) => { (self.toasts.success(message)); }
+                            WorkerNotice::Error(message) => { (self.toasts.error(message)); }
+                        }
+                    }
+                }
+                self.poll_login(ctx);
+                self.poll_devices();
+                self.poll_credential_update();
                 self.toasts.show(ctx);
 
 
+                ui.horizontal(|ui|{
+                    ui.label("Log in with:");
+                    let mut changed = false;
+                    egui::ComboBox::from_id_salt("auth_mode").selected_text(self.settings.auth_mode.label()).show_ui(ui, |ui| {
+                        for mode in AuthMode::ALL {
+                            changed |= ui.selectable_value(&mut self.settings.auth_mode, mode, mode.label()).changed();
+                        }
+                    });
+                    if changed {
+                        let _ = self.settings.save();
+                        // The next initialize logs in with the new mode. A running
+                        // worker keeps the client it started with.
+                        if !self.init.is_running() {
+                            self.init = InitState::new(self.has_credentials());
+                        }
+                    }
+                });
                 ui.horizontal(|ui|{
                     let clientid = ui.label("Client id: ");
                     ui.text_edit_singleline(&mut self.clientId)
@@ -361,7 +792,8 @@ fn main() -> eframe::Result {
                 });
                 ui.horizontal(|ui|{
                     let clientsecret = ui.label("Client secret: ");
-                    ui.text_edit_singleline(&mut self.clientSecret)
+                    let needs_secret = self.settings.auth_mode == AuthMode::ClientSecret;
+                    ui.add_enabled(needs_secret, egui::TextEdit::singleline(&mut self.clientSecret))
                         .labelled_by(clientsecret.id);
                 });
                 ui.horizontal(|ui|{
@@ -371,38 +803,64 @@ fn main() -> eframe::Result {
                 });
 
                 ui.horizontal(|ui|{
-                    if ui.button("Save credentials").clicked() {
+                    let saving = self.credential_update.is_some();
+                    if ui.add_enabled(!saving, egui::Button::new("Save credentials")).clicked() {
                         let instance = MyToken{
                             RSPOTIFY_CLIENT_ID: self.clientId.clone(),
                             RSPOTIFY_CLIENT_SECRET: self.clientSecret.clone(),
                             RSPOTIFY_REDIRECT_URI: self.redirectUri.clone(),
                         };
-                        if let Err(e) = instance.save_to_json() {
-                            (self.toasts.error(format!("Failed to save: {}", e)));
-                        } else {
-                            (self.toasts.success("Saved!"));
-                        }
+                        // an empty secret field keeps the stored secret
+                        self.credential_update = Some(spawn_credential_update(ctx.clone(), move || {
+                            instance.save_to_json().map_err(|e| format!("Failed to save: {}", e))?;
+                            Ok(format!("Saved! Secrets are kept in the {}.", credential_store().name()))
+                        }));
+                    }
+                    if ui.add_enabled(!saving, egui::Button::new("Forget secret")).clicked() {
+                        self.clientSecret.clear();
+                        self.credential_update = Some(spawn_credential_update(ctx.clone(), || {
+                            MyToken::forget_secret().map_err(|e| format!("Couldn't forget the secret: {}", e))?;
+                            Ok("Forgot the stored client secret.".to_owned())
+                        }));
+                    }
+                    if saving {
+                        ui.spinner();
                     }
                 });
 
                 
-                if !self.spotifyinitialized {
-                    ui.horizontal(|ui|{
-                        if ui.button("Initialize spotify client").clicked() {
-                            if let Some(spotify) = tokio::task::block_in_place(|| {
-                                tokio::runtime::Handle::current().block_on(spotifyinit())
-                            }) {
-                                self.spotify = Some(spotify); //do we have a working spotify connection
-                                self.spotifyinitialized = true;
-                                (self.toasts.success("Spotify client initialized!"));
-                            } else {
-                                self.spotify=None;
-                                self.spotifyinitialized = false;
-                                (self.toasts.error("Failed to initialize spotify client. Check your credentials."));
+                ui.horizontal(|ui|{
+                    match &self.init {
+                        InitState::NoCredentials => {
+                            ui.add_enabled(false, egui::Button::new("Initialize spotify client"));
+                        }
+                        InitState::Idle => {
+                            if ui.button("Initialize spotify client").clicked() {
+                                self.start_login(ctx);
                             }
                         }
-                    });
-                }
+                        InitState::Failed(_) => {
+                            if ui.button("Retry").clicked() {
+                                self.start_login(ctx);
+                            }
+                        }
+                        InitState::AwaitingBrowser(Some(url)) => {
+                            if ui.button("Copy login link").clicked() {
+                                ctx.copy_text(url.clone());
+                            }
+                            if ui.button("Cancel").clicked() {
+                                self.cancel_login();
+                            }
+                        }
+                        InitState::LoadingCredentials | InitState::Connecting | InitState::AwaitingBrowser(None) | InitState::ExchangingToken => {
+                            if ui.button("Cancel").clicked() {
+                                self.cancel_login();
+                            }
+                        }
+                        InitState::Ready(_) => {}
+                    }
+                    ui.label(self.init.describe());
+                });
                 
 
                 ui.horizontal(|ui|{
@@ -412,52 +870,11 @@ fn main() -> eframe::Result {
                     } else if ui.button("Start").clicked() {
                         self.alreadystarted = true;
                         
-                        // Move the spotify client into a background async worker so
-                        // key events are handled even when the UI is minimized.
-                        if let Some(spotify) = self.spotify.take() {
-                            let toggle_key = self.toggleplayback.clone();
-                            let play_key = self.play.clone();
-                            let pause_key = self.pause.clone();
-                            let next_key = self.next.clone();
-                            let previous_key = self.previous.clone();
-                            let volup_key = self.volup.clone();
-                            let voldown_key = self.voldown.clone();
-                            let mute_key = self.mute.clone();
-                            let incamt = self.volstepup;
-                            let decamt = self.volstepdown;
-                            
-
-                            // Create a tokio unbounded channel for the async spotify worker
-                            let (tx_tokio, mut rx_tokio) = tokio::sync::mpsc::unbounded_channel::<KeyEvent>();
-
-                            // Start the blocking rdev listener on its own OS thread and pass the
-                            // tokio sender directly so it can forward events without a bridge.
-                            let toggle = toggle_key.clone();
-                            let play = play_key.clone();
-                            let pause = pause_key.clone();
-                            let next = next_key.clone();
-                            let previous = previous_key.clone();
-                            let tx_clone = tx_tokio.clone();
-                            std::thread::spawn(move || {
-                                listenforkey_send(tx_clone, toggle, play, pause, next, previous, volup_key, voldown_key, mute_key);
-                            });
-
-                            // Spawn the spotify worker on the tokio runtime. It owns the AuthCodeSpotify.
-                            tokio::spawn(async move {
-                                let client = SpotifyClient { spotify };
-                                while let Some(ev) = rx_tokio.recv().await {
-                                    match ev {
-                                        KeyEvent::Toggle => { let _ = client.toggle_playback(None).await; }
-                                        KeyEvent::Play => { let _ = client.play(None).await; }
-                                        KeyEvent::Pause => { let _ = client.pause(None).await; }
-                                        KeyEvent::Next => { let _ = client.next_track(None).await; }
-                                        KeyEvent::Previous => { let _ = client.previous_track(None).await; }
-                                        KeyEvent::Volup => { let _ = client.volup(None, incamt).await; }
-                                        KeyEvent::Voldown => { let _ = client.voldown(None, decamt).await; }
-                                        KeyEvent::Mute => { let _ = client.mute(None).await; }
-                                    }
-                                }
-                            });
+                        // Hand the spotify client to a background async worker so key events
+                        // are handled even when the UI is minimized. The UI keeps a clone
+                        // for looking up devices.
+                        if self.can_start() {
+                            self.notices = Some(spawn_bind_worker(self.init.client().cloned(), &self.settings, ctx.clone()));
 
                             (self.toasts.success("Started! Running in background."));
 
@@ -529,269 +946,267 @@ fn main() -> eframe::Result {
 
                 ui.add_space(10.0);
 
-                ui.horizontal(|ui| {
-                    ui.label("Toggle playback: ");
-                    
-                    if self.recording_target == Some(RecordingTarget::Toggle) {
-                        ui.label("Press a key or key combination...");
-
-                        if let Some(key_combo) = capture_key_input(ctx) {
-                            self.toggleplayback = key_combo.clone();
-                            self.settings.toggle = key_combo;
-                            let _ = self.settings.save();
-                            self.recording_target = None;
+                let mut changed = false;
+                let mut remove = None;
+                for (i, bind) in self.settings.binds.iter_mut().enumerate() {
+                    ui.horizontal(|ui| {
+                        egui::ComboBox::from_id_salt(("bind_action", i))
+                            .selected_text(bind.action.label())
+                            .show_ui(ui, |ui| {
+                                for action in KeyEvent::ALL {
+                                    if ui.selectable_label(bind.action.same_kind(&action), action.label()).clicked()
+                                        && !bind.action.same_kind(&action)
+                                    {
+                                        bind.action = action;
+                                        changed = true;
+                                    }
+                                }
+                            });
+                        match &mut bind.action {
+                            KeyEvent::Volup(step) | KeyEvent::Voldown(step) => {
+                                changed |= ui.add(egui::DragValue::new(step).range(1..=100).suffix("%")).changed();
+                            }
+                            KeyEvent::Seek(secs) => {
+                                let sign = if *secs >= 0 { "+" } else { "" };
+                                changed |= ui.add(egui::DragValue::new(secs).range(-600..=600).prefix(sign).suffix(" s")).changed();
+                            }
+                            KeyEvent::SetVolume(percent) => {
+                                changed |= ui.add(egui::DragValue::new(percent).range(0..=100).suffix("%")).changed();
+                            }
+                            KeyEvent::PlayUri(target) => {
+                                changed |= ui
+                                    .add(
+                                        egui::TextEdit::singleline(&mut target.uri)
+                                            .hint_text("spotify:playlist:... or link")
+                                            .desired_width(180.0),
+                                    )
+                                    .lost_focus();
+                                changed |= ui.checkbox(&mut target.shuffle, "Shuffle").changed();
+                                let mut start = target.offset + 1;
+                                if ui.add(egui::DragValue::new(&mut start).range(1..=10000).prefix("from #")).changed() {
+                                    target.offset = start - 1;
+                                    changed = true;
+                                }
+                            }
+                            KeyEvent::AddToPlaylist(target) => {
+                                changed |= ui
+                                    .add(
+                                        egui::TextEdit::singleline(&mut target.uri)
+                                            .hint_text("playlist URI or link")
+                                            .desired_width(180.0),
+                                    )
+                                    .lost_focus();
+                                changed |= ui
+                                    .checkbox(&mut target.allow_duplicates, "Allow duplicates")
+                                    .on_hover_text("Add the track even if the playlist already has it")
+                                    .changed();
+                            }
+                            KeyEvent::TransferTo(name) => {
+                                changed |= device_picker(ui, ("bind_transfer", i), name, "Choose device", &self.devices);
+                            }
+                            _ => {}
                         }
 
-                        if ui.button("Cancel").clicked() {
-                            self.recording_target = None;
-                        }
-                    } else {
-                        if ui.button(&self.toggleplayback).clicked() {
-                            println!("Recording key...");
-                            self.recording_target = Some(RecordingTarget::Toggle);
-                            (self.toasts.info("Key recording..."));
+                        if bind.action.targets_device() {
+                            let mut device = bind.device.clone().unwrap_or_default();
+                            if device_picker(ui, ("bind_device", i), &mut device, "Active device", &self.devices) {
+                                bind.device = Some(device).filter(|d| !d.is_empty());
+                                changed = true;
+                            }
                         }
-                    }
-                    ui.add_space(15.0);
-                    if ui.button("Clear").clicked() {
-                        self.toggleplayback = "           ".to_owned();
-                        self.settings.toggle = "           ".to_owned();
-                        let _ = self.settings.save();
-                    }
-                });
 
-                ui.horizontal(|ui| {
-                    ui.label("Skip: ");
-                    
-                    if self.recording_target == Some(RecordingTarget::Next) {
-                        ui.label("Press a key or key combination...");
-
-                        if let Some(key_combo) = capture_key_input(ctx) {
-                            self.next = key_combo.clone();
-                            self.settings.next = key_combo;
-                            let _ = self.settings.save();
-                            self.recording_target = None;
+                        egui::ComboBox::from_id_salt(("bind_trigger", i))
+                            .selected_text(bind.trigger.label())
+                            .show_ui(ui, |ui| {
+                                for kind in Trigger::KINDS {
+                                    if ui.selectable_label(bind.trigger.same_kind(&kind), kind.label()).clicked()
+                                        && !bind.trigger.same_kind(&kind)
+                                    {
+                                        bind.trigger = kind;
+                                        changed = true;
+                                    }
+                                }
+                            });
+                        match &mut bind.trigger {
+                            Trigger::Hold(ms) => {
+                                changed |= ui.add(egui::DragValue::new(ms).range(100..=5000).suffix(" ms")).changed();
+                            }
+                            Trigger::Tap(count) => {
+                                changed |= ui.add(egui::DragValue::new(count).range(1..=5).suffix("x")).changed();
+                            }
+                            _ => {}
                         }
 
-                        if ui.button("Cancel").clicked() {
-                            self.recording_target = None;
-                        }
-                    } else {
-                        if ui.button(&self.next).clicked() {
-                            println!("Recording key...");
-                            self.recording_target = Some(RecordingTarget::Next);
-                            (self.toasts.info("Key recording..."));
+                        // Repeat while held only applies to press triggers
+                        if bind.trigger == Trigger::Press {
+                            let mut policy = bind.repeat_policy();
+                            let mut repeats = policy != RepeatPolicy::Ignore;
+                            let mut repeat_changed = false;
+                            if ui.checkbox(&mut repeats, "Repeat").changed() {
+                                policy = if repeats { RepeatPolicy::DEFAULT_REPEAT } else { RepeatPolicy::Ignore };
+                                repeat_changed = true;
+                            }
+                            if let RepeatPolicy::Repeat { interval_ms, min_interval_ms } = &mut policy {
+                                repeat_changed |= ui.add(egui::DragValue::new(interval_ms).range(MIN_REPEAT_MS..=2000).prefix("every ").suffix(" ms")).changed();
+                                repeat_changed |= ui.add(egui::DragValue::new(min_interval_ms).range(MIN_REPEAT_MS..=2000).prefix("down to ").suffix(" ms")).changed();
+                            }
+                            if repeat_changed {
+                                bind.repeat = Some(policy);
+                                changed = true;
+                            }
                         }
-                    }
-                    ui.add_space(15.0);
-                    if ui.button("Clear").clicked() {
-                        self.next = "           ".to_owned();
-                        self.settings.next = "           ".to_owned();
-                        let _ = self.settings.save();
-                    }
-                });
 
-                ui.horizontal(|ui| {
-                    ui.label("Previous: ");
-                    
-                    if self.recording_target == Some(RecordingTarget::Previous) {
-                        ui.label("Press a key or key combination...");
-
-                        if let Some(key_combo) = capture_key_input(ctx) {
-                            self.previous = key_combo.clone();
-                            self.settings.previous = key_combo;
-                            let _ = self.settings.save();
-                            self.recording_target = None;
-                        }
+                        changed |= ui
+                            .checkbox(&mut bind.consume, "Consume")
+                            .on_hover_text("Keep the bound keys from reaching the focused app")
+                            .changed();
 
-                        if ui.button("Cancel").clicked() {
-                            self.recording_target = None;
-                        }
-                    } else {
-                        if ui.button(&self.previous).clicked() {
-                            println!("Recording key...");
-                            self.recording_target = Some(RecordingTarget::Previous);
-                            (self.toasts.info("Key recording..."));
-                        }
-                    } 
-                    ui.add_space(15.0);
-                    if ui.button("Clear").clicked() {
-                        self.previous = "           ".to_owned();
-                        self.settings.previous = "           ".to_owned();
-                        let _ = self.settings.save();
-                    }
-                });
+                        if self.recording_target == Some(i) {
+                            if self.recorded_steps.is_empty() {
+                                ui.label("Press a key or mouse button (hold a modifier for clicks and scrolling), or type one:");
+                                if platform::super_held().is_none() {
+                                    ui.weak("Super can't be recorded here, type it in (e.g. Super+P)");
+                                }
+                            } else {
+                                let steps = KeySequence::new(self.recorded_steps.clone()).unwrap();
+                                ui.label(format!("{}, ...", steps));
+                            }
 
-                ui.horizontal(|ui| {
-                    ui.label("Play: ");
-                    
-                    if self.recording_target == Some(RecordingTarget::Play) {
-                        ui.label("Press a key or key combination...");
-
-                        if let Some(key_combo) = capture_key_input(ctx) {
-                            self.play = key_combo.clone();
-                            self.settings.play = key_combo;
-                            let _ = self.settings.save();
-                            self.recording_target = None;
-                        }
+                            let typed = ui.add(
+                                egui::TextEdit::singleline(&mut self.chord_text)
+                                    .hint_text("Ctrl+Alt+S, N")
+                                    .desired_width(120.0),
+                            );
+                            let mut done = None;
+                            if typed.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter)) {
+                                match self.chord_text.parse::<KeySequence>() {
+                                    Ok(keys) => done = Some(keys),
+                                    Err(e) => {
+                                        (self.toasts.error(format!("Invalid key combination: {}", e)));
+                                    }
+                                }
+                            } else if !typed.has_focus() {
+                                // Each captured chord is one step. The sequence is saved once
+                                // the user stops pressing keys for the sequence timeout.
+                                if let Some(chord) = capture_key_input(ctx, platform::super_held().unwrap_or(false)) {
+                                    self.recorded_steps.push(chord);
+                                    self.last_step = Some(std::time::Instant::now());
+                                }
+                                let timeout = Duration::from_millis(self.settings.sequence_timeout_ms);
+                                if let Some(last) = self.last_step {
+                                    if last.elapsed() >= timeout {
+                                        done = KeySequence::new(self.recorded_steps.clone());
+                                    } else {
+                                        ctx.request_repaint_after(timeout - last.elapsed());
+                                    }
+                                }
+                            }
+
+                            if ui.add_enabled(!self.recorded_steps.is_empty(), egui::Button::new("Done")).clicked() {
+                                done = KeySequence::new(self.recorded_steps.clone());
+                            }
 
-                        if ui.button("Cancel").clicked() {
-                            self.recording_target = None;
+                            if let Some(keys) = done {
+                                bind.keys = Some(keys);
+                                changed = true;
+                                self.recording_target = None;
+                            }
+
+                            if ui.button("Cancel").clicked() {
+                                self.recording_target = None;
+                            }
+                        } else {
+                            let text = match &bind.keys {
+                                Some(keys) => keys.to_string(),
+                                None => "           ".to_owned(),
+                            };
+                            if ui.button(text).clicked() {
+                                println!("Recording key...");
+                                self.recording_target = Some(i);
+                                self.chord_text.clear();
+                                self.recorded_steps.clear();
+                                self.last_step = None;
+                                (self.toasts.info("Key recording..."));
+                            }
                         }
-                    } else {
-                        if ui.button(&self.play).clicked() {
-                            println!("Recording key...");
-                            self.recording_target = Some(RecordingTarget::Play);
-                            (self.toasts.info("Key recording..."));
+                        ui.add_space(15.0);
+                        if ui.button("Clear").clicked() {
+                            bind.keys = None;
+                            changed = true;
                         }
-                    } 
-                    ui.add_space(15.0);
-                    if ui.button("Clear").clicked() {
-                        self.play = "           ".to_owned();
-                        self.settings.play = "           ".to_owned();
-                        let _ = self.settings.save();
-                    }
-                });
-
-                ui.horizontal(|ui| {
-                    ui.label("Pause: ");
-                    
-                    if self.recording_target == Some(RecordingTarget::Pause) {
-                        ui.label("Press a key or key combination...");
-
-                        if let Some(key_combo) = capture_key_input(ctx) {
-                            self.pause = key_combo.clone();
-                            self.settings.pause = key_combo;
-                            let _ = self.settings.save();
-                            self.recording_target = None;
+                        if ui.button("Remove").clicked() {
+                            remove = Some(i);
                         }
+                    });
+                }
 
-                        if ui.button("Cancel").clicked() {
-                            self.recording_target = None;
+                if let Some(i) = remove {
+                    self.settings.binds.remove(i);
+                    self.recording_target = None;
+                    changed = true;
+                }
+
+                if ui.button("Add bind").clicked() {
+                    self.settings.binds.push(Binding::new(KeyEvent::Toggle));
+                    changed = true;
+                }
+
+                if changed {
+                    // URIs are checked before anything is written, and saved in spotify: form
+                    let mut invalid = None;
+                    for bind in &mut self.settings.binds {
+                        if let Err(e) = bind.action.normalize_uri() {
+                            invalid = Some(e);
                         }
-                    } else {
-                        if ui.button(&self.pause).clicked() {
-                            println!("Recording key...");
-                            self.recording_target = Some(RecordingTarget::Pause);
-                            (self.toasts.info("Key recording..."));
+                    }
+                    match invalid {
+                        Some(e) => {
+                            (self.toasts.error(format!("Invalid URI: {}", e)));
+                        }
+                        None => {
+                            let _ = self.settings.save();
                         }
-                    } 
-                    ui.add_space(15.0);
-                    if ui.button("Clear").clicked() {
-                        self.pause = "           ".to_owned();
-                        self.settings.pause = "           ".to_owned();
-                        let _ = self.settings.save();
                     }
-                });
+                }
 
                 ui.add_space(10.0);
                 ui.separator();
                 ui.add_space(10.0);
 
                 ui.horizontal(|ui| {
-                    ui.label("Volume up: ");
-                    
-                    if self.recording_target == Some(RecordingTarget::Volup) {
-                        ui.label("Press a key or key combination...");
-
-                        if let Some(key_combo) = capture_key_input(ctx) {
-                            self.volup = key_combo.clone();
-                            self.settings.volup = key_combo;
-                            let _ = self.settings.save();
-                            self.recording_target = None;
-                        }
-
-                        if ui.button("Cancel").clicked() {
-                            self.recording_target = None;
-                        }
-                    } else {
-                        if ui.button(&self.volup).clicked() {
-                            println!("Recording key...");
-                            self.recording_target = Some(RecordingTarget::Volup);
-                            (self.toasts.info("Key recording..."));
-                        }
-                    } 
-                    ui.add_space(15.0);
-                    if ui.button("Clear").clicked() {
-                        self.volup = "           ".to_owned();
-                        self.settings.volup = "           ".to_owned();
-                        let _ = self.settings.save();
-                    }
-                    
-                    if ui.add(egui::Slider::new(&mut self.volstepup, 0..=100).text("Increase amount")).changed() {
-                        self.settings.volstepup = self.volstepup;
+                    if ui.add(egui::Slider::new(&mut self.settings.sequence_timeout_ms, 200..=3000).text("Sequence timeout (ms)")).changed() {
                         let _ = self.settings.save();
                     }
                 });
 
+                #[cfg(target_os = "linux")]
                 ui.horizontal(|ui| {
-                    ui.label("Volume down: ");
-                    
-                    if self.recording_target == Some(RecordingTarget::Voldown) {
-                        ui.label("Press a key or key combination...");
-
-                        if let Some(key_combo) = capture_key_input(ctx) {
-                            self.voldown = key_combo.clone();
-                            self.settings.voldown = key_combo;
-                            let _ = self.settings.save();
-                            self.recording_target = None;
-                        }
-
-                        if ui.button("Cancel").clicked() {
-                            self.recording_target = None;
-                        }
-                    } else {
-                        if ui.button(&self.voldown).clicked() {
-                            println!("Recording key...");
-                            self.recording_target = Some(RecordingTarget::Voldown);
-                            (self.toasts.info("Key recording..."));
+                    ui.label("Control:");
+                    let mut changed = false;
+                    egui::ComboBox::from_id_salt("player_backend").selected_text(self.settings.player_backend.label()).show_ui(ui, |ui| {
+                        for kind in PlayerKind::ALL {
+                            changed |= ui.selectable_value(&mut self.settings.player_backend, kind, kind.label()).changed();
                         }
-                    } 
-                    ui.add_space(15.0);
-                    if ui.button("Clear").clicked() {
-                        self.voldown = "           ".to_owned();
-                        self.settings.voldown = "           ".to_owned();
-                        let _ = self.settings.save();
-                    }
-
-                    if ui.add(egui::Slider::new(&mut self.volstepdown, 0..=100).text("Decrease amount")).changed() {
-                        self.settings.volstepdown = self.volstepdown;
+                    });
+                    if changed {
+                        // device names differ between backends
+                        self.devices.clear();
                         let _ = self.settings.save();
                     }
-                    
                 });
 
                 ui.horizontal(|ui| {
-                    ui.label("Mute: ");
-                    
-                    if self.recording_target == Some(RecordingTarget::Mute) {
-                        ui.label("Press a key or key combination...");
-
-                        if let Some(key_combo) = capture_key_input(ctx) {
-                            self.mute = key_combo.clone();
-                            self.settings.mute = key_combo;
-                            let _ = self.settings.save();
-                            self.recording_target = None;
-                        }
-
-                        if ui.button("Cancel").clicked() {
-                            self.recording_target = None;
-                        }
-                    } else {
-                        if ui.button(&self.mute).clicked() {
-                            println!("Recording key...");
-                            self.recording_target = Some(RecordingTarget::Mute);
-                            (self.toasts.info("Key recording..."));
-                        }
-                    } 
-                    ui.add_space(15.0);
-                    if ui.button("Clear").clicked() {
-                        self.mute = "           ".to_owned();
-                        self.settings.mute = "           ".to_owned();
+                    ui.label("Preferred device:");
+                    if device_picker(ui, "preferred_device", &mut self.settings.preferred_device, "None", &self.devices) {
                         let _ = self.settings.save();
                     }
-
-                    
+                    let searching = self.device_lookup.is_some();
+                    if ui.add_enabled(self.can_start() && !searching, egui::Button::new("Find devices")).clicked() {
+                        self.device_lookup = Some(spawn_device_lookup(ctx.clone(), self.settings.player_backend, self.init.client().cloned()));
+                    }
+                    if searching {
+                        ui.spinner();
+                    }
                 });
 
                 
@@ -801,92 +1216,3 @@ fn main() -> eframe::Result {
             //ctx.request_repaint_after(Duration::from_millis(33)); // ~30 FPS
         }
     }
-
-fn minimize_window_by_title(title: &str) {
-    let title_w: Vec<u16> = OsStr::new(title).encode_wide().chain(Some(0)).collect();
-    std::thread::spawn(move || {
-        std::thread::sleep(Duration::from_millis(300));
-        unsafe {
-            let hwnd = FindWindowW(PCWSTR(title_w.as_ptr()), PCWSTR::null());
-            if hwnd.0 != 0 {
-                let _ = ShowWindow(hwnd, SW_MINIMIZE);
-            }
-        }
-    });
-}
-
-
-#[cfg(windows)] //HUGE thanks to phoglund on github, this is the only thing that worked.
-pub unsafe fn force_window_wakeup() { //Do I know whats going on here? nope. I think its poking the window
-    use windows::Win32::UI::WindowsAndMessaging::*; // because it lies in a dormant state after calling
-    use windows::Win32::System::Threading::GetCurrentProcessId; //visible(false) on the viewport
-
-    unsafe extern "system" fn enum_proc(hwnd: HWND, _lparam: LPARAM) -> BOOL {
-        let mut pid: u32 = 0;
-
-        unsafe {
-            GetWindowThreadProcessId(hwnd, Some(&mut pid));
-        }
-
-        let current_pid = unsafe { GetCurrentProcessId() };
-
-        if pid == current_pid {
-            if unsafe { IsIconic(hwnd).as_bool() } {
-                unsafe {
-                    ShowWindow(hwnd, SW_RESTORE);
-                }
-            } else {
-                unsafe {
-                    ShowWindow(hwnd, SW_SHOW);
-                }
-            }
-
-            return BOOL(0); // stop enum
-        }
-
-        BOOL(1)
-    }
-
-    unsafe {
-        EnumWindows(Some(enum_proc), LPARAM(0));
-    }
-}
-
-// Minimize the window (stops wgpu rendering) and adjust extended styles so it
-// does not appear on the taskbar. This avoids the higher CPU cost seen when
-// only hiding the window with SW_HIDE while still rendering.
-fn minimize_and_hide_from_taskbar(title: &str) {
-    let wide: Vec<u16> = OsStr::new(title).encode_wide().chain(Some(0)).collect();
-    unsafe {
-        let hwnd = FindWindowW(PCWSTR::null(), PCWSTR(wide.as_ptr()));
-        if hwnd.0 != 0 {
-            // Minimize first – this reduces GPU usage.
-            ShowWindow(hwnd, SW_MINIMIZE);
-            // Modify extended window style: remove APPWINDOW, add TOOLWINDOW so it
-            // is not shown on the taskbar but can have a tray icon.
-            let ex_style = GetWindowLongW(hwnd, GWL_EXSTYLE);
-            let mut new_style = ex_style | WS_EX_TOOLWINDOW.0 as i32;
-            new_style &= !(WS_EX_APPWINDOW.0 as i32);
-            SetWindowLongW(hwnd, GWL_EXSTYLE, new_style);
-        }
-    }
-}
-
-// Restore window: undo style change and bring window back (Show button)
-fn restore_and_show_window(title: &str) {
-    let wide: Vec<u16> = OsStr::new(title).encode_wide().chain(Some(0)).collect();
-    unsafe {
-        let hwnd = FindWindowW(PCWSTR::null(), PCWSTR(wide.as_ptr()));
-        if hwnd.0 != 0 {
-            // Restore extended style so it appears again on taskbar.
-            let ex_style = GetWindowLongW(hwnd, GWL_EXSTYLE);
-            let mut new_style = ex_style | WS_EX_APPWINDOW.0 as i32; // add taskbar presence
-            new_style &= !(WS_EX_TOOLWINDOW.0 as i32); // remove toolwindow flag
-            SetWindowLongW(hwnd, GWL_EXSTYLE, new_style);
-            // Restore window (if minimized) and show.
-            ShowWindow(hwnd, SW_RESTORE);
-            ShowWindow(hwnd, SW_SHOW); // ensure visibility
-            SetForegroundWindow(hwnd);
-        }
-    }
-}
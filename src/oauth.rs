@@ -0,0 +1,502 @@
+// The redirect half of Spotify's authorization code flow. A small HTTP listener on the
+// redirect URI's host and port catches the browser coming back from the authorize
+// page, so nobody has to paste a URL into a terminal the GUI doesn't have.
+use crate::credentials::CredentialError;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+
+// How long to wait for the user to finish logging in
+pub const CALLBACK_TIMEOUT: Duration = Duration::from_secs(300);
+
+// Requests bigger than this aren't a browser following a redirect
+const MAX_REQUEST: usize = 16 * 1024;
+
+// How the app proves who it is to Spotify, picked in settings
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AuthMode {
+    #[default]
+    ClientSecret, // the developer app's id and secret
+    Pkce,         // only the client id, with a one-off code verifier standing in for the secret
+}
+
+impl AuthMode {
+    pub const ALL: [AuthMode; 2] = [AuthMode::ClientSecret, AuthMode::Pkce];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            AuthMode::ClientSecret => "Client id and secret",
+            AuthMode::Pkce => "Client id only (PKCE)",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AuthProgress {
+    WaitingForBrowser,    // the authorize page is open, waiting for the redirect
+    OpenManually(String), // no browser could be opened, so the user has to visit this URL
+    Exchanging,           // got a code, trading it for a token
+}
+
+// Where initializing the Spotify client has got to, with `C` the logged in client
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InitState<C> {
+    NoCredentials,                   // nothing to log in with yet
+    Idle,                            // ready to log in, but no login is running
+    LoadingCredentials,              // reading the stored secret, which may wait on the keyring being unlocked
+    Connecting,                      // trying the stored token first
+    AwaitingBrowser(Option<String>), // the user is logging in, with the link if no browser opened
+    ExchangingToken,
+    Ready(C),
+    Failed(InitError),
+}
+
+impl<C> InitState<C> {
+    pub fn new(has_credentials: bool) -> Self {
+        if has_credentials { InitState::Idle } else { InitState::NoCredentials }
+    }
+
+    pub fn is_running(&self) -> bool {
+        matches!(
+            self,
+            InitState::LoadingCredentials | InitState::Connecting | InitState::AwaitingBrowser(_) | InitState::ExchangingToken
+        )
+    }
+
+    pub fn client(&self) -> Option<&C> {
+        match self {
+            InitState::Ready(client) => Some(client),
+            _ => None,
+        }
+    }
+
+    // A login can be started from here, i.e. Initialize or Retry
+    pub fn can_start(&self) -> bool {
+        matches!(self, InitState::Idle | InitState::Failed(_))
+    }
+
+    // The credentials were edited, so an idle state may have become startable or not
+    pub fn credentials_changed(&mut self, has_credentials: bool) {
+        if matches!(self, InitState::NoCredentials | InitState::Idle) {
+            *self = Self::new(has_credentials);
+        }
+    }
+
+    // The running login has its credentials and moves on to connecting
+    pub fn credentials_loaded(&mut self) {
+        if matches!(self, InitState::LoadingCredentials) {
+            *self = InitState::Connecting;
+        }
+    }
+
+    // A step reported by the running login. Anything arriving after it stopped is stale.
+    pub fn progress(&mut self, step: AuthProgress) {
+        if !self.is_running() {
+            return;
+        }
+        *self = match step {
+            AuthProgress::WaitingForBrowser => InitState::AwaitingBrowser(None),
+            AuthProgress::OpenManually(url) => InitState::AwaitingBrowser(Some(url)),
+            AuthProgress::Exchanging => InitState::ExchangingToken,
+        };
+    }
+
+    pub fn finish(&mut self, result: Result<C, InitError>) {
+        if !self.is_running() {
+            return;
+        }
+        *self = match result {
+            Ok(client) => InitState::Ready(client),
+            Err(reason) => InitState::Failed(reason),
+        };
+    }
+
+    // Stop waiting on the login. False if there wasn't one to stop.
+    pub fn cancel(&mut self) -> bool {
+        if !self.is_running() {
+            return false;
+        }
+        *self = InitState::Idle;
+        true
+    }
+
+    // Shown next to the Initialize/Cancel/Retry buttons
+    pub fn describe(&self) -> String {
+        match self {
+            InitState::NoCredentials => "Enter and save your credentials to log in.".to_owned(),
+            InitState::Idle => "Not logged in.".to_owned(),
+            InitState::LoadingCredentials => "Reading stored credentials. Unlock your keyring if it asks.".to_owned(),
+            InitState::Connecting => "Connecting...".to_owned(),
+            InitState::AwaitingBrowser(None) => "Waiting for you to log in in the browser...".to_owned(),
+            InitState::AwaitingBrowser(Some(_)) => "Couldn't open a browser. Open the login link to continue.".to_owned(),
+            InitState::ExchangingToken => "Finishing login...".to_owned(),
+            InitState::Ready(_) => "Logged in.".to_owned(),
+            InitState::Failed(e) => e.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AuthError {
+    InvalidRedirect(String), // the redirect URI can't be served from here
+    Bind(String),            // couldn't listen on the redirect address
+    Denied(String),          // the authorize page sent back an error instead of a code
+    StateMismatch,           // the redirect wasn't for the request we made
+    TimedOut,
+    Exchange(String), // the code couldn't be traded for a token
+}
+
+impl fmt::Display for AuthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuthError::InvalidRedirect(reason) => write!(f, "invalid redirect URI: {}", reason),
+            AuthError::Bind(e) => write!(f, "couldn't listen for the login redirect: {}", e),
+            AuthError::Denied(error) => write!(f, "authorization was denied: {}", error),
+            AuthError::StateMismatch => write!(f, "the login redirect didn't match this request"),
+            AuthError::TimedOut => write!(f, "timed out waiting for the login to finish"),
+            AuthError::Exchange(e) => write!(f, "couldn't get a token: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for AuthError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CredentialField {
+    ClientId,
+    ClientSecret,
+    RedirectUri,
+}
+
+impl fmt::Display for CredentialField {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CredentialField::ClientId => write!(f, "client id"),
+            CredentialField::ClientSecret => write!(f, "client secret"),
+            CredentialField::RedirectUri => write!(f, "redirect URI"),
+        }
+    }
+}
+
+// Why the Spotify client couldn't be initialized. Each message says what to do about it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InitError {
+    Missing(CredentialField),
+    Whitespace(CredentialField),
+    WrongLength(CredentialField, usize), // the length it has, instead of 32
+    InvalidRedirect(String),             // why the redirect URI can't be served
+    CorruptSettings(String),             // token.json isn't valid
+    Settings(String),                    // token.json couldn't be read or written
+    Store(CredentialError),              // the keyring couldn't be read
+    AuthorizeUrl(String),                // the login link couldn't be built
+    Login(AuthError),                    // the browser half of the login failed
+    TokenExchange(String),               // Spotify turned down the code
+}
+
+impl fmt::Display for InitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InitError::Missing(CredentialField::ClientSecret) => write!(
+                f,
+                "The client secret is empty. Copy it from your app in the Spotify developer dashboard, or log in with the client id only (PKCE)."
+            ),
+            InitError::Missing(field) => {
+                write!(f, "The {} is empty. Copy it from your app in the Spotify developer dashboard and save.", field)
+            }
+            InitError::Whitespace(field) => {
+                write!(f, "The {} contains spaces or line breaks. Paste it again without them and save.", field)
+            }
+            InitError::WrongLength(field, len) => write!(
+                f,
+                "The {} is {} characters long but should be 32. Check it was copied whole and save.",
+                field, len
+            ),
+            InitError::InvalidRedirect(reason) => write!(
+                f,
+                "The redirect URI can't be used: {}. Set the same one on your app in the developer dashboard.",
+                reason
+            ),
+            InitError::CorruptSettings(e) => {
+                write!(f, "token.json is damaged ({}). Enter your credentials again and save to replace it.", e)
+            }
+            InitError::Settings(e) => {
+                write!(f, "Couldn't access token.json ({}). Check SpotifyBinds can write to its folder.", e)
+            }
+            InitError::Store(e) => write!(f, "Couldn't read the saved client secret ({}). Unlock your keyring and retry.", e),
+            InitError::AuthorizeUrl(e) => write!(f, "Couldn't build the Spotify login link ({}). Retry.", e),
+            InitError::Login(AuthError::TimedOut) => {
+                write!(f, "The login timed out. Retry and finish logging in within {} minutes.", CALLBACK_TIMEOUT.as_secs() / 60)
+            }
+            InitError::Login(AuthError::Denied(e)) => {
+                write!(f, "Spotify refused the login ({}). Retry and accept the permissions it asks for.", e)
+            }
+            InitError::Login(AuthError::Bind(e)) => write!(
+                f,
+                "Couldn't listen for the login redirect on {}. Pick a port nothing else uses for the redirect URI, here and in the developer dashboard.",
+                e
+            ),
+            InitError::Login(e) => write!(f, "The login failed: {}. Retry.", e),
+            InitError::TokenExchange(e) => write!(
+                f,
+                "Spotify rejected the login ({}). Check the client id, secret and redirect URI match your app in the developer dashboard.",
+                e
+            ),
+        }
+    }
+}
+
+impl std::error::Error for InitError {}
+
+impl From<AuthError> for InitError {
+    fn from(e: AuthError) -> Self {
+        match e {
+            AuthError::InvalidRedirect(reason) => InitError::InvalidRedirect(reason),
+            AuthError::Exchange(e) => InitError::TokenExchange(e),
+            e => InitError::Login(e),
+        }
+    }
+}
+
+impl From<CredentialError> for InitError {
+    fn from(e: CredentialError) -> Self {
+        InitError::Store(e)
+    }
+}
+
+// Spotify's client ids and secrets are 32 hex characters
+const CREDENTIAL_LEN: usize = 32;
+
+// Check the saved credentials before trying them. PKCE logins don't use the secret,
+// so it isn't checked for them.
+pub fn validate_credentials(mode: AuthMode, client_id: &str, client_secret: &str, redirect_uri: &str) -> Result<(), InitError> {
+    let mut fields = vec![(CredentialField::ClientId, client_id)];
+    if mode == AuthMode::ClientSecret {
+        fields.push((CredentialField::ClientSecret, client_secret));
+    }
+    for (field, value) in fields {
+        if value.is_empty() {
+            return Err(InitError::Missing(field));
+        }
+        if value.contains(char::is_whitespace) {
+            return Err(InitError::Whitespace(field));
+        }
+        if value.chars().count() != CREDENTIAL_LEN {
+            return Err(InitError::WrongLength(field, value.chars().count()));
+        }
+    }
+    if redirect_uri.trim().is_empty() {
+        return Err(InitError::Missing(CredentialField::RedirectUri));
+    }
+    redirect_uri.parse::<RedirectTarget>()?;
+    Ok(())
+}
+
+// Where the redirect URI sends the browser, e.g. "http://127.0.0.1:8888/callback"
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RedirectTarget {
+    pub host: String,
+    pub port: u16,
+    pub path: String,
+}
+
+impl FromStr for RedirectTarget {
+    type Err = AuthError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = |reason: &str| AuthError::InvalidRedirect(reason.to_owned());
+        let rest = s.trim().strip_prefix("http://").ok_or_else(|| invalid("it has to start with http://"))?;
+        let (authority, path) = match rest.find(['/', '?', '#']) {
+            Some(i) => (&rest[..i], &rest[i..]),
+            None => (rest, "/"),
+        };
+        let path = path.split(['?', '#']).next().filter(|p| !p.is_empty()).unwrap_or("/");
+
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) if !port.contains(']') => {
+                (host, port.parse().map_err(|_| invalid("the port isn't a number"))?)
+            }
+            _ => (authority, 80),
+        };
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        if !matches!(host, "127.0.0.1" | "localhost" | "::1") {
+            return Err(invalid("it has to point at this computer, like http://127.0.0.1:8888/callback"));
+        }
+        Ok(Self { host: host.to_owned(), port, path: path.to_owned() })
+    }
+}
+
+// Decode a query string component, where '+' is a space
+pub fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => out.push(b' '),
+            b'%' => {
+                let hex = bytes.get(i + 1..i + 3).and_then(|h| std::str::from_utf8(h).ok());
+                match hex.and_then(|h| u8::from_str_radix(h, 16).ok()) {
+                    Some(byte) => {
+                        out.push(byte);
+                        i += 2;
+                    }
+                    None => out.push(b'%'),
+                }
+            }
+            byte => out.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+fn query_param(query: &str, name: &str) -> Option<String> {
+    query.split('&').filter_map(|pair| pair.split_once('=')).find(|(key, _)| *key == name).map(|(_, value)| percent_decode(value))
+}
+
+// Listens on the redirect address until the browser arrives on the callback path
+pub struct CallbackServer {
+    listener: TcpListener,
+    path: String,
+}
+
+impl CallbackServer {
+    pub async fn bind(redirect_uri: &str) -> Result<Self, AuthError> {
+        let target: RedirectTarget = redirect_uri.parse()?;
+        let listener = TcpListener::bind((target.host.as_str(), target.port))
+            .await
+            .map_err(|e| AuthError::Bind(format!("{}:{}: {}", target.host, target.port, e)))?;
+        Ok(Self { listener, path: target.path })
+    }
+
+    pub fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    // Answer requests until one comes in on the callback path, returning its code.
+    // Each connection is handled on its own so a browser's idle preconnects or a
+    // favicon request can't hold up the real redirect.
+    pub async fn wait_for_code(&self, state: &str) -> Result<String, AuthError> {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        loop {
+            tokio::select! {
+                accepted = self.listener.accept() => {
+                    let Ok((stream, _)) = accepted else { continue };
+                    tokio::spawn(handle_connection(stream, self.path.clone(), state.to_owned(), tx.clone()));
+                }
+                Some(result) = rx.recv() => return result,
+            }
+        }
+    }
+}
+
+async fn read_request_line(stream: &mut TcpStream) -> Option<String> {
+    let mut request = Vec::new();
+    let mut buf = [0; 1024];
+    while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+        let n = stream.read(&mut buf).await.ok()?;
+        if n == 0 || request.len() + n > MAX_REQUEST {
+            return None;
+        }
+        request.extend_from_slice(&buf[..n]);
+    }
+    let request = String::from_utf8_lossy(&request);
+    request.lines().next().map(str::to_owned)
+}
+
+// The message can carry text from the query string, so it's never taken as markup
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+async fn respond(stream: &mut TcpStream, status: &str, message: &str) {
+    let body = format!("<!DOCTYPE html><html><body><h3>SpotifyBinds</h3><p>{}</p></body></html>", escape_html(message));
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+    let _ = stream.write_all(response.as_bytes()).await;
+    let _ = stream.shutdown().await;
+}
+
+async fn handle_connection(
+    mut stream: TcpStream,
+    path: String,
+    state: String,
+    results: mpsc::UnboundedSender<Result<String, AuthError>>,
+) {
+    let Some(line) = read_request_line(&mut stream).await else {
+        return;
+    };
+    // "GET /callback?code=...&state=... HTTP/1.1"
+    let target = line.split_whitespace().nth(1).unwrap_or_default();
+    let (request_path, query) = target.split_once('?').unwrap_or((target, ""));
+    if request_path != path {
+        respond(&mut stream, "404 Not Found", "Nothing here.").await;
+        return;
+    }
+
+    // A stale tab or another site's request mustn't end this login, so anything
+    // without our state is turned away and we keep waiting
+    if query_param(query, "state").as_deref() != Some(state.as_str()) {
+        respond(&mut stream, "400 Bad Request", &format!("Login failed: {}", AuthError::StateMismatch)).await;
+        return;
+    }
+
+    let result = if let Some(error) = query_param(query, "error") {
+        Err(AuthError::Denied(error))
+    } else {
+        query_param(query, "code").ok_or_else(|| AuthError::Denied("no code in the redirect".to_owned()))
+    };
+    match &result {
+        Ok(_) => respond(&mut stream, "200 OK", "Logged in. You can close this tab and go back to SpotifyBinds.").await,
+        Err(e) => respond(&mut stream, "400 Bad Request", &format!("Login failed: {}", e)).await,
+    }
+    let _ = results.send(result);
+}
+
+// Open the authorize page and wait for the browser to come back with a code, then
+// hand the code to `exchange`. Each step is reported to `progress` as it happens.
+pub async fn authorize<Fut>(
+    redirect_uri: &str,
+    authorize_url: &str,
+    state: &str,
+    open_browser: impl FnOnce(&str) -> std::io::Result<()>,
+    exchange: impl FnOnce(String) -> Fut,
+    progress: impl Fn(AuthProgress),
+) -> Result<(), AuthError>
+where
+    Fut: Future<Output = Result<(), String>>,
+{
+    // Listen first so the redirect can't arrive before anything is there to take it
+    let server = CallbackServer::bind(redirect_uri).await?;
+    match open_browser(authorize_url) {
+        Ok(()) => progress(AuthProgress::WaitingForBrowser),
+        Err(_) => progress(AuthProgress::OpenManually(authorize_url.to_owned())),
+    }
+
+    let code = tokio::time::timeout(CALLBACK_TIMEOUT, server.wait_for_code(state))
+        .await
+        .map_err(|_| AuthError::TimedOut)??;
+    progress(AuthProgress::Exchanging);
+    exchange(code).await.map_err(AuthError::Exchange)
+}
@@ -0,0 +1,70 @@
+use eframe::egui::{self, ViewportCommand};
+use std::sync::OnceLock;
+use std::time::Duration;
+use tray_icon::TrayIcon;
+
+// There is no portable way to look a window up by title across X11 and Wayland,
+// so window handling goes through the egui context instead
+static CONTEXT: OnceLock<egui::Context> = OnceLock::new();
+
+// Called every frame; only the first call does anything
+pub fn register_context(ctx: &egui::Context) {
+    let _ = CONTEXT.set(ctx.clone());
+}
+
+fn send(commands: &[ViewportCommand]) {
+    if let Some(ctx) = CONTEXT.get() {
+        for command in commands {
+            ctx.send_viewport_cmd(command.clone());
+        }
+        ctx.request_repaint();
+    }
+}
+
+// The tray icon needs a running gtk main loop, which eframe doesn't provide, so it
+// gets a thread of its own and stays there
+pub fn tray() -> Option<TrayIcon> {
+    std::thread::spawn(|| {
+        if let Err(e) = gtk::init() {
+            println!("Could not start gtk for the tray icon: {}", e);
+            return;
+        }
+        let _tray = crate::icon();
+        gtk::main();
+    });
+    None
+}
+
+// egui only reports Super as Cmd on macOS and Wayland won't say what's held outside a
+// key event, so Super chords have to be typed into the bind field
+pub fn super_held() -> Option<bool> {
+    None
+}
+
+pub fn minimize_window_by_title(_title: &str) {
+    std::thread::spawn(|| {
+        // Wait for the first frame to hand us the context
+        while CONTEXT.get().is_none() {
+            std::thread::sleep(Duration::from_millis(50));
+        }
+        send(&[ViewportCommand::Minimized(true)]);
+    });
+}
+
+pub unsafe fn force_window_wakeup() {
+    send(&[ViewportCommand::Visible(true), ViewportCommand::Focus]);
+}
+
+// Minimizing stops rendering. Window managers don't agree on how to drop a window
+// from the taskbar, so unlike Windows it stays listed there.
+pub fn minimize_and_hide_from_taskbar(_title: &str) {
+    send(&[ViewportCommand::Minimized(true)]);
+}
+
+pub fn restore_and_show_window(_title: &str) {
+    send(&[
+        ViewportCommand::Visible(true),
+        ViewportCommand::Minimized(false),
+        ViewportCommand::Focus,
+    ]);
+}
@@ -0,0 +1,12 @@
+// Window and tray handling that differs between operating systems. Each
+// platform file provides the same set of functions.
+
+#[cfg(windows)]
+mod windows;
+#[cfg(windows)]
+pub use self::windows::*;
+
+#[cfg(target_os = "linux")]
+mod linux;
+#[cfg(target_os = "linux")]
+pub use self::linux::*;
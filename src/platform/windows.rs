@@ -0,0 +1,112 @@
+use std::ffi::OsStr;
+use std::os::windows::ffi::OsStrExt;
+use std::time::Duration;
+use tray_icon::TrayIcon;
+use windows::core::PCWSTR;
+use windows::Win32::Foundation::{BOOL, HWND, LPARAM};
+use windows::Win32::UI::WindowsAndMessaging::*;
+
+// The tray icon lives on the UI thread, which already pumps Win32 messages for it
+pub fn tray() -> Option<TrayIcon> {
+    Some(crate::icon())
+}
+
+// egui only reports Super as Cmd on macOS, so the recorder asks Windows directly
+pub fn super_held() -> Option<bool> {
+    use windows::Win32::UI::Input::KeyboardAndMouse::{GetAsyncKeyState, VIRTUAL_KEY, VK_LWIN, VK_RWIN};
+    // the top bit is set while the key is down
+    let down = |key: VIRTUAL_KEY| unsafe { GetAsyncKeyState(key.0 as i32) } < 0;
+    Some(down(VK_LWIN) || down(VK_RWIN))
+}
+
+// Nothing to do here: windows are found by title
+pub fn register_context(_ctx: &eframe::egui::Context) {}
+
+pub fn minimize_window_by_title(title: &str) {
+    let title_w: Vec<u16> = OsStr::new(title).encode_wide().chain(Some(0)).collect();
+    std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_millis(300));
+        unsafe {
+            let hwnd = FindWindowW(PCWSTR(title_w.as_ptr()), PCWSTR::null());
+            if hwnd.0 != 0 {
+                let _ = ShowWindow(hwnd, SW_MINIMIZE);
+            }
+        }
+    });
+}
+
+
+//HUGE thanks to phoglund on github, this is the only thing that worked.
+pub unsafe fn force_window_wakeup() { //Do I know whats going on here? nope. I think its poking the window
+    // because it lies in a dormant state after calling visible(false) on the viewport
+    use windows::Win32::System::Threading::GetCurrentProcessId;
+
+    unsafe extern "system" fn enum_proc(hwnd: HWND, _lparam: LPARAM) -> BOOL {
+        let mut pid: u32 = 0;
+
+        unsafe {
+            GetWindowThreadProcessId(hwnd, Some(&mut pid));
+        }
+
+        let current_pid = unsafe { GetCurrentProcessId() };
+
+        if pid == current_pid {
+            if unsafe { IsIconic(hwnd).as_bool() } {
+                unsafe {
+                    ShowWindow(hwnd, SW_RESTORE);
+                }
+            } else {
+                unsafe {
+                    ShowWindow(hwnd, SW_SHOW);
+                }
+            }
+
+            return BOOL(0); // stop enum
+        }
+
+        BOOL(1)
+    }
+
+    unsafe {
+        EnumWindows(Some(enum_proc), LPARAM(0));
+    }
+}
+
+// Minimize the window (stops wgpu rendering) and adjust extended styles so it
+// does not appear on the taskbar. This avoids the higher CPU cost seen when
+// only hiding the window with SW_HIDE while still rendering.
+pub fn minimize_and_hide_from_taskbar(title: &str) {
+    let wide: Vec<u16> = OsStr::new(title).encode_wide().chain(Some(0)).collect();
+    unsafe {
+        let hwnd = FindWindowW(PCWSTR::null(), PCWSTR(wide.as_ptr()));
+        if hwnd.0 != 0 {
+            // Minimize first – this reduces GPU usage.
+            ShowWindow(hwnd, SW_MINIMIZE);
+            // Modify extended window style: remove APPWINDOW, add TOOLWINDOW so it
+            // is not shown on the taskbar but can have a tray icon.
+            let ex_style = GetWindowLongW(hwnd, GWL_EXSTYLE);
+            let mut new_style = ex_style | WS_EX_TOOLWINDOW.0 as i32;
+            new_style &= !(WS_EX_APPWINDOW.0 as i32);
+            SetWindowLongW(hwnd, GWL_EXSTYLE, new_style);
+        }
+    }
+}
+
+// Restore window: undo style change and bring window back (Show button)
+pub fn restore_and_show_window(title: &str) {
+    let wide: Vec<u16> = OsStr::new(title).encode_wide().chain(Some(0)).collect();
+    unsafe {
+        let hwnd = FindWindowW(PCWSTR::null(), PCWSTR(wide.as_ptr()));
+        if hwnd.0 != 0 {
+            // Restore extended style so it appears again on taskbar.
+            let ex_style = GetWindowLongW(hwnd, GWL_EXSTYLE);
+            let mut new_style = ex_style | WS_EX_APPWINDOW.0 as i32; // add taskbar presence
+            new_style &= !(WS_EX_TOOLWINDOW.0 as i32); // remove toolwindow flag
+            SetWindowLongW(hwnd, GWL_EXSTYLE, new_style);
+            // Restore window (if minimized) and show.
+            ShowWindow(hwnd, SW_RESTORE);
+            ShowWindow(hwnd, SW_SHOW); // ensure visibility
+            SetForegroundWindow(hwnd);
+        }
+    }
+}
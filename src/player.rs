@@ -0,0 +1,394 @@
+// Player control written against a `PlayerBackend`, so the logic on top (fallback
+// devices, volume clamping, mute memory) doesn't depend on the Spotify Web API and
+// can be tested against the in-memory `fake::FakePlayer`.
+use crate::hotkeyreg::{PlayTarget, PlaylistTarget, SpotifyItem, SpotifyUri, UriParseError};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::time::Duration;
+
+pub mod fake;
+#[cfg(target_os = "linux")]
+pub mod mpris;
+
+// Which backend the bind worker drives, picked in settings
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PlayerKind {
+    #[default]
+    WebApi, // the Spotify Web API, needs Premium and developer credentials
+    Mpris,  // local players over D-Bus, Linux only
+}
+
+impl PlayerKind {
+    pub const ALL: [PlayerKind; 2] = [PlayerKind::WebApi, PlayerKind::Mpris];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            PlayerKind::WebApi => "Spotify Web API",
+            PlayerKind::Mpris => "MPRIS (local players)",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Device {
+    pub id: Option<String>,
+    pub name: String,
+    pub is_active: bool,
+    pub is_restricted: bool,         // can't be controlled remotely
+    pub volume_percent: Option<u32>, // None for devices that don't report it
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlayingItem {
+    pub uri: Option<SpotifyUri>, // None for local files
+    pub name: String,
+    pub duration: Duration,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Repeat {
+    #[default]
+    Off,
+    Context,
+    Track,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Playback {
+    pub device: Device,
+    pub is_playing: bool,
+    pub progress: Option<Duration>,
+    pub item: Option<PlayingItem>,
+    pub shuffle: bool,
+    pub repeat: Repeat,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BackendError {
+    Api(String),               // the player refused or failed the request
+    Unsupported(&'static str), // the backend can't do this at all
+    NothingPlaying,
+    InvalidUri(UriParseError),
+}
+
+impl fmt::Display for BackendError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BackendError::Api(message) => write!(f, "{}", message),
+            BackendError::Unsupported(what) => write!(f, "{}", what),
+            BackendError::NothingPlaying => write!(f, "nothing is playing"),
+            BackendError::InvalidUri(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for BackendError {}
+
+pub type BackendResult<T> = Result<T, BackendError>;
+
+// The operations the bind worker needs from a player. Device ids are the backend's
+// own, and `None` means whichever device is active.
+pub trait PlayerBackend {
+    fn playback(&self) -> impl Future<Output = BackendResult<Option<Playback>>> + Send;
+    fn devices(&self) -> impl Future<Output = BackendResult<Vec<Device>>> + Send;
+    fn resume(&self, device_id: Option<&str>) -> impl Future<Output = BackendResult<()>> + Send;
+    fn pause(&self, device_id: Option<&str>) -> impl Future<Output = BackendResult<()>> + Send;
+    fn next(&self, device_id: Option<&str>) -> impl Future<Output = BackendResult<()>> + Send;
+    fn previous(&self, device_id: Option<&str>) -> impl Future<Output = BackendResult<()>> + Send;
+    fn set_volume(&self, percent: u32, device_id: Option<&str>) -> impl Future<Output = BackendResult<()>> + Send;
+    fn seek(&self, position: Duration, device_id: Option<&str>) -> impl Future<Output = BackendResult<()>> + Send;
+    fn set_shuffle(&self, on: bool, device_id: Option<&str>) -> impl Future<Output = BackendResult<()>> + Send;
+    fn set_repeat(&self, repeat: Repeat, device_id: Option<&str>) -> impl Future<Output = BackendResult<()>> + Send;
+    fn transfer(&self, device_id: &str) -> impl Future<Output = BackendResult<()>> + Send;
+    // Start an item playing, from `offset` within an album or playlist
+    fn start(&self, uri: &SpotifyUri, offset: u32, device_id: Option<&str>) -> impl Future<Output = BackendResult<()>> + Send;
+    fn is_saved(&self, track: &SpotifyUri) -> impl Future<Output = BackendResult<bool>> + Send;
+    fn set_saved(&self, track: &SpotifyUri, saved: bool) -> impl Future<Output = BackendResult<()>> + Send;
+    fn playlist_contains(&self, playlist: &SpotifyUri, item: &SpotifyUri) -> impl Future<Output = BackendResult<bool>> + Send;
+    fn playlist_add(&self, playlist: &SpotifyUri, item: &SpotifyUri) -> impl Future<Output = BackendResult<()>> + Send;
+}
+
+// What unmuting goes back to when the volume was already 0 before muting
+pub const UNMUTE_FALLBACK_VOLUME: u32 = 50;
+
+// Volume each device had before it was muted, by device name. The worker keeps one
+// for its lifetime and it's persisted in the settings file.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VolumeState {
+    pub premute: HashMap<String, u32>,
+}
+
+pub struct Player<B> {
+    pub backend: B,
+    pub preferred_device: Option<String>, // device name to start on when nothing is active
+}
+
+impl<B: PlayerBackend + Sync> Player<B> {
+    pub async fn pause(&self, device_id: Option<&str>) -> BackendResult<()> {
+        if let Err(e) = self.backend.pause(device_id).await {
+            println!("Already paused! : {}", e);
+        }
+        Ok(())
+    }
+
+    pub async fn play(&self, device_id: Option<&str>) -> BackendResult<()> {
+        // Try to resume existing playback first
+        if self.backend.resume(device_id).await.is_ok() {
+            return Ok(());
+        }
+
+        // If that fails, try to resume on an available device
+        match self.get_available_device().await {
+            Ok(Some(dev_id)) => {
+                let _ = self.backend.resume(Some(&dev_id)).await;
+            }
+            _ => println!("Could not start playback: no active device"),
+        }
+        Ok(())
+    }
+
+    pub async fn toggle_playback(&self, device_id: Option<&str>) -> BackendResult<()> {
+        match self.backend.playback().await? {
+            Some(playback) if playback.is_playing => self.backend.pause(device_id).await?,
+            Some(_) => self.backend.resume(device_id).await?,
+            // No active playback - try to start it on an available device
+            None => match self.get_available_device().await {
+                Ok(Some(dev_id)) => {
+                    let _ = self.backend.resume(Some(&dev_id)).await;
+                }
+                _ => println!("Could not start playback: no active device"),
+            },
+        }
+        Ok(())
+    }
+
+    pub async fn next_track(&self, device_id: Option<&str>) -> BackendResult<()> {
+        self.backend.next(device_id).await
+    }
+
+    pub async fn previous_track(&self, device_id: Option<&str>) -> BackendResult<()> {
+        self.backend.previous(device_id).await
+    }
+
+    async fn device(&self, device_id: Option<&str>) -> BackendResult<Option<Device>> {
+        Ok(match device_id {
+            Some(id) => self.backend.devices().await?.into_iter().find(|d| d.id.as_deref() == Some(id)),
+            None => self.backend.playback().await?.map(|p| p.device),
+        })
+    }
+
+    // Apply a relative change (possibly several merged key presses) as one absolute volume call
+    pub async fn change_volume(&self, device_id: Option<&str>, delta: i32) -> BackendResult<()> {
+        let Some(current) = self.device(device_id).await?.and_then(|d| d.volume_percent) else {
+            println!("Could not get current volume");
+            return Ok(());
+        };
+
+        let new_vol = (current as i32).saturating_add(delta).clamp(0, 100);
+        self.backend.set_volume(new_vol as u32, device_id).await
+    }
+
+    pub async fn set_volume(&self, device_id: Option<&str>, percent: u32) -> BackendResult<()> {
+        self.backend.set_volume(percent.min(100), device_id).await
+    }
+
+    // Mute the device, or put back the volume it had before if it's muted. Returns
+    // whether `state` changed and should be saved.
+    pub async fn toggle_mute(&self, device_id: Option<&str>, state: &mut VolumeState) -> BackendResult<bool> {
+        let Some(device) = self.device(device_id).await? else {
+            println!("No device to mute");
+            return Ok(false);
+        };
+        let Some(volume) = device.volume_percent else {
+            println!("{} doesn't report its volume, so it can't be muted", device.name);
+            return Ok(false);
+        };
+        let target = device.id.as_deref().or(device_id);
+
+        if volume > 0 {
+            self.backend.set_volume(0, target).await?;
+            state.premute.insert(device.name, volume);
+        } else {
+            let restore = state.premute.remove(&device.name).unwrap_or(UNMUTE_FALLBACK_VOLUME);
+            self.backend.set_volume(restore.min(100), target).await?;
+        }
+        Ok(true)
+    }
+
+    // Move the playhead by `offset_ms` (negative goes back), staying inside the track
+    pub async fn seek_relative(&self, device_id: Option<&str>, offset_ms: i64) -> BackendResult<()> {
+        let Some(playback) = self.backend.playback().await? else {
+            println!("Nothing is playing");
+            return Ok(());
+        };
+        let Some(item) = &playback.item else {
+            return Ok(());
+        };
+
+        let progress = playback.progress.unwrap_or_default().as_millis() as i64;
+        let position = progress.saturating_add(offset_ms).clamp(0, item.duration.as_millis() as i64);
+        self.backend.seek(Duration::from_millis(position as u64), device_id).await
+    }
+
+    pub async fn restart_track(&self, device_id: Option<&str>) -> BackendResult<()> {
+        self.backend.seek(Duration::ZERO, device_id).await
+    }
+
+    pub async fn cycle_shuffle(&self, device_id: Option<&str>) -> BackendResult<()> {
+        if let Some(playback) = self.backend.playback().await? {
+            self.backend.set_shuffle(!playback.shuffle, device_id).await?;
+        }
+        Ok(())
+    }
+
+    // Off, then repeat the context (album or playlist), then repeat the track
+    pub async fn cycle_repeat(&self, device_id: Option<&str>) -> BackendResult<()> {
+        if let Some(playback) = self.backend.playback().await? {
+            let next = match playback.repeat {
+                Repeat::Off => Repeat::Context,
+                Repeat::Context => Repeat::Track,
+                Repeat::Track => Repeat::Off,
+            };
+            self.backend.set_repeat(next, device_id).await?;
+        }
+        Ok(())
+    }
+
+    // Save the current track to Liked Songs, or remove it if it's already there
+    pub async fn toggle_like(&self) -> BackendResult<()> {
+        let Some(item) = self.backend.playback().await?.and_then(|p| p.item) else {
+            println!("No track playing to like");
+            return Ok(());
+        };
+        let Some(track) = item.uri.filter(|uri| uri.item == SpotifyItem::Track) else {
+            println!("Only Spotify tracks can be liked");
+            return Ok(());
+        };
+
+        if self.backend.is_saved(&track).await? {
+            self.backend.set_saved(&track, false).await?;
+            println!("Removed {} from Liked Songs", item.name);
+        } else {
+            self.backend.set_saved(&track, true).await?;
+            println!("Added {} to Liked Songs", item.name);
+        }
+        Ok(())
+    }
+
+    // Start the item a play bind points at, shuffled and from an offset if it asks for them
+    pub async fn play_target(&self, device_id: Option<&str>, target: &PlayTarget) -> BackendResult<()> {
+        let uri = match target.uri.parse::<SpotifyUri>() {
+            Ok(uri) => uri,
+            Err(e) => {
+                println!("Can't play '{}': {}", target.uri, e);
+                return Ok(());
+            }
+        };
+
+        // Shuffle needs an active device, so it's set again once playback has started.
+        // Targets that don't ask for shuffle leave the user's setting alone.
+        let shuffled = !target.shuffle || self.backend.set_shuffle(true, device_id).await.is_ok();
+        let mut device_id = device_id.map(str::to_owned);
+        if let Err(e) = self.backend.start(&uri, target.offset, device_id.as_deref()).await {
+            // Nothing active to play on, so pick an available device
+            let Ok(Some(dev_id)) = self.get_available_device().await else {
+                println!("Could not start playback: {}", e);
+                return Ok(());
+            };
+            self.backend.start(&uri, target.offset, Some(&dev_id)).await?;
+            device_id = Some(dev_id);
+        }
+        if !shuffled {
+            self.backend.set_shuffle(true, device_id.as_deref()).await?;
+        }
+        Ok(())
+    }
+
+    // Add the playing track or episode to a playlist, returning a message for the UI
+    pub async fn add_to_playlist(&self, target: &PlaylistTarget) -> BackendResult<String> {
+        let playlist: SpotifyUri = target.uri.parse().map_err(BackendError::InvalidUri)?;
+        if playlist.item != SpotifyItem::Playlist {
+            return Err(BackendError::InvalidUri(UriParseError::NotPlaylist(target.uri.clone())));
+        }
+        let Some(item) = self.backend.playback().await?.and_then(|p| p.item) else {
+            return Err(BackendError::NothingPlaying);
+        };
+        let Some(uri) = &item.uri else {
+            return Err(BackendError::Unsupported("local tracks can't be added to playlists"));
+        };
+
+        if !target.allow_duplicates && self.backend.playlist_contains(&playlist, uri).await? {
+            return Ok(format!("{} is already in the playlist", item.name));
+        }
+        self.backend.playlist_add(&playlist, uri).await?;
+        Ok(format!("Added {} to the playlist", item.name))
+    }
+
+    // The preferred device if it's online, otherwise the first one listed
+    pub async fn get_available_device(&self) -> BackendResult<Option<String>> {
+        let devices = self.backend.devices().await?;
+        let preferred = self
+            .preferred_device
+            .as_deref()
+            .and_then(|name| devices.iter().find(|d| d.name.eq_ignore_ascii_case(name)));
+        Ok(preferred.or(devices.first()).and_then(|d| d.id.clone()))
+    }
+
+    // Devices are chosen by name in the UI since ids can change between sessions
+    pub async fn device_id(&self, name: &str) -> BackendResult<Option<String>> {
+        let devices = self.backend.devices().await?;
+        Ok(devices.into_iter().find(|d| d.name.eq_ignore_ascii_case(name)).and_then(|d| d.id))
+    }
+
+    pub async fn device_names(&self) -> BackendResult<Vec<String>> {
+        let devices = self.backend.devices().await?;
+        Ok(devices.into_iter().map(|d| d.name).collect())
+    }
+
+    pub async fn transfer_to(&self, name: &str) -> BackendResult<()> {
+        match self.device_id(name).await? {
+            Some(id) => self.backend.transfer(&id).await?,
+            None => println!("Device '{}' is not available", name),
+        }
+        Ok(())
+    }
+
+    // Move playback to the device after the active one, in the order they're listed
+    pub async fn cycle_device(&self) -> BackendResult<()> {
+        let devices: Vec<Device> = self.backend.devices().await?.into_iter().filter(|d| !d.is_restricted).collect();
+        let next = match devices.iter().position(|d| d.is_active) {
+            Some(i) => devices.iter().cycle().skip(i + 1).take(devices.len() - 1).find(|d| d.id.is_some()),
+            None => devices.iter().find(|d| d.id.is_some()),
+        };
+        if let Some(id) = next.and_then(|d| d.id.as_deref()) {
+            self.backend.transfer(id).await?;
+        }
+        Ok(())
+    }
+}
+
+// Device ids by name for the bind worker, so a bind naming a device doesn't cost a
+// device list request on every press. One lookup fills in every device. Ids change
+// when a device reconnects, so an entry is dropped once a command sent to it fails.
+#[derive(Debug, Default)]
+pub struct DeviceIds {
+    ids: HashMap<String, String>, // lowercased name to id
+}
+
+impl DeviceIds {
+    pub async fn get<B: PlayerBackend>(&mut self, player: &Player<B>, name: &str) -> BackendResult<Option<String>> {
+        let key = name.to_lowercase();
+        if let Some(id) = self.ids.get(&key) {
+            return Ok(Some(id.clone()));
+        }
+        let devices = player.backend.devices().await?;
+        self.ids = devices.into_iter().filter_map(|d| Some((d.name.to_lowercase(), d.id?))).collect();
+        Ok(self.ids.get(&key).cloned())
+    }
+
+    pub fn forget(&mut self, name: &str) {
+        self.ids.remove(&name.to_lowercase());
+    }
+}
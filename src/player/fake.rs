@@ -0,0 +1,225 @@
+// An in-memory player for tests. Every backend call edits `FakeState` the way the
+// Web API would, including its refusals, so no network is needed.
+use super::{BackendError, BackendResult, Device, Playback, PlayerBackend, PlayingItem, Repeat};
+use crate::hotkeyreg::SpotifyUri;
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FakeState {
+    pub devices: Vec<Device>,
+    pub is_playing: bool,
+    pub progress: Duration,
+    pub item: Option<PlayingItem>,
+    pub shuffle: bool,
+    pub repeat: Repeat,
+    pub saved: HashSet<String>,                  // liked track URIs
+    pub playlists: HashMap<String, Vec<String>>, // playlist URI to item URIs
+    pub started: Option<(String, u32)>,          // last URI started and its offset
+}
+
+// A device the fake can play on, with id "id-<name>"
+pub fn device(name: &str, volume_percent: Option<u32>) -> Device {
+    Device {
+        id: Some(format!("id-{}", name)),
+        name: name.to_owned(),
+        is_active: false,
+        is_restricted: false,
+        volume_percent,
+    }
+}
+
+#[derive(Default)]
+pub struct FakePlayer {
+    state: Mutex<FakeState>,
+}
+
+impl FakePlayer {
+    pub fn new(state: FakeState) -> Self {
+        Self { state: Mutex::new(state) }
+    }
+
+    pub fn state(&self) -> FakeState {
+        self.state.lock().unwrap().clone()
+    }
+
+    // Change the state behind the player's back, e.g. a device reconnecting
+    pub fn edit(&self, f: impl FnOnce(&mut FakeState)) {
+        f(&mut self.state.lock().unwrap())
+    }
+
+    fn with<T>(&self, f: impl FnOnce(&mut FakeState) -> BackendResult<T>) -> BackendResult<T> {
+        f(&mut self.state.lock().unwrap())
+    }
+}
+
+impl FakeState {
+    pub fn active_device(&self) -> Option<&Device> {
+        self.devices.iter().find(|d| d.is_active)
+    }
+
+    pub fn device_named(&self, name: &str) -> Option<&Device> {
+        self.devices.iter().find(|d| d.name == name)
+    }
+
+    // The device a command goes to. Naming one makes it the active device, like the
+    // Web API does; otherwise there has to be an active device already.
+    fn target(&mut self, device_id: Option<&str>) -> BackendResult<&mut Device> {
+        let index = match device_id {
+            Some(id) => {
+                let index = self.devices.iter().position(|d| d.id.as_deref() == Some(id));
+                let index = index.ok_or_else(|| BackendError::Api(format!("device {} not found", id)))?;
+                self.activate(index);
+                index
+            }
+            None => self
+                .devices
+                .iter()
+                .position(|d| d.is_active)
+                .ok_or_else(|| BackendError::Api("no active device".to_owned()))?,
+        };
+        Ok(&mut self.devices[index])
+    }
+
+    fn activate(&mut self, index: usize) {
+        for (i, device) in self.devices.iter_mut().enumerate() {
+            device.is_active = i == index;
+        }
+    }
+}
+
+impl PlayerBackend for FakePlayer {
+    async fn playback(&self) -> BackendResult<Option<Playback>> {
+        self.with(|s| {
+            Ok(s.active_device().cloned().map(|device| Playback {
+                device,
+                is_playing: s.is_playing,
+                progress: Some(s.progress),
+                item: s.item.clone(),
+                shuffle: s.shuffle,
+                repeat: s.repeat,
+            }))
+        })
+    }
+
+    async fn devices(&self) -> BackendResult<Vec<Device>> {
+        self.with(|s| Ok(s.devices.clone()))
+    }
+
+    async fn resume(&self, device_id: Option<&str>) -> BackendResult<()> {
+        self.with(|s| {
+            s.target(device_id)?;
+            s.is_playing = true;
+            Ok(())
+        })
+    }
+
+    async fn pause(&self, device_id: Option<&str>) -> BackendResult<()> {
+        self.with(|s| {
+            s.target(device_id)?;
+            if !s.is_playing {
+                return Err(BackendError::Api("already paused".to_owned()));
+            }
+            s.is_playing = false;
+            Ok(())
+        })
+    }
+
+    async fn next(&self, device_id: Option<&str>) -> BackendResult<()> {
+        self.with(|s| {
+            s.target(device_id)?;
+            s.progress = Duration::ZERO;
+            Ok(())
+        })
+    }
+
+    async fn previous(&self, device_id: Option<&str>) -> BackendResult<()> {
+        self.next(device_id).await
+    }
+
+    async fn set_volume(&self, percent: u32, device_id: Option<&str>) -> BackendResult<()> {
+        self.with(|s| {
+            let device = s.target(device_id)?;
+            if percent > 100 {
+                return Err(BackendError::Api(format!("volume {} is out of range", percent)));
+            }
+            if device.volume_percent.is_none() {
+                return Err(BackendError::Api(format!("{} can't change volume", device.name)));
+            }
+            device.volume_percent = Some(percent);
+            Ok(())
+        })
+    }
+
+    async fn seek(&self, position: Duration, device_id: Option<&str>) -> BackendResult<()> {
+        self.with(|s| {
+            s.target(device_id)?;
+            let length = s.item.as_ref().map(|i| i.duration).unwrap_or_default();
+            if position > length {
+                return Err(BackendError::Api("position is past the end of the track".to_owned()));
+            }
+            s.progress = position;
+            Ok(())
+        })
+    }
+
+    async fn set_shuffle(&self, on: bool, device_id: Option<&str>) -> BackendResult<()> {
+        self.with(|s| {
+            s.target(device_id)?;
+            s.shuffle = on;
+            Ok(())
+        })
+    }
+
+    async fn set_repeat(&self, repeat: Repeat, device_id: Option<&str>) -> BackendResult<()> {
+        self.with(|s| {
+            s.target(device_id)?;
+            s.repeat = repeat;
+            Ok(())
+        })
+    }
+
+    async fn transfer(&self, device_id: &str) -> BackendResult<()> {
+        self.with(|s| s.target(Some(device_id)).map(|_| ()))
+    }
+
+    async fn start(&self, uri: &SpotifyUri, offset: u32, device_id: Option<&str>) -> BackendResult<()> {
+        self.with(|s| {
+            s.target(device_id)?;
+            s.started = Some((uri.to_string(), offset));
+            s.is_playing = true;
+            Ok(())
+        })
+    }
+
+    async fn is_saved(&self, track: &SpotifyUri) -> BackendResult<bool> {
+        self.with(|s| Ok(s.saved.contains(&track.to_string())))
+    }
+
+    async fn set_saved(&self, track: &SpotifyUri, saved: bool) -> BackendResult<()> {
+        self.with(|s| {
+            if saved {
+                s.saved.insert(track.to_string());
+            } else {
+                s.saved.remove(&track.to_string());
+            }
+            Ok(())
+        })
+    }
+
+    async fn playlist_contains(&self, playlist: &SpotifyUri, item: &SpotifyUri) -> BackendResult<bool> {
+        self.with(|s| {
+            let items = s.playlists.get(&playlist.to_string()).ok_or_else(|| BackendError::Api("playlist not found".to_owned()))?;
+            Ok(items.contains(&item.to_string()))
+        })
+    }
+
+    async fn playlist_add(&self, playlist: &SpotifyUri, item: &SpotifyUri) -> BackendResult<()> {
+        self.with(|s| {
+            let items = s.playlists.get_mut(&playlist.to_string()).ok_or_else(|| BackendError::Api("playlist not found".to_owned()))?;
+            items.push(item.to_string());
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,291 @@
+// Local players over MPRIS (org.mpris.MediaPlayer2) on the session bus. This drives the
+// Spotify desktop app without Premium or developer credentials, and works for any other
+// player too. Each player on the bus is a device, with its bus name as the device id.
+use super::{BackendError, BackendResult, Device, Playback, PlayerBackend, PlayingItem, Repeat};
+use crate::hotkeyreg::SpotifyUri;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+use zbus::proxy::CacheProperties;
+use zbus::zvariant::{ObjectPath, OwnedValue};
+use zbus::Connection;
+
+pub const MPRIS_PREFIX: &str = "org.mpris.MediaPlayer2.";
+pub const MPRIS_PATH: &str = "/org/mpris/MediaPlayer2";
+
+#[zbus::proxy(interface = "org.mpris.MediaPlayer2", default_path = "/org/mpris/MediaPlayer2", gen_blocking = false)]
+trait MediaPlayer2 {
+    #[zbus(property)]
+    fn identity(&self) -> zbus::Result<String>;
+}
+
+#[zbus::proxy(interface = "org.mpris.MediaPlayer2.Player", default_path = "/org/mpris/MediaPlayer2", gen_blocking = false)]
+trait Player {
+    fn play(&self) -> zbus::Result<()>;
+    fn pause(&self) -> zbus::Result<()>;
+    fn next(&self) -> zbus::Result<()>;
+    fn previous(&self) -> zbus::Result<()>;
+    fn seek(&self, offset: i64) -> zbus::Result<()>;
+    fn set_position(&self, track_id: &ObjectPath<'_>, position: i64) -> zbus::Result<()>;
+    fn open_uri(&self, uri: &str) -> zbus::Result<()>;
+
+    #[zbus(property)]
+    fn playback_status(&self) -> zbus::Result<String>;
+    #[zbus(property)]
+    fn loop_status(&self) -> zbus::Result<String>;
+    #[zbus(property)]
+    fn set_loop_status(&self, value: &str) -> zbus::Result<()>;
+    #[zbus(property)]
+    fn shuffle(&self) -> zbus::Result<bool>;
+    #[zbus(property)]
+    fn set_shuffle(&self, value: bool) -> zbus::Result<()>;
+    #[zbus(property)]
+    fn metadata(&self) -> zbus::Result<HashMap<String, OwnedValue>>;
+    #[zbus(property)]
+    fn volume(&self) -> zbus::Result<f64>;
+    #[zbus(property)]
+    fn set_volume(&self, value: f64) -> zbus::Result<()>;
+    #[zbus(property)]
+    fn position(&self) -> zbus::Result<i64>;
+    #[zbus(property)]
+    fn can_control(&self) -> zbus::Result<bool>;
+}
+
+fn api_error(e: zbus::Error) -> BackendError {
+    BackendError::Api(e.to_string())
+}
+
+fn micros(duration: Duration) -> i64 {
+    duration.as_micros().min(i64::MAX as u128) as i64
+}
+
+fn from_micros(micros: i64) -> Duration {
+    Duration::from_micros(micros.max(0) as u64)
+}
+
+// Spotify sends its track ids as "/com/spotify/track/<id>" and other players may send
+// a URL or URI, so all of them are tried
+fn metadata_uri(metadata: &HashMap<String, OwnedValue>) -> Option<SpotifyUri> {
+    let url = metadata.get("xesam:url").and_then(|v| v.downcast_ref::<&str>().ok());
+    let track_id = metadata.get("mpris:trackid").and_then(|v| {
+        v.downcast_ref::<ObjectPath>().map(|p| p.as_str().to_owned()).or_else(|_| v.downcast_ref::<&str>().map(str::to_owned)).ok()
+    });
+    let from_path = track_id
+        .as_deref()
+        .and_then(|id| id.strip_prefix("/com/spotify/"))
+        .map(|rest| format!("spotify:{}", rest.replace('/', ":")));
+
+    [url.map(str::to_owned), track_id, from_path].into_iter().flatten().find_map(|s| s.parse().ok())
+}
+
+fn playing_item(metadata: &HashMap<String, OwnedValue>) -> Option<PlayingItem> {
+    if metadata.is_empty() {
+        return None;
+    }
+    let name = metadata.get("xesam:title").and_then(|v| v.downcast_ref::<&str>().ok()).unwrap_or_default();
+    // the spec says x but some players send t
+    let length = metadata
+        .get("mpris:length")
+        .and_then(|v| v.downcast_ref::<i64>().or_else(|_| v.downcast_ref::<u64>().map(|n| n as i64)).ok());
+    Some(PlayingItem {
+        uri: metadata_uri(metadata),
+        name: name.to_owned(),
+        duration: from_micros(length.unwrap_or_default()),
+    })
+}
+
+fn repeat_from(status: &str) -> Repeat {
+    match status {
+        "Track" => Repeat::Track,
+        "Playlist" => Repeat::Context,
+        _ => Repeat::Off,
+    }
+}
+
+pub struct MprisPlayer {
+    connection: Connection,
+    // Player chosen by a transfer. MPRIS can't move audio between players, so
+    // transferring switches which one unassigned binds control.
+    target: Mutex<Option<String>>,
+}
+
+impl MprisPlayer {
+    pub async fn connect() -> zbus::Result<Self> {
+        Ok(Self::with_connection(Connection::session().await?))
+    }
+
+    pub fn with_connection(connection: Connection) -> Self {
+        Self { connection, target: Mutex::new(None) }
+    }
+
+    async fn player_names(&self) -> BackendResult<Vec<String>> {
+        let dbus = zbus::fdo::DBusProxy::new(&self.connection).await.map_err(api_error)?;
+        let names = dbus.list_names().await.map_err(|e| BackendError::Api(e.to_string()))?;
+        let mut players: Vec<String> =
+            names.into_iter().map(|n| n.to_string()).filter(|n| n.starts_with(MPRIS_PREFIX)).collect();
+        players.sort();
+        Ok(players)
+    }
+
+    // Properties are read fresh on every call, since players don't signal position changes
+    async fn proxy(&self, name: &str) -> BackendResult<PlayerProxy<'static>> {
+        PlayerProxy::builder(&self.connection)
+            .destination(name.to_owned())
+            .map_err(api_error)?
+            .cache_properties(CacheProperties::No)
+            .build()
+            .await
+            .map_err(api_error)
+    }
+
+    // The transfer target while it's running, then a playing player, then the first one
+    async fn active_name(&self, names: &[String]) -> BackendResult<Option<String>> {
+        let target = self.target.lock().unwrap().clone();
+        if let Some(target) = target.filter(|t| names.contains(t)) {
+            return Ok(Some(target));
+        }
+        for name in names {
+            if self.proxy(name).await?.playback_status().await.is_ok_and(|s| s == "Playing") {
+                return Ok(Some(name.clone()));
+            }
+        }
+        Ok(names.first().cloned())
+    }
+
+    // The player a command goes to: the named one, otherwise the active one
+    async fn resolve(&self, device_id: Option<&str>) -> BackendResult<PlayerProxy<'static>> {
+        let name = match device_id {
+            Some(id) => id.to_owned(),
+            None => {
+                let names = self.player_names().await?;
+                self.active_name(&names).await?.ok_or_else(|| BackendError::Api("no MPRIS player is running".to_owned()))?
+            }
+        };
+        self.proxy(&name).await
+    }
+
+    async fn device(&self, name: &str, is_active: bool) -> BackendResult<Device> {
+        let root = MediaPlayer2Proxy::builder(&self.connection)
+            .destination(name.to_owned())
+            .map_err(api_error)?
+            .cache_properties(CacheProperties::No)
+            .build()
+            .await
+            .map_err(api_error)?;
+        let player = self.proxy(name).await?;
+        let identity = root.identity().await.ok();
+        Ok(Device {
+            id: Some(name.to_owned()),
+            name: identity.unwrap_or_else(|| name.trim_start_matches(MPRIS_PREFIX).to_owned()),
+            is_active,
+            is_restricted: !player.can_control().await.unwrap_or(true),
+            volume_percent: player.volume().await.ok().map(|v| (v.clamp(0.0, 1.0) * 100.0).round() as u32),
+        })
+    }
+}
+
+impl PlayerBackend for MprisPlayer {
+    async fn playback(&self) -> BackendResult<Option<Playback>> {
+        let names = self.player_names().await?;
+        let Some(name) = self.active_name(&names).await? else {
+            return Ok(None);
+        };
+        let player = self.proxy(&name).await?;
+        let status = player.playback_status().await.map_err(api_error)?;
+        let metadata = player.metadata().await.unwrap_or_default();
+        Ok(Some(Playback {
+            device: self.device(&name, true).await?,
+            is_playing: status == "Playing",
+            progress: player.position().await.ok().map(from_micros),
+            item: playing_item(&metadata),
+            shuffle: player.shuffle().await.unwrap_or(false),
+            repeat: player.loop_status().await.map(|s| repeat_from(&s)).unwrap_or_default(),
+        }))
+    }
+
+    async fn devices(&self) -> BackendResult<Vec<Device>> {
+        let names = self.player_names().await?;
+        let active = self.active_name(&names).await?;
+        let mut devices = Vec::new();
+        for name in &names {
+            devices.push(self.device(name, active.as_ref() == Some(name)).await?);
+        }
+        Ok(devices)
+    }
+
+    async fn resume(&self, device_id: Option<&str>) -> BackendResult<()> {
+        self.resolve(device_id).await?.play().await.map_err(api_error)
+    }
+
+    async fn pause(&self, device_id: Option<&str>) -> BackendResult<()> {
+        self.resolve(device_id).await?.pause().await.map_err(api_error)
+    }
+
+    async fn next(&self, device_id: Option<&str>) -> BackendResult<()> {
+        self.resolve(device_id).await?.next().await.map_err(api_error)
+    }
+
+    async fn previous(&self, device_id: Option<&str>) -> BackendResult<()> {
+        self.resolve(device_id).await?.previous().await.map_err(api_error)
+    }
+
+    async fn set_volume(&self, percent: u32, device_id: Option<&str>) -> BackendResult<()> {
+        let volume = percent.min(100) as f64 / 100.0;
+        self.resolve(device_id).await?.set_volume(volume).await.map_err(api_error)
+    }
+
+    // SetPosition needs the track id. Without one, seek relative to where it is now.
+    async fn seek(&self, position: Duration, device_id: Option<&str>) -> BackendResult<()> {
+        let player = self.resolve(device_id).await?;
+        let metadata = player.metadata().await.unwrap_or_default();
+        match metadata.get("mpris:trackid").and_then(|v| v.downcast_ref::<ObjectPath>().ok()) {
+            Some(track_id) => player.set_position(&track_id, micros(position)).await.map_err(api_error),
+            None => {
+                let current = player.position().await.map_err(api_error)?;
+                player.seek(micros(position) - current).await.map_err(api_error)
+            }
+        }
+    }
+
+    async fn set_shuffle(&self, on: bool, device_id: Option<&str>) -> BackendResult<()> {
+        self.resolve(device_id).await?.set_shuffle(on).await.map_err(api_error)
+    }
+
+    async fn set_repeat(&self, repeat: Repeat, device_id: Option<&str>) -> BackendResult<()> {
+        let status = match repeat {
+            Repeat::Off => "None",
+            Repeat::Context => "Playlist",
+            Repeat::Track => "Track",
+        };
+        self.resolve(device_id).await?.set_loop_status(status).await.map_err(api_error)
+    }
+
+    async fn transfer(&self, device_id: &str) -> BackendResult<()> {
+        if !self.player_names().await?.iter().any(|n| n == device_id) {
+            return Err(BackendError::Api(format!("player {} not found", device_id)));
+        }
+        *self.target.lock().unwrap() = Some(device_id.to_owned());
+        Ok(())
+    }
+
+    // MPRIS can only open a URI, so starting part way into an album or playlist isn't possible
+    async fn start(&self, uri: &SpotifyUri, _offset: u32, device_id: Option<&str>) -> BackendResult<()> {
+        self.resolve(device_id).await?.open_uri(&uri.to_string()).await.map_err(api_error)
+    }
+
+    async fn is_saved(&self, _track: &SpotifyUri) -> BackendResult<bool> {
+        Err(BackendError::Unsupported("liking tracks needs the Web API backend"))
+    }
+
+    async fn set_saved(&self, _track: &SpotifyUri, _saved: bool) -> BackendResult<()> {
+        Err(BackendError::Unsupported("liking tracks needs the Web API backend"))
+    }
+
+    async fn playlist_contains(&self, _playlist: &SpotifyUri, _item: &SpotifyUri) -> BackendResult<bool> {
+        Err(BackendError::Unsupported("playlists need the Web API backend"))
+    }
+
+    async fn playlist_add(&self, _playlist: &SpotifyUri, _item: &SpotifyUri) -> BackendResult<()> {
+        Err(BackendError::Unsupported("playlists need the Web API backend"))
+    }
+}
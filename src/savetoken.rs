@@ -2,28 +2,57 @@
 use serde::{Deserialize, Serialize};
 use serde_json::from_str;
 use std::{error::Error, fs}; 
+use std::sync::OnceLock;
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 struct MyToken{
     RSPOTIFY_CLIENT_ID: String,
     RSPOTIFY_CLIENT_SECRET: String,
     RSPOTIFY_REDIRECT_URI: String,
 }
  
+// Where token.json and the encrypted credentials fallback live
+fn exe_dir() -> std::path::PathBuf {
+    std::env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(std::path::Path::to_path_buf))
+        .unwrap_or_else(|| std::path::PathBuf::from("."))
+}
+
+// The keyring (or encrypted file) holding the client secret and the Spotify token. The
+// first time it's opened, secrets older versions left in plain text are moved into it.
+fn credential_store() -> &'static dyn CredentialStore {
+    static STORE: OnceLock<Box<dyn CredentialStore>> = OnceLock::new();
+    STORE
+        .get_or_init(|| {
+            let store = default_store(&exe_dir());
+            let token_cache = exe_dir().join(".spotify_token_cache.json");
+            match migrate_plaintext(store.as_ref(), &MyToken::path(), &token_cache) {
+                Ok(migrated) if migrated != Migrated::default() => {
+                    println!("Moved plain text credentials into the {}", store.name())
+                }
+                Ok(_) => {}
+                Err(e) => println!("Couldn't move plain text credentials into the {}: {}", store.name(), e),
+            }
+            store
+        })
+        .as_ref()
+}
+
+// token.json only holds the client id and redirect URI. The secret field is kept, always
+// empty, so older versions can still read the file.
 impl MyToken {
     fn path() -> std::path::PathBuf {
-        if let Ok(exe) = std::env::current_exe() {
-            if let Some(dir) = exe.parent() {
-                return dir.join("token.json");
-            }
-        }
-        std::path::PathBuf::from("token.json")
+        exe_dir().join("token.json")
     }
 
-    pub fn from_json() -> Result<Self, Box<dyn Error>> {
+    // Only what token.json holds. This never touches the credential store, so it's safe
+    // on the UI thread.
+    pub fn from_file() -> Result<Self, InitError> {
         let p = Self::path();
         let content = match fs::read_to_string(&p) {
             Ok(content) => content,
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(InitError::Settings(e.to_string())),
             Err(_) => {
                 // File doesn't exist, create with default values
                 let default_app = crate::Appinfo::default();
@@ -32,22 +61,40 @@ impl MyToken {
                     RSPOTIFY_CLIENT_SECRET: default_app.clientSecret.clone(),
                     RSPOTIFY_REDIRECT_URI: default_app.redirectUri.clone(),
                 };
-                let json = serde_json::to_string_pretty(&default_token)?;
-                fs::write(&p, json)?;
+                let json = serde_json::to_string_pretty(&default_token).map_err(|e| InitError::Settings(e.to_string()))?;
+                fs::write(&p, json).map_err(|e| InitError::Settings(e.to_string()))?;
                 
                 
                 return Ok(default_token);
             }
         };
         
-        let token: MyToken = from_str(&content)?;
+        from_str(&content).map_err(|e| InitError::CorruptSettings(e.to_string()))
+    }
+
+    // token.json with the secret from the credential store. Opening the store may wait
+    // on the user unlocking their keyring, so this runs off the UI thread.
+    pub fn from_json() -> Result<Self, InitError> {
+        let mut token = Self::from_file()?;
+        // The file only still has a secret if it couldn't be migrated
+        if let Some(secret) = credential_store().get(CLIENT_SECRET)? {
+            token.RSPOTIFY_CLIENT_SECRET = secret;
+        }
         Ok(token)
     }
 
+    // Both of these open the credential store, so they're run off the UI thread
     pub fn save_to_json(&self) -> Result<(), Box<dyn Error>> {
-        let json = serde_json::to_string_pretty(&self)?;
+        save_client_secret(credential_store(), &self.RSPOTIFY_CLIENT_SECRET)?;
+        let public = MyToken { RSPOTIFY_CLIENT_SECRET: String::new(), ..self.clone() };
+        let json = serde_json::to_string_pretty(&public)?;
         fs::write(Self::path(), json)?;
         Ok(())
     }
+
+    pub fn forget_secret() -> Result<(), Box<dyn Error>> {
+        credential_store().delete(CLIENT_SECRET)?;
+        Ok(())
+    }
 }
 
@@ -1,7 +1,10 @@
 
 use rspotify::{
-    AuthCodeSpotify, ClientResult, Config, Credentials, OAuth,
-    model::{AdditionalType, Country, Market, PlayableItem},
+    AuthCodePkceSpotify, AuthCodeSpotify, ClientError, ClientResult, Config, Credentials, OAuth, Token,
+    model::{
+        AdditionalType, AlbumId, ArtistId, Country, EpisodeId, IdError, Market, Offset, PlayContextId, PlayableId,
+        PlayableItem, PlaylistId, RepeatState, ShowId, TrackId,
+    },
     prelude::*,
     scopes,
 };
@@ -10,7 +13,18 @@ use rspotify::{
 
 include!("savetoken.rs");
 
-async fn spotifyinit() -> Option<AuthCodeSpotify> {
+// A logged in Web API client, from whichever auth mode is selected
+#[derive(Clone)]
+enum SpotifyAuth {
+    Secret(AuthCodeSpotify),
+    Pkce(AuthCodePkceSpotify),
+}
+
+async fn spotifyinit(
+    token: MyToken,
+    mode: AuthMode,
+    progress: impl Fn(AuthProgress) + Send + Sync,
+) -> Result<SpotifyAuth, InitError> {
     // Set RSPOTIFY_CLIENT_ID and RS POTIFY_CLIENT_SECRET in an .env file (after
     // enabling the `env-file` feature) or export them manually:
     //
@@ -26,27 +40,12 @@ async fn spotifyinit() -> Option<AuthCodeSpotify> {
     // ```
     //let creds = Credentials::from_env().unwrap();
 
-    let token = MyToken::from_json().ok()?;
     let client_id = &token.RSPOTIFY_CLIENT_ID;
     let client_secret = &token.RSPOTIFY_CLIENT_SECRET;
     let redirect_uri = &token.RSPOTIFY_REDIRECT_URI;
 
-    if client_id.is_empty() || client_secret.is_empty() || redirect_uri.is_empty() {
-        println!("Spotify credentials are missing or empty");
-        return None;
-    }
-
-    if client_id.contains(char::is_whitespace) || client_secret.contains(char::is_whitespace) {
-        println!("Spotify credentials contain whitespace");
-        return None;
-    }
-
-    if client_id.len() != 32 || client_secret.len() != 32 {
-        println!("Spotify credentials have incorrect length");
-        return None;
-    }
-
-    let creds = Credentials::new(&client_id, &client_secret);
+    // PKCE logins only need the client id
+    validate_credentials(mode, client_id, client_secret, redirect_uri)?;
 
     // Same for RSPOTIFY_REDIRECT_URI. You can also set it explictly:
     //
@@ -62,39 +61,40 @@ async fn spotifyinit() -> Option<AuthCodeSpotify> {
         scopes: scopes!(
             "user-read-currently-playing",
             "user-modify-playback-state",
-            "user-read-playback-state"
+            "user-read-playback-state",
+            "user-library-read",
+            "user-library-modify",
+            "playlist-read-private",
+            "playlist-read-collaborative",
+            "playlist-modify-public",
+            "playlist-modify-private"
         ),
         ..Default::default()
     };
 
-    let cache_path = if let Ok(exe) = std::env::current_exe() {
-        if let Some(dir) = exe.parent() {
-            dir.join(".spotify_token_cache.json")
-        } else {
-            std::path::PathBuf::from(".spotify_token_cache.json")
-        }
-    } else {
-        std::path::PathBuf::from(".spotify_token_cache.json")
-    };
-
+    // The token goes to the credential store, not rspotify's plain text cache file
     let config = Config {
-        token_cached: true,
         token_refreshing: true,
-        cache_path,
         ..Default::default()
     };
 
-    let spotify = AuthCodeSpotify::with_config(creds, oauth, config);
-
-    // Obtaining the access token
-    let url = spotify.get_authorize_url(false).ok()?;
-
-    // This function requires the `cli` feature enabled.
-
-    spotify.prompt_for_token(&url).await.ok()?;
-
-
-    Some(spotify)
+    // Both modes share the stored token, so switching doesn't force a new login
+    // unless the stored token can't be refreshed
+    match mode {
+        AuthMode::ClientSecret => {
+            let creds = Credentials::new(client_id, client_secret);
+            let spotify = AuthCodeSpotify::with_config(creds, oauth, config);
+            let url = spotify.get_authorize_url(false).map_err(|e| InitError::AuthorizeUrl(e.to_string()))?;
+            login(&spotify, redirect_uri, &url, progress).await?;
+            Ok(SpotifyAuth::Secret(spotify))
+        }
+        AuthMode::Pkce => {
+            let mut spotify = AuthCodePkceSpotify::with_config(Credentials::new_pkce(client_id), oauth, config);
+            let url = spotify.get_authorize_url(None).map_err(|e| InitError::AuthorizeUrl(e.to_string()))?;
+            login(&spotify, redirect_uri, &url, progress).await?;
+            Ok(SpotifyAuth::Pkce(spotify))
+        }
+    }
 
     // Running the requests
     /*
@@ -195,139 +195,289 @@ async fn spotifyinit() -> Option<AuthCodeSpotify> {
 
 
 
-struct VolumeInfo {
-    stored_volume: u32,
+// Obtaining the access token. The browser comes back to a listener on the
+// redirect URI, since a GUI has no stdin to paste the URL into.
+async fn login<C: OAuthClient + 'static>(
+    spotify: &C,
+    redirect_uri: &str,
+    url: &str,
+    progress: impl Fn(AuthProgress),
+) -> Result<(), InitError> {
+    if !use_cached_token(spotify).await {
+        let open_browser = |url: &str| webbrowser::open(url).map(|_| ());
+        let exchange = |code: String| async move { spotify.request_token(&code).await.map_err(|e| e.to_string()) };
+        let state = &spotify.get_oauth().state;
+        authorize(redirect_uri, url, state, open_browser, exchange, progress).await?;
+    }
+    Ok(())
 }
 
+// Load the stored token, refreshing it if it has expired. False means the user has
+// to log in again.
+async fn use_cached_token(spotify: &impl OAuthClient) -> bool {
+    let stored = match credential_store().get(TOKEN) {
+        Ok(stored) => stored,
+        Err(e) => {
+            println!("Couldn't read the stored Spotify token: {}", e);
+            None
+        }
+    };
+    let Some(token) = stored.and_then(|json| serde_json::from_str::<Token>(&json).ok()) else {
+        return false;
+    };
+    // a token from before more scopes were needed has to be replaced
+    if !spotify.get_oauth().scopes.is_subset(&token.scopes) {
+        return false;
+    }
+    let expired = token.is_expired();
+    *spotify.get_token().lock().await.unwrap() = Some(token);
+    if !expired {
+        return true;
+    }
+    match spotify.refetch_token().await {
+        Ok(Some(token)) => {
+            *spotify.get_token().lock().await.unwrap() = Some(token);
+            true
+        }
+        _ => false,
+    }
+}
 
+// How often to check whether rspotify has refreshed the token
+const TOKEN_SAVE_INTERVAL: Duration = Duration::from_secs(60);
 
+// Store the client's token unless it's the one already stored. Returns what's stored.
+async fn save_token(spotify: &impl OAuthClient, saved: Option<String>) -> Option<String> {
+    let token = spotify.get_token().lock().await.unwrap().clone();
+    let Some(json) = token.and_then(|t| serde_json::to_string(&t).ok()) else {
+        return saved;
+    };
+    if saved.as_ref() == Some(&json) {
+        return saved;
+    }
+    match credential_store().set(TOKEN, &json) {
+        Ok(()) => Some(json),
+        Err(e) => {
+            println!("Couldn't save the Spotify token: {}", e);
+            saved
+        }
+    }
+}
 
-struct SpotifyClient {
-    spotify: AuthCodeSpotify,
-    //volcontext: VolumeInfo,
+// rspotify refreshes the token whenever a request needs it, but only knows how to
+// save it to its own cache file, so refreshed tokens are picked up here instead.
+// The first save is straight away. Losing the token only means logging in again
+// next launch.
+async fn keep_token_saved(spotify: impl OAuthClient) {
+    let mut saved = None;
+    let mut interval = tokio::time::interval(TOKEN_SAVE_INTERVAL);
+    loop {
+        interval.tick().await;
+        saved = save_token(&spotify, saved).await;
+    }
 }
 
-impl SpotifyClient {
-    async fn pause(&self, device_id: Option<&str>) -> ClientResult<()> {
-        if let Err(e) = self.spotify.pause_playback(device_id).await {
-            println!("Already paused! : {}", e);
-            return Ok(());
+impl SpotifyAuth {
+    // Never returns, so the caller decides when it stops by aborting its task
+    async fn keep_token_saved(self) {
+        match self {
+            SpotifyAuth::Secret(spotify) => keep_token_saved(spotify).await,
+            SpotifyAuth::Pkce(spotify) => keep_token_saved(spotify).await,
         }
-        Ok(())
     }
+}
 
-    async fn get_volume(&self, _device_id: Option<&str>) -> ClientResult<Option<u32>> {
-        let playback = self.spotify.current_playback(None, None::<Vec<_>>).await?;
-        if let Some(pb) = playback {
-            if let Some(volume) = pb.device.volume_percent {
-                return Ok(Some(volume));
-            }
-        }
-        Ok(None)
+// The Spotify Web API as a player backend, over either auth mode's client
+struct SpotifyClient<C> {
+    spotify: C,
+}
+
+fn api_error(e: ClientError) -> BackendError {
+    BackendError::Api(e.to_string())
+}
+
+fn id_error(e: IdError) -> BackendError {
+    BackendError::Api(e.to_string())
+}
+
+fn to_device(device: rspotify::model::Device) -> Device {
+    Device {
+        id: device.id,
+        name: device.name,
+        is_active: device.is_active,
+        is_restricted: device.is_restricted,
+        volume_percent: device.volume_percent,
     }
+}
 
-    async fn mute(&self, device_id: Option<&str>) -> ClientResult<()> { //doesnt work
-        let vol = VolumeInfo {
-            stored_volume: self.get_volume(device_id).await?.unwrap(),
-        };
+fn to_item(item: PlayableItem) -> Option<PlayingItem> {
+    let (uri, name, duration) = match item {
+        PlayableItem::Track(track) => (track.id.map(|id| id.uri()), track.name, track.duration),
+        PlayableItem::Episode(episode) => (Some(episode.id.uri()), episode.name, episode.duration),
+        PlayableItem::Unknown(_) => return None,
+    };
+    Some(PlayingItem {
+        uri: uri.and_then(|uri| uri.parse().ok()),
+        name,
+        duration: duration.to_std().unwrap_or_default(),
+    })
+}
 
-        let currentvol = self.get_volume(device_id).await?;
+fn playable_id(uri: &SpotifyUri) -> BackendResult<PlayableId<'_>> {
+    let id = uri.id.as_str();
+    match uri.item {
+        SpotifyItem::Track => TrackId::from_id(id).map(PlayableId::Track).map_err(id_error),
+        SpotifyItem::Episode => EpisodeId::from_id(id).map(PlayableId::Episode).map_err(id_error),
+        _ => Err(BackendError::Unsupported("only tracks and episodes can be added")),
+    }
+}
 
-        if currentvol == Some(0) {
-            self.spotify.volume(vol.stored_volume as u8, device_id).await?;
-            return Ok(());
-        } else {
-            self.spotify.volume(0, device_id).await?;
-        }
-        Ok(())
+fn playlist_id(uri: &SpotifyUri) -> BackendResult<PlaylistId<'_>> {
+    PlaylistId::from_id(uri.id.as_str()).map_err(id_error)
+}
+
+impl<C: OAuthClient> PlayerBackend for SpotifyClient<C> {
+    async fn playback(&self) -> BackendResult<Option<Playback>> {
+        let additional_types = [AdditionalType::Episode];
+        let playback = self
+            .spotify
+            .current_playback(None, Some(&additional_types))
+            .await
+            .map_err(api_error)?;
+        Ok(playback.map(|p| Playback {
+            device: to_device(p.device),
+            is_playing: p.is_playing,
+            progress: p.progress.and_then(|d| d.to_std().ok()),
+            item: p.item.and_then(to_item),
+            shuffle: p.shuffle_state,
+            repeat: match p.repeat_state {
+                RepeatState::Off => Repeat::Off,
+                RepeatState::Context => Repeat::Context,
+                RepeatState::Track => Repeat::Track,
+            },
+        }))
     }
 
-    async fn volup(&self, device_id: Option<&str>, incamt: u32) -> ClientResult<()> {
-        let currentvol = match self.get_volume(device_id).await? {
-            Some(vol) => vol,
-            None => {
-                println!("Could not get current volume");
-                return Ok(());
-            }
-        };
+    async fn devices(&self) -> BackendResult<Vec<Device>> {
+        let devices = self.spotify.device().await.map_err(api_error)?;
+        Ok(devices.into_iter().map(to_device).collect())
+    }
 
-        let new_vol = currentvol.saturating_add(incamt).min(100);
-        self.spotify.volume(new_vol as u8, device_id).await?;
-        Ok(())
+    async fn resume(&self, device_id: Option<&str>) -> BackendResult<()> {
+        self.spotify.resume_playback(device_id, None).await.map_err(api_error)
     }
 
-    async fn voldown(&self, device_id: Option<&str>, decamt: u32) -> ClientResult<()> {
-        let currentvol = match self.get_volume(device_id).await? {
-            Some(vol) => vol,
-            None => {
-                println!("Could not get current volume");
-                return Ok(());
-            }
+    async fn pause(&self, device_id: Option<&str>) -> BackendResult<()> {
+        self.spotify.pause_playback(device_id).await.map_err(api_error)
+    }
+
+    async fn next(&self, device_id: Option<&str>) -> BackendResult<()> {
+        self.spotify.next_track(device_id).await.map_err(api_error)
+    }
+
+    async fn previous(&self, device_id: Option<&str>) -> BackendResult<()> {
+        self.spotify.previous_track(device_id).await.map_err(api_error)
+    }
+
+    async fn set_volume(&self, percent: u32, device_id: Option<&str>) -> BackendResult<()> {
+        self.spotify.volume(percent.min(100) as u8, device_id).await.map_err(api_error)
+    }
+
+    async fn seek(&self, position: Duration, device_id: Option<&str>) -> BackendResult<()> {
+        let position = chrono::Duration::milliseconds(position.as_millis() as i64);
+        self.spotify.seek_track(position, device_id).await.map_err(api_error)
+    }
+
+    async fn set_shuffle(&self, on: bool, device_id: Option<&str>) -> BackendResult<()> {
+        self.spotify.shuffle(on, device_id).await.map_err(api_error)
+    }
+
+    async fn set_repeat(&self, repeat: Repeat, device_id: Option<&str>) -> BackendResult<()> {
+        let state = match repeat {
+            Repeat::Off => RepeatState::Off,
+            Repeat::Context => RepeatState::Context,
+            Repeat::Track => RepeatState::Track,
         };
+        self.spotify.repeat(state, device_id).await.map_err(api_error)
+    }
 
-        let new_vol = currentvol.saturating_sub(decamt);
-        self.spotify.volume(new_vol as u8, device_id).await?;
-        Ok(())
+    async fn transfer(&self, device_id: &str) -> BackendResult<()> {
+        self.spotify.transfer_playback(device_id, None).await.map_err(api_error)
     }
 
-    async fn play(&self, device_id: Option<&str>) -> ClientResult<()> {
-        // Try to resume existing playback first
-        if self.spotify.resume_playback(device_id, None).await.is_ok() {
-            return Ok(());
+    async fn start(&self, uri: &SpotifyUri, offset: u32, device_id: Option<&str>) -> BackendResult<()> {
+        if !uri.item.is_context() {
+            let playable = playable_id(uri)?;
+            return self
+                .spotify
+                .start_uris_playback([playable], device_id, None, None)
+                .await
+                .map_err(api_error);
         }
-        
-        // If that fails, try to resume on an available device
-        match self.get_available_device().await {
-            Ok(Some(dev_id)) => {
-                let _ = self.spotify.resume_playback(Some(&dev_id), None).await;
-                Ok(())
-            }
-            _ => {
-                println!("Could not start playback: no active device");
-                Ok(())
-            }
+
+        let id = uri.id.as_str();
+        let context = match uri.item {
+            SpotifyItem::Album => AlbumId::from_id(id).map(PlayContextId::Album),
+            SpotifyItem::Artist => ArtistId::from_id(id).map(PlayContextId::Artist),
+            SpotifyItem::Show => ShowId::from_id(id).map(PlayContextId::Show),
+            _ => PlaylistId::from_id(id).map(PlayContextId::Playlist),
         }
+        .map_err(id_error)?;
+        // Artists can't start from an offset. rspotify sends Offset::Position as a plain
+        // index despite its Duration type.
+        let offset = (offset > 0 && uri.item != SpotifyItem::Artist)
+            .then(|| Offset::Position(chrono::Duration::milliseconds(offset as i64)));
+        self.spotify
+            .start_context_playback(context, device_id, offset, None)
+            .await
+            .map_err(api_error)
     }
 
-    async fn toggle_playback(&self, device_id: Option<&str>) -> ClientResult<()> {
-        let playback = self.spotify.current_playback(None, None::<Vec<_>>).await?;
+    async fn is_saved(&self, track: &SpotifyUri) -> BackendResult<bool> {
+        let id = TrackId::from_id(track.id.as_str()).map_err(id_error)?;
+        let saved = self.spotify.current_user_saved_tracks_contains([id]).await.map_err(api_error)?;
+        Ok(saved.first().copied().unwrap_or(false))
+    }
 
-        if let Some(context) = playback {
-            if context.is_playing {
-                self.spotify.pause_playback(device_id).await?;
-            } else {
-                self.spotify.resume_playback(device_id, None).await?;
-            }
+    async fn set_saved(&self, track: &SpotifyUri, saved: bool) -> BackendResult<()> {
+        let id = TrackId::from_id(track.id.as_str()).map_err(id_error)?;
+        if saved {
+            self.spotify.current_user_saved_tracks_add([id]).await.map_err(api_error)
         } else {
-            // No active playback - try to start it on an available device
-            match self.get_available_device().await {
-                Ok(Some(dev_id)) => {
-                    let _ = self.spotify.resume_playback(Some(&dev_id), None).await;
-                }
-                _ => {
-                    println!("Could not start playback: no active device");
-                }
-            }
+            self.spotify.current_user_saved_tracks_delete([id]).await.map_err(api_error)
         }
-
-        Ok(())
     }
 
-    async fn next_track(&self, device_id: Option<&str>) -> ClientResult<()> {
-        self.spotify.next_track(device_id).await?;
-        Ok(())
+    async fn playlist_contains(&self, playlist: &SpotifyUri, item: &SpotifyUri) -> BackendResult<bool> {
+        let playlist = playlist_id(playlist)?;
+        let item = item.to_string();
+        let mut offset = 0;
+        loop {
+            let page = self
+                .spotify
+                .playlist_items_manual(playlist.as_ref(), None, None, Some(100), Some(offset))
+                .await
+                .map_err(api_error)?;
+            if page.items.iter().any(|i| i.track.as_ref().and_then(|t| t.id()).is_some_and(|id| id.uri() == item)) {
+                return Ok(true);
+            }
+            if page.next.is_none() || page.items.is_empty() {
+                return Ok(false);
+            }
+            offset += page.items.len() as u32;
+        }
     }
 
-    async fn previous_track(&self, device_id: Option<&str>) -> ClientResult<()> {
-        self.spotify.previous_track(device_id.as_deref()).await?;
+    async fn playlist_add(&self, playlist: &SpotifyUri, item: &SpotifyUri) -> BackendResult<()> {
+        self.spotify
+            .playlist_add_items(playlist_id(playlist)?, [playable_id(item)?], None)
+            .await
+            .map_err(api_error)?;
         Ok(())
     }
+}
 
-    async fn get_available_device(&self) -> ClientResult<Option<String>> {
-        let devices = self.spotify.device().await?;
-        Ok(devices.first().and_then(|d| d.id.clone()))
-    }
-
+impl<C: OAuthClient> SpotifyClient<C> {
     async fn song_info(&self, device_id: Option<&str>) -> ClientResult<()> {
         let market = Market::Country(Country::UnitedStates);
         let additional_types = [AdditionalType::Episode];
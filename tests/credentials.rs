@@ -0,0 +1,404 @@
+// Credential stores and the migration away from plain text files. The Secret Service
+// store runs against a mock keyring on a private session bus, skipped when dbus-daemon
+// isn't installed.
+use std::fs;
+use std::path::PathBuf;
+use SpotifyBinds::credentials::file::EncryptedFileStore;
+use SpotifyBinds::credentials::{
+    migrate_plaintext, save_client_secret, CredentialError, CredentialStore, MemoryStore, Migrated, CLIENT_SECRET,
+    TOKEN,
+};
+
+const SECRET: &str = "0123456789abcdef0123456789abcdef";
+const CACHE: &str = r#"{"access_token":"a","refresh_token":"r","expires_in":3600}"#;
+
+// A fresh directory per test
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("spotifybinds-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn file_store(dir: &std::path::Path) -> EncryptedFileStore {
+    EncryptedFileStore::new(dir.join("credentials.enc"), dir.join("config").join("credentials.key"))
+}
+
+#[test]
+fn plaintext_secrets_move_into_the_store() {
+    let dir = temp_dir("migrate");
+    let settings = dir.join("token.json");
+    let cache = dir.join(".spotify_token_cache.json");
+    fs::write(
+        &settings,
+        format!(
+            r#"{{"RSPOTIFY_CLIENT_ID":"id","RSPOTIFY_CLIENT_SECRET":"{}","RSPOTIFY_REDIRECT_URI":"http://127.0.0.1:8888/callback"}}"#,
+            SECRET
+        ),
+    )
+    .unwrap();
+    fs::write(&cache, CACHE).unwrap();
+
+    let store = MemoryStore::default();
+    let migrated = migrate_plaintext(&store, &settings, &cache).unwrap();
+    assert_eq!(migrated, Migrated { client_secret: true, token: true });
+    assert_eq!(store.get(CLIENT_SECRET).unwrap().as_deref(), Some(SECRET));
+    assert_eq!(store.get(TOKEN).unwrap().as_deref(), Some(CACHE));
+
+    // the rest of token.json is left alone
+    let rewritten: serde_json::Value = serde_json::from_str(&fs::read_to_string(&settings).unwrap()).unwrap();
+    assert_eq!(rewritten["RSPOTIFY_CLIENT_SECRET"], "");
+    assert_eq!(rewritten["RSPOTIFY_CLIENT_ID"], "id");
+    assert!(!cache.exists());
+
+    // nothing left to do the second time
+    assert_eq!(migrate_plaintext(&store, &settings, &cache).unwrap(), Migrated::default());
+    assert_eq!(store.get(CLIENT_SECRET).unwrap().as_deref(), Some(SECRET));
+}
+
+// A store that refuses everything, like a keyring whose unlock prompt was dismissed
+struct LockedStore;
+
+impl CredentialStore for LockedStore {
+    fn name(&self) -> &'static str {
+        "locked"
+    }
+
+    fn get(&self, _key: &str) -> Result<Option<String>, CredentialError> {
+        Err(CredentialError::Locked)
+    }
+
+    fn set(&self, _key: &str, _value: &str) -> Result<(), CredentialError> {
+        Err(CredentialError::Locked)
+    }
+
+    fn delete(&self, _key: &str) -> Result<(), CredentialError> {
+        Err(CredentialError::Locked)
+    }
+}
+
+#[test]
+fn files_are_kept_when_the_store_fails() {
+    let dir = temp_dir("migrate-locked");
+    let settings = dir.join("token.json");
+    let cache = dir.join(".spotify_token_cache.json");
+    let original = format!(r#"{{"RSPOTIFY_CLIENT_SECRET":"{}"}}"#, SECRET);
+    fs::write(&settings, &original).unwrap();
+    fs::write(&cache, CACHE).unwrap();
+
+    assert_eq!(migrate_plaintext(&LockedStore, &settings, &cache), Err(CredentialError::Locked));
+    assert_eq!(fs::read_to_string(&settings).unwrap(), original);
+    assert!(cache.exists());
+}
+
+#[test]
+fn saving_an_empty_secret_keeps_the_stored_one() {
+    let store = MemoryStore::default();
+    store.set(CLIENT_SECRET, SECRET).unwrap();
+    save_client_secret(&store, "").unwrap();
+    assert_eq!(store.get(CLIENT_SECRET).unwrap().as_deref(), Some(SECRET));
+
+    save_client_secret(&store, "new-secret").unwrap();
+    assert_eq!(store.get(CLIENT_SECRET).unwrap().as_deref(), Some("new-secret"));
+}
+
+#[test]
+fn encrypted_file_round_trips() {
+    let dir = temp_dir("file");
+    let store = file_store(&dir);
+    assert_eq!(store.get(TOKEN).unwrap(), None);
+
+    store.set(TOKEN, CACHE).unwrap();
+    store.set(CLIENT_SECRET, SECRET).unwrap();
+    store.delete("missing").unwrap();
+
+    // a new store over the same files sees the same entries
+    let reopened = file_store(&dir);
+    assert_eq!(reopened.get(TOKEN).unwrap().as_deref(), Some(CACHE));
+    assert_eq!(reopened.get(CLIENT_SECRET).unwrap().as_deref(), Some(SECRET));
+    reopened.delete(TOKEN).unwrap();
+    assert_eq!(store.get(TOKEN).unwrap(), None);
+
+    // and none of it is readable on disk
+    let data = fs::read(dir.join("credentials.enc")).unwrap();
+    assert!(!String::from_utf8_lossy(&data).contains(SECRET));
+}
+
+#[test]
+fn encrypted_file_rejects_tampering() {
+    let dir = temp_dir("file-tamper");
+    let store = file_store(&dir);
+    store.set(CLIENT_SECRET, SECRET).unwrap();
+
+    let path = dir.join("credentials.enc");
+    let mut data = fs::read(&path).unwrap();
+    let last = data.len() - 1;
+    data[last] ^= 1;
+    fs::write(&path, data).unwrap();
+    assert!(matches!(store.get(CLIENT_SECRET), Err(CredentialError::Corrupt(_))));
+}
+
+#[test]
+fn losing_the_key_starts_over() {
+    let dir = temp_dir("file-key");
+    let store = file_store(&dir);
+    store.set(CLIENT_SECRET, SECRET).unwrap();
+    fs::remove_file(dir.join("config").join("credentials.key")).unwrap();
+
+    assert_eq!(store.get(CLIENT_SECRET).unwrap(), None);
+    store.set(TOKEN, CACHE).unwrap();
+    assert_eq!(store.get(TOKEN).unwrap().as_deref(), Some(CACHE));
+    assert_eq!(store.get(CLIENT_SECRET).unwrap(), None);
+}
+
+#[cfg(unix)]
+#[test]
+fn key_file_is_private() {
+    use std::os::unix::fs::PermissionsExt;
+    let dir = temp_dir("file-mode");
+    file_store(&dir).set(TOKEN, CACHE).unwrap();
+    for file in [dir.join("config").join("credentials.key"), dir.join("credentials.enc")] {
+        assert_eq!(fs::metadata(file).unwrap().permissions().mode() & 0o777, 0o600);
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod secret_service {
+    use super::*;
+    use std::collections::HashMap;
+    use std::io::{BufRead, BufReader};
+    use std::process::{Child, Command, Stdio};
+    use std::sync::{Arc, Mutex};
+    use zbus::blocking::connection;
+    use zbus::zvariant::{OwnedObjectPath, OwnedValue};
+    use zbus::{fdo, ObjectServer};
+    use SpotifyBinds::credentials::secret_service::{Secret, SecretServiceStore, SECRETS_NAME, SECRETS_PATH};
+
+    const COLLECTION: &str = "/org/freedesktop/secrets/collection/login";
+
+    #[derive(Debug, Clone)]
+    struct MockItem {
+        attributes: HashMap<String, String>,
+        value: Vec<u8>,
+    }
+
+    #[derive(Debug, Default)]
+    struct Keyring {
+        items: HashMap<String, MockItem>, // by object path
+        next: usize,
+        locked: bool, // whether items start out locked
+        unlocked: Vec<String>,
+    }
+
+    type Shared = Arc<Mutex<Keyring>>;
+
+    fn item_path(n: usize) -> String {
+        format!("{}/{}", COLLECTION, n)
+    }
+
+    struct MockService {
+        keyring: Shared,
+    }
+
+    #[zbus::interface(name = "org.freedesktop.Secret.Service")]
+    impl MockService {
+        fn open_session(&self, algorithm: String, _input: OwnedValue) -> fdo::Result<(OwnedValue, OwnedObjectPath)> {
+            if algorithm != "plain" {
+                return Err(fdo::Error::NotSupported(algorithm));
+            }
+            let session = OwnedObjectPath::try_from("/org/freedesktop/secrets/session/1").unwrap();
+            Ok((OwnedValue::from(0u8), session))
+        }
+
+        fn search_items(&self, attributes: HashMap<String, String>) -> (Vec<OwnedObjectPath>, Vec<OwnedObjectPath>) {
+            let keyring = self.keyring.lock().unwrap();
+            let (mut unlocked, mut locked) = (Vec::new(), Vec::new());
+            for (path, item) in &keyring.items {
+                if attributes.iter().all(|(k, v)| item.attributes.get(k) == Some(v)) {
+                    let object = OwnedObjectPath::try_from(path.as_str()).unwrap();
+                    if keyring.locked && !keyring.unlocked.contains(path) {
+                        locked.push(object);
+                    } else {
+                        unlocked.push(object);
+                    }
+                }
+            }
+            (unlocked, locked)
+        }
+
+        // unlocks without asking, so no prompt
+        fn unlock(&self, objects: Vec<OwnedObjectPath>) -> (Vec<OwnedObjectPath>, OwnedObjectPath) {
+            let mut keyring = self.keyring.lock().unwrap();
+            keyring.unlocked.extend(objects.iter().map(|o| o.to_string()));
+            (objects, OwnedObjectPath::try_from("/").unwrap())
+        }
+
+        fn read_alias(&self, name: String) -> OwnedObjectPath {
+            let path = if name == "default" { COLLECTION } else { "/" };
+            OwnedObjectPath::try_from(path).unwrap()
+        }
+    }
+
+    struct MockCollection {
+        keyring: Shared,
+    }
+
+    #[zbus::interface(name = "org.freedesktop.Secret.Collection")]
+    impl MockCollection {
+        async fn create_item(
+            &self,
+            properties: HashMap<String, OwnedValue>,
+            secret: Secret,
+            replace: bool,
+            #[zbus(object_server)] server: &ObjectServer,
+        ) -> fdo::Result<(OwnedObjectPath, OwnedObjectPath)> {
+            let attributes: HashMap<String, String> = properties
+                .get("org.freedesktop.Secret.Item.Attributes")
+                .and_then(|v| v.try_clone().ok())
+                .and_then(|v| v.try_into().ok())
+                .ok_or_else(|| fdo::Error::InvalidArgs("no attributes".to_owned()))?;
+            let (path, new) = {
+                let mut keyring = self.keyring.lock().unwrap();
+                let existing = keyring.items.iter().find(|(_, i)| i.attributes == attributes).map(|(p, _)| p.clone());
+                let path = match existing.filter(|_| replace) {
+                    Some(path) => path,
+                    None => {
+                        keyring.next += 1;
+                        item_path(keyring.next)
+                    }
+                };
+                let new = !keyring.items.contains_key(&path);
+                keyring.items.insert(path.clone(), MockItem { attributes, value: secret.value });
+                (path, new)
+            };
+            if new {
+                let item = MockItemObject { keyring: self.keyring.clone(), path: path.clone() };
+                server.at(path.as_str(), item).await?;
+            }
+            Ok((OwnedObjectPath::try_from(path).unwrap(), OwnedObjectPath::try_from("/").unwrap()))
+        }
+    }
+
+    struct MockItemObject {
+        keyring: Shared,
+        path: String,
+    }
+
+    #[zbus::interface(name = "org.freedesktop.Secret.Item")]
+    impl MockItemObject {
+        fn get_secret(&self, session: OwnedObjectPath) -> fdo::Result<Secret> {
+            let keyring = self.keyring.lock().unwrap();
+            if keyring.locked && !keyring.unlocked.contains(&self.path) {
+                return Err(fdo::Error::AccessDenied("locked".to_owned()));
+            }
+            let item = keyring.items.get(&self.path).ok_or_else(|| fdo::Error::UnknownObject(self.path.clone()))?;
+            Ok(Secret { session, parameters: Vec::new(), value: item.value.clone(), content_type: "text/plain".to_owned() })
+        }
+
+        async fn delete(&self, #[zbus(object_server)] server: &ObjectServer) -> fdo::Result<OwnedObjectPath> {
+            self.keyring.lock().unwrap().items.remove(&self.path);
+            server.remove::<MockItemObject, _>(self.path.as_str()).await?;
+            Ok(OwnedObjectPath::try_from("/").unwrap())
+        }
+    }
+
+    struct Bus {
+        daemon: Child,
+        address: String,
+    }
+
+    impl Drop for Bus {
+        fn drop(&mut self) {
+            let _ = self.daemon.kill();
+            let _ = self.daemon.wait();
+        }
+    }
+
+    fn private_bus() -> Option<Bus> {
+        let mut daemon = Command::new("dbus-daemon")
+            .args(["--session", "--nofork", "--print-address"])
+            .stdout(Stdio::piped())
+            .spawn()
+            .ok()?;
+        let mut address = String::new();
+        BufReader::new(daemon.stdout.take()?).read_line(&mut address).ok()?;
+        Some(Bus { daemon, address: address.trim().to_owned() })
+    }
+
+    fn serve(bus: &Bus, keyring: &Shared) -> zbus::blocking::Connection {
+        connection::Builder::address(bus.address.as_str())
+            .unwrap()
+            .name(SECRETS_NAME)
+            .unwrap()
+            .serve_at(SECRETS_PATH, MockService { keyring: keyring.clone() })
+            .unwrap()
+            .serve_at(COLLECTION, MockCollection { keyring: keyring.clone() })
+            .unwrap()
+            .build()
+            .unwrap()
+    }
+
+    fn store(bus: &Bus) -> Result<SecretServiceStore, CredentialError> {
+        let connection = connection::Builder::address(bus.address.as_str()).unwrap().build().unwrap();
+        SecretServiceStore::with_connection(connection)
+    }
+
+    #[test]
+    fn secrets_live_in_the_keyring() {
+        let Some(bus) = private_bus() else {
+            eprintln!("dbus-daemon not available, skipping");
+            return;
+        };
+        let keyring = Shared::default();
+        let _service = serve(&bus, &keyring);
+        let store = store(&bus).unwrap();
+
+        assert_eq!(store.get(TOKEN).unwrap(), None);
+        store.set(TOKEN, CACHE).unwrap();
+        store.set(CLIENT_SECRET, SECRET).unwrap();
+        store.set(CLIENT_SECRET, "replaced").unwrap();
+        assert_eq!(store.get(TOKEN).unwrap().as_deref(), Some(CACHE));
+        assert_eq!(store.get(CLIENT_SECRET).unwrap().as_deref(), Some("replaced"));
+        assert_eq!(keyring.lock().unwrap().items.len(), 2);
+
+        let contents = keyring.lock().unwrap();
+        let attributes = &contents.items.values().find(|i| i.value == b"replaced").unwrap().attributes;
+        assert_eq!(attributes.get("service").map(String::as_str), Some("SpotifyBinds"));
+        assert_eq!(attributes.get("key").map(String::as_str), Some(CLIENT_SECRET));
+        drop(contents);
+
+        store.delete(TOKEN).unwrap();
+        store.delete(TOKEN).unwrap();
+        assert_eq!(store.get(TOKEN).unwrap(), None);
+        assert_eq!(keyring.lock().unwrap().items.len(), 1);
+    }
+
+    #[test]
+    fn locked_items_are_unlocked_first() {
+        let Some(bus) = private_bus() else {
+            eprintln!("dbus-daemon not available, skipping");
+            return;
+        };
+        let keyring = Shared::default();
+        let _service = serve(&bus, &keyring);
+        let store = store(&bus).unwrap();
+        store.set(CLIENT_SECRET, SECRET).unwrap();
+
+        {
+            let mut keyring = keyring.lock().unwrap();
+            keyring.locked = true;
+            keyring.unlocked.clear();
+        }
+        assert_eq!(store.get(CLIENT_SECRET).unwrap().as_deref(), Some(SECRET));
+        assert!(keyring.lock().unwrap().unlocked.iter().any(|p| p.as_str() == item_path(1)));
+    }
+
+    #[test]
+    fn no_keyring_means_unavailable() {
+        let Some(bus) = private_bus() else {
+            eprintln!("dbus-daemon not available, skipping");
+            return;
+        };
+        assert!(matches!(store(&bus), Err(CredentialError::Unavailable(_))));
+    }
+}
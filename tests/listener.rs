@@ -0,0 +1,193 @@
+// Drives the hotkey listener with scripted key events, so no keyboard or
+// display is needed.
+use rdev::{Button, EventType, Key};
+use tokio::sync::mpsc::unbounded_channel;
+use std::time::Duration;
+use SpotifyBinds::hotkeyreg::{
+    run_listener, Binding, KeyEvent, RepeatPolicy, ScriptedInput, Trigger, DEFAULT_SEQUENCE_TIMEOUT, SIDE_BUTTONS,
+};
+
+fn bind(keys: &str, action: KeyEvent) -> Binding {
+    Binding { keys: Some(keys.parse().unwrap()), ..Binding::new(action) }
+}
+
+fn run(script: ScriptedInput, binds: Vec<Binding>) -> Vec<KeyEvent> {
+    let (tx, mut rx) = unbounded_channel();
+    run_listener(script, tx, binds, DEFAULT_SEQUENCE_TIMEOUT);
+    let mut actions = Vec::new();
+    while let Ok(fired) = rx.try_recv() {
+        actions.push(fired.action);
+    }
+    actions
+}
+
+#[test]
+fn chord_with_modifiers() {
+    let script = ScriptedInput::new()
+        .press(0, Key::ControlLeft)
+        .press(10, Key::ShiftRight)
+        .tap(20, Key::KeyP, 30)
+        .release(60, Key::ShiftRight)
+        .release(70, Key::ControlLeft)
+        // same key without the modifiers doesn't match
+        .tap(100, Key::KeyP, 30);
+
+    let actions = run(script, vec![bind("Ctrl+Shift+P", KeyEvent::Toggle)]);
+    assert_eq!(actions, vec![KeyEvent::Toggle]);
+}
+
+#[test]
+fn side_specific_modifier() {
+    let script = ScriptedInput::new()
+        .press(0, Key::ControlLeft)
+        .tap(10, Key::Space, 20)
+        .release(40, Key::ControlLeft)
+        .press(100, Key::ControlRight)
+        .tap(110, Key::Space, 20)
+        .release(140, Key::ControlRight);
+
+    let actions = run(script, vec![bind("RightCtrl+Space", KeyEvent::Next)]);
+    assert_eq!(actions, vec![KeyEvent::Next]);
+}
+
+#[test]
+fn leader_sequence() {
+    let script = ScriptedInput::new()
+        .press(0, Key::ControlLeft)
+        .tap(10, Key::KeyK, 20)
+        .release(40, Key::ControlLeft)
+        .tap(200, Key::KeyN, 20)
+        // too slow for the second step
+        .press(1000, Key::ControlLeft)
+        .tap(1010, Key::KeyK, 20)
+        .release(1040, Key::ControlLeft)
+        .tap(3000, Key::KeyN, 20);
+
+    let actions = run(script, vec![bind("Ctrl+K, N", KeyEvent::Next)]);
+    assert_eq!(actions, vec![KeyEvent::Next]);
+}
+
+#[test]
+fn tap_hold_and_double_tap() {
+    let binds = vec![
+        Binding { trigger: Trigger::Tap(1), ..bind("F9", KeyEvent::Toggle) },
+        Binding { trigger: Trigger::Tap(2), ..bind("F9", KeyEvent::Next) },
+        Binding { trigger: Trigger::Hold(500), ..bind("F9", KeyEvent::Previous) },
+    ];
+    let script = ScriptedInput::new()
+        .tap(0, Key::F9, 50)
+        .tap(1000, Key::F9, 50)
+        .tap(1150, Key::F9, 50)
+        .tap(2000, Key::F9, 800)
+        // a trailing single tap is still settled when the input ends
+        .tap(4000, Key::F9, 50);
+
+    let actions = run(script, binds);
+    assert_eq!(actions, vec![KeyEvent::Toggle, KeyEvent::Next, KeyEvent::Previous, KeyEvent::Toggle]);
+}
+
+#[test]
+fn held_volume_key_repeats_until_release() {
+    let binds = vec![Binding {
+        repeat: Some(RepeatPolicy::Repeat { interval_ms: 100, min_interval_ms: 100 }),
+        ..bind("F10", KeyEvent::Volup(5))
+    }];
+    // OS auto-repeat presses in between are ignored
+    let script = ScriptedInput::new()
+        .press(0, Key::F10)
+        .press(30, Key::F10)
+        .press(60, Key::F10)
+        .press(250, Key::F10)
+        .release(350, Key::F10);
+
+    let actions = run(script, binds);
+    assert_eq!(actions, vec![KeyEvent::Volup(5); 4]);
+}
+
+#[test]
+fn unbound_keys_send_nothing() {
+    let script = ScriptedInput::new().tap(0, Key::KeyA, 20).tap(50, Key::F9, 20);
+    let actions = run(script, vec![Binding::new(KeyEvent::Mute), bind("F8", KeyEvent::Mute)]);
+    assert!(actions.is_empty());
+}
+
+#[test]
+fn sequence_timeout_is_respected() {
+    let script = ScriptedInput::new().tap(0, Key::KeyG, 20).tap(400, Key::KeyH, 20);
+    let binds = vec![bind("G, H", KeyEvent::Play)];
+
+    let (tx, mut rx) = unbounded_channel();
+    run_listener(script, tx, binds, Duration::from_millis(200));
+    assert!(rx.try_recv().is_err());
+}
+
+#[test]
+fn wheel_with_modifier() {
+    let binds = vec![bind("Alt+WheelUp", KeyEvent::Volup(5)), bind("Alt+WheelDown", KeyEvent::Voldown(5))];
+    let script = ScriptedInput::new()
+        .press(0, Key::Alt)
+        .wheel(10, 3)
+        .wheel(50, -1)
+        .release(100, Key::Alt)
+        // plain scrolling is left alone
+        .wheel(200, 2);
+
+    let actions = run(script, binds);
+    assert_eq!(actions, vec![KeyEvent::Volup(5), KeyEvent::Volup(5), KeyEvent::Volup(5), KeyEvent::Voldown(5)]);
+}
+
+// A consuming bind mustn't stop mouse binds from firing alongside it
+#[test]
+fn consume_and_wheel_binds_together() {
+    let binds = vec![
+        Binding { consume: true, ..bind("Ctrl+Shift+Space", KeyEvent::Toggle) },
+        bind("Alt+WheelUp", KeyEvent::Volup(5)),
+    ];
+    let script = ScriptedInput::new()
+        .press(0, Key::ControlLeft)
+        .press(10, Key::ShiftLeft)
+        .tap(20, Key::Space, 20)
+        .release(50, Key::ShiftLeft)
+        .release(60, Key::ControlLeft)
+        .press(100, Key::Alt)
+        .wheel(110, 1)
+        .release(150, Key::Alt);
+
+    let actions = run(script, binds);
+    assert_eq!(actions, vec![KeyEvent::Toggle, KeyEvent::Volup(5)]);
+}
+
+#[test]
+fn mouse_buttons() {
+    let back = Button::Unknown(SIDE_BUTTONS.0);
+    let binds = vec![
+        bind("MouseBack", KeyEvent::Previous),
+        Binding { trigger: Trigger::Hold(400), ..bind("Ctrl+MouseMiddle", KeyEvent::Toggle) },
+    ];
+    let script = ScriptedInput::new()
+        .event(0, EventType::ButtonPress(back))
+        .event(50, EventType::ButtonRelease(back))
+        .event(100, EventType::ButtonPress(Button::Left))
+        .event(150, EventType::ButtonRelease(Button::Left))
+        .press(200, Key::ControlLeft)
+        .event(210, EventType::ButtonPress(Button::Middle))
+        .event(800, EventType::ButtonRelease(Button::Middle))
+        .release(900, Key::ControlLeft);
+
+    let actions = run(script, binds);
+    assert_eq!(actions, vec![KeyEvent::Previous, KeyEvent::Toggle]);
+}
+
+#[test]
+fn binding_device_travels_with_the_action() {
+    let script = ScriptedInput::new().tap(0, Key::F7, 20).tap(100, Key::F8, 20);
+    let binds = vec![
+        Binding { device: Some("Living Room".to_owned()), ..bind("F7", KeyEvent::Toggle) },
+        bind("F8", KeyEvent::Toggle),
+    ];
+
+    let (tx, mut rx) = unbounded_channel();
+    run_listener(script, tx, binds, DEFAULT_SEQUENCE_TIMEOUT);
+    let devices: Vec<Option<String>> = std::iter::from_fn(|| rx.try_recv().ok()).map(|f| f.device).collect();
+    assert_eq!(devices, vec![Some("Living Room".to_owned()), None]);
+}
@@ -0,0 +1,297 @@
+// Runs the MPRIS backend against mock players on a private session bus. Skipped when
+// dbus-daemon isn't installed.
+#![cfg(target_os = "linux")]
+
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader};
+use std::process::{Child, Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use zbus::connection;
+use zbus::zvariant::{ObjectPath, OwnedValue, Value};
+use SpotifyBinds::hotkeyreg::{PlayTarget, PlaylistTarget};
+use SpotifyBinds::player::mpris::{MprisPlayer, MPRIS_PATH};
+use SpotifyBinds::player::{BackendError, Player, PlayerBackend, Repeat, VolumeState};
+
+const SPOTIFY: &str = "org.mpris.MediaPlayer2.spotify";
+const VLC: &str = "org.mpris.MediaPlayer2.vlc";
+const TRACK_ID: &str = "/com/spotify/track/4uLU6hMCjMI75M1A2tKUQC";
+
+#[derive(Debug, Clone, Default)]
+struct MockState {
+    status: String,
+    loop_status: String,
+    shuffle: bool,
+    volume: f64,
+    position: i64, // microseconds
+    track_id: Option<String>,
+    calls: Vec<String>, // methods called, with their arguments
+}
+
+fn state(status: &str, track_id: Option<&str>) -> Arc<Mutex<MockState>> {
+    Arc::new(Mutex::new(MockState {
+        status: status.to_owned(),
+        loop_status: "None".to_owned(),
+        volume: 0.5,
+        position: 60_000_000,
+        track_id: track_id.map(str::to_owned),
+        ..Default::default()
+    }))
+}
+
+struct MockRoot {
+    identity: String,
+}
+
+#[zbus::interface(name = "org.mpris.MediaPlayer2")]
+impl MockRoot {
+    #[zbus(property)]
+    fn identity(&self) -> String {
+        self.identity.clone()
+    }
+}
+
+struct MockPlayer {
+    state: Arc<Mutex<MockState>>,
+}
+
+impl MockPlayer {
+    fn call(&self, call: String) {
+        self.state.lock().unwrap().calls.push(call);
+    }
+}
+
+#[zbus::interface(name = "org.mpris.MediaPlayer2.Player")]
+impl MockPlayer {
+    fn play(&self) {
+        self.state.lock().unwrap().status = "Playing".to_owned();
+    }
+
+    fn pause(&self) {
+        self.state.lock().unwrap().status = "Paused".to_owned();
+    }
+
+    fn next(&self) {
+        self.call("Next".to_owned());
+    }
+
+    fn previous(&self) {
+        self.call("Previous".to_owned());
+    }
+
+    fn seek(&self, offset: i64) {
+        self.state.lock().unwrap().position += offset;
+    }
+
+    fn set_position(&self, track_id: ObjectPath<'_>, position: i64) {
+        self.call(format!("SetPosition {} {}", track_id, position));
+        self.state.lock().unwrap().position = position;
+    }
+
+    fn open_uri(&self, uri: String) {
+        self.call(format!("OpenUri {}", uri));
+    }
+
+    #[zbus(property)]
+    fn playback_status(&self) -> String {
+        self.state.lock().unwrap().status.clone()
+    }
+
+    #[zbus(property)]
+    fn loop_status(&self) -> String {
+        self.state.lock().unwrap().loop_status.clone()
+    }
+
+    #[zbus(property)]
+    fn set_loop_status(&mut self, value: String) {
+        self.state.lock().unwrap().loop_status = value;
+    }
+
+    #[zbus(property)]
+    fn shuffle(&self) -> bool {
+        self.state.lock().unwrap().shuffle
+    }
+
+    #[zbus(property)]
+    fn set_shuffle(&mut self, value: bool) {
+        self.state.lock().unwrap().shuffle = value;
+    }
+
+    #[zbus(property)]
+    fn volume(&self) -> f64 {
+        self.state.lock().unwrap().volume
+    }
+
+    #[zbus(property)]
+    fn set_volume(&mut self, value: f64) {
+        self.state.lock().unwrap().volume = value;
+    }
+
+    #[zbus(property)]
+    fn position(&self) -> i64 {
+        self.state.lock().unwrap().position
+    }
+
+    #[zbus(property)]
+    fn can_control(&self) -> bool {
+        true
+    }
+
+    // A 3 minute track, with Spotify's style of track id when there is one
+    #[zbus(property)]
+    fn metadata(&self) -> HashMap<String, OwnedValue> {
+        let mut metadata = HashMap::from([
+            ("xesam:title".to_owned(), Value::from("Song").try_to_owned().unwrap()),
+            ("mpris:length".to_owned(), OwnedValue::from(180_000_000i64)),
+        ]);
+        if let Some(id) = &self.state.lock().unwrap().track_id {
+            let path = ObjectPath::try_from(id.as_str()).unwrap();
+            metadata.insert("mpris:trackid".to_owned(), Value::from(path).try_to_owned().unwrap());
+        }
+        metadata
+    }
+}
+
+struct Bus {
+    daemon: Child,
+    address: String,
+}
+
+impl Drop for Bus {
+    fn drop(&mut self) {
+        let _ = self.daemon.kill();
+        let _ = self.daemon.wait();
+    }
+}
+
+fn private_bus() -> Option<Bus> {
+    let mut daemon = Command::new("dbus-daemon")
+        .args(["--session", "--nofork", "--print-address"])
+        .stdout(Stdio::piped())
+        .spawn()
+        .ok()?;
+    let mut address = String::new();
+    BufReader::new(daemon.stdout.take()?).read_line(&mut address).ok()?;
+    Some(Bus { daemon, address: address.trim().to_owned() })
+}
+
+async fn serve(bus: &Bus, name: &str, identity: &str, state: &Arc<Mutex<MockState>>) -> zbus::Connection {
+    connection::Builder::address(bus.address.as_str())
+        .unwrap()
+        .name(name)
+        .unwrap()
+        .serve_at(MPRIS_PATH, MockRoot { identity: identity.to_owned() })
+        .unwrap()
+        .serve_at(MPRIS_PATH, MockPlayer { state: state.clone() })
+        .unwrap()
+        .build()
+        .await
+        .unwrap()
+}
+
+async fn player(bus: &Bus) -> Player<MprisPlayer> {
+    let connection = connection::Builder::address(bus.address.as_str()).unwrap().build().await.unwrap();
+    Player { backend: MprisPlayer::with_connection(connection), preferred_device: None }
+}
+
+#[tokio::test]
+async fn actions_map_onto_the_playing_player() {
+    let Some(bus) = private_bus() else {
+        eprintln!("dbus-daemon not available, skipping");
+        return;
+    };
+    let spotify = state("Playing", Some(TRACK_ID));
+    let vlc = state("Paused", None);
+    let _spotify = serve(&bus, SPOTIFY, "Spotify", &spotify).await;
+    let _vlc = serve(&bus, VLC, "VLC media player", &vlc).await;
+    let player = player(&bus).await;
+
+    let playback = player.backend.playback().await.unwrap().unwrap();
+    assert_eq!(playback.device.name, "Spotify");
+    assert_eq!(playback.progress, Some(Duration::from_secs(60)));
+    let item = playback.item.unwrap();
+    assert_eq!(item.uri.unwrap().to_string(), "spotify:track:4uLU6hMCjMI75M1A2tKUQC");
+    assert_eq!(item.duration, Duration::from_secs(180));
+
+    player.next_track(None).await.unwrap();
+    player.previous_track(None).await.unwrap();
+    player.change_volume(None, 10).await.unwrap();
+    assert_eq!(spotify.lock().unwrap().volume, 0.6);
+
+    let mut volumes = VolumeState::default();
+    assert!(player.toggle_mute(None, &mut volumes).await.unwrap());
+    assert_eq!(spotify.lock().unwrap().volume, 0.0);
+    assert!(player.toggle_mute(None, &mut volumes).await.unwrap());
+    assert_eq!(spotify.lock().unwrap().volume, 0.6);
+
+    player.seek_relative(None, 10_000).await.unwrap();
+    player.cycle_shuffle(None).await.unwrap();
+    player.cycle_repeat(None).await.unwrap();
+    assert_eq!(player.backend.playback().await.unwrap().unwrap().repeat, Repeat::Context);
+
+    let target = PlayTarget { uri: "spotify:album:4aawyAB9vmqN3uQ7FjRGTy".to_owned(), shuffle: true, offset: 0 };
+    player.play_target(None, &target).await.unwrap();
+
+    // pausing last, so Spotify stays the player everything above went to
+    player.toggle_playback(None).await.unwrap();
+
+    let spotify = spotify.lock().unwrap();
+    assert_eq!(spotify.status, "Paused");
+    assert!(spotify.shuffle);
+    assert_eq!(
+        spotify.calls,
+        vec![
+            "Next".to_owned(),
+            "Previous".to_owned(),
+            format!("SetPosition {} 70000000", TRACK_ID),
+            "OpenUri spotify:album:4aawyAB9vmqN3uQ7FjRGTy".to_owned(),
+        ]
+    );
+    assert!(vlc.lock().unwrap().calls.is_empty());
+}
+
+#[tokio::test]
+async fn players_are_devices() {
+    let Some(bus) = private_bus() else {
+        eprintln!("dbus-daemon not available, skipping");
+        return;
+    };
+    let spotify = state("Paused", Some(TRACK_ID));
+    let vlc = state("Paused", None);
+    let _spotify = serve(&bus, SPOTIFY, "Spotify", &spotify).await;
+    let _vlc = serve(&bus, VLC, "VLC media player", &vlc).await;
+    let player = player(&bus).await;
+
+    assert_eq!(player.device_names().await.unwrap(), vec!["Spotify", "VLC media player"]);
+
+    // a binding's device gets the action without becoming the target
+    let vlc_id = player.device_id("vlc media player").await.unwrap();
+    assert_eq!(vlc_id.as_deref(), Some(VLC));
+    player.seek_relative(vlc_id.as_deref(), 5_000).await.unwrap();
+    assert_eq!(vlc.lock().unwrap().position, 65_000_000);
+    player.toggle_playback(None).await.unwrap();
+    assert_eq!(spotify.lock().unwrap().status, "Playing");
+
+    // switching players moves where unassigned actions go
+    player.cycle_device().await.unwrap();
+    player.next_track(None).await.unwrap();
+    assert_eq!(vlc.lock().unwrap().calls, vec!["Next"]);
+    player.transfer_to("Spotify").await.unwrap();
+    player.next_track(None).await.unwrap();
+    assert_eq!(spotify.lock().unwrap().calls, vec!["Next"]);
+}
+
+#[tokio::test]
+async fn web_api_only_actions_are_unsupported() {
+    let Some(bus) = private_bus() else {
+        eprintln!("dbus-daemon not available, skipping");
+        return;
+    };
+    let spotify = state("Playing", Some(TRACK_ID));
+    let _spotify = serve(&bus, SPOTIFY, "Spotify", &spotify).await;
+    let player = player(&bus).await;
+
+    assert!(matches!(player.toggle_like().await, Err(BackendError::Unsupported(_))));
+    let target = PlaylistTarget { uri: "spotify:playlist:37i9dQZF1DXc8kgYqQLMfH".to_owned(), allow_duplicates: false };
+    assert!(matches!(player.add_to_playlist(&target).await, Err(BackendError::Unsupported(_))));
+}
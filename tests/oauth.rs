@@ -0,0 +1,206 @@
+// The login flow against a fake authorize endpoint, with a scripted browser that follows
+// its redirect back to the callback server the way a real one would.
+use std::net::{SocketAddr, TcpListener as StdListener};
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedSender};
+use SpotifyBinds::oauth::{authorize, percent_decode, AuthError, AuthProgress, CallbackServer};
+
+const STATE: &str = "abc123";
+
+// A redirect URI on a port nothing is using
+fn redirect_uri() -> String {
+    let port = StdListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    format!("http://127.0.0.1:{}/callback", port)
+}
+
+fn query_param(url: &str, name: &str) -> Option<String> {
+    let query = url.split_once('?')?.1;
+    query.split('&').filter_map(|p| p.split_once('=')).find(|(k, _)| *k == name).map(|(_, v)| percent_decode(v))
+}
+
+// GET a URL and return the status line and the Location header, if any
+async fn get(url: &str) -> (String, Option<String>) {
+    let rest = url.strip_prefix("http://").unwrap();
+    let (host, path) = rest.split_at(rest.find('/').unwrap_or(rest.len()));
+    let mut stream = TcpStream::connect(host).await.unwrap();
+    let request = format!("GET {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n\r\n", path, host);
+    stream.write_all(request.as_bytes()).await.unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    let status = response.lines().next().unwrap_or_default().to_owned();
+    let location = response.lines().find_map(|l| l.strip_prefix("Location: ")).map(str::to_owned);
+    (status, location)
+}
+
+// Stands in for Spotify's /authorize: it sends the browser back to the redirect URI
+// with whatever `reply` makes of the state it was given
+async fn fake_authorize(reply: fn(&str) -> String) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        loop {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buf = vec![0; 4096];
+            let n = stream.read(&mut buf).await.unwrap();
+            let request = String::from_utf8_lossy(&buf[..n]).into_owned();
+            let target = request.split_whitespace().nth(1).unwrap_or_default().to_owned();
+            let redirect = query_param(&target, "redirect_uri").unwrap();
+            let state = query_param(&target, "state").unwrap();
+            let response = format!(
+                "HTTP/1.1 302 Found\r\nLocation: {}?{}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                redirect,
+                reply(&state)
+            );
+            stream.write_all(response.as_bytes()).await.unwrap();
+        }
+    });
+    addr
+}
+
+fn authorize_url(endpoint: SocketAddr, redirect_uri: &str) -> String {
+    format!("http://{}/authorize?client_id=x&redirect_uri={}&state={}", endpoint, redirect_uri, STATE)
+}
+
+// Follows the authorize page's redirect, recording what the callback server answered
+fn browser(answers: UnboundedSender<String>) -> impl FnOnce(&str) -> std::io::Result<()> {
+    move |url: &str| {
+        let url = url.to_owned();
+        tokio::spawn(async move {
+            let (_, location) = get(&url).await;
+            let (status, _) = get(&location.unwrap()).await;
+            let _ = answers.send(status);
+        });
+        Ok(())
+    }
+}
+
+type Login = (Result<(), AuthError>, Option<String>, Vec<AuthProgress>);
+
+async fn login(redirect_uri: &str, url: &str, open: impl FnOnce(&str) -> std::io::Result<()>) -> Login {
+    let exchanged = Arc::new(Mutex::new(None));
+    let progress = Arc::new(Mutex::new(Vec::new()));
+    let result = authorize(
+        redirect_uri,
+        url,
+        STATE,
+        open,
+        |code| {
+            *exchanged.lock().unwrap() = Some(code);
+            async { Ok(()) }
+        },
+        |step| progress.lock().unwrap().push(step),
+    )
+    .await;
+    let exchanged = exchanged.lock().unwrap().clone();
+    let progress = progress.lock().unwrap().clone();
+    (result, exchanged, progress)
+}
+
+#[tokio::test]
+async fn code_from_the_redirect_is_exchanged() {
+    let endpoint = fake_authorize(|state| format!("code=the%2Dcode&state={}", state)).await;
+    let redirect = redirect_uri();
+    let (tx, mut answers) = unbounded_channel();
+
+    let (result, code, progress) = login(&redirect, &authorize_url(endpoint, &redirect), browser(tx)).await;
+    assert_eq!(result, Ok(()));
+    assert_eq!(code.as_deref(), Some("the-code"));
+    assert_eq!(progress, vec![AuthProgress::WaitingForBrowser, AuthProgress::Exchanging]);
+    assert!(answers.recv().await.unwrap().contains("200"));
+}
+
+#[tokio::test]
+async fn denied_logins_are_reported() {
+    let endpoint = fake_authorize(|state| format!("error=access_denied&state={}", state)).await;
+    let redirect = redirect_uri();
+    let (tx, mut answers) = unbounded_channel();
+    let (result, code, _) = login(&redirect, &authorize_url(endpoint, &redirect), browser(tx)).await;
+    assert_eq!(result, Err(AuthError::Denied("access_denied".to_owned())));
+    assert_eq!(code, None);
+    assert!(answers.recv().await.unwrap().contains("400"));
+}
+
+#[tokio::test]
+async fn redirects_for_another_request_are_rejected() {
+    let server = CallbackServer::bind(&redirect_uri()).await.unwrap();
+    let addr = server.local_addr().unwrap();
+
+    let requests = tokio::spawn(async move {
+        // a stale tab and a forged error are turned away without ending the login
+        let (stale, _) = get(&format!("http://{}/callback?code=x&state=someone-else", addr)).await;
+        let (forged, _) = get(&format!("http://{}/callback?error=access_denied", addr)).await;
+        let (callback, _) = get(&format!("http://{}/callback?code=ok&state={}", addr, STATE)).await;
+        (stale, forged, callback)
+    });
+    assert_eq!(server.wait_for_code(STATE).await, Ok("ok".to_owned()));
+    let (stale, forged, callback) = requests.await.unwrap();
+    assert!(stale.contains("400"));
+    assert!(forged.contains("400"));
+    assert!(callback.contains("200"));
+}
+
+#[tokio::test]
+async fn error_text_is_escaped() {
+    let server = CallbackServer::bind(&redirect_uri()).await.unwrap();
+    let addr = server.local_addr().unwrap();
+
+    let request = tokio::spawn(async move {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let path = format!("/callback?error=%3Cscript%3Ealert(1)%3C%2Fscript%3E&state={}", STATE);
+        let request = format!("GET {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n\r\n", path, addr);
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response
+    });
+    assert!(matches!(server.wait_for_code(STATE).await, Err(AuthError::Denied(_))));
+    let response = request.await.unwrap();
+    assert!(!response.contains("<script>"));
+    assert!(response.contains("&lt;script&gt;"));
+}
+
+#[tokio::test]
+async fn without_a_browser_the_user_is_sent_the_link() {
+    let endpoint = fake_authorize(|state| format!("code=manual&state={}", state)).await;
+    let redirect = redirect_uri();
+    let url = authorize_url(endpoint, &redirect);
+
+    // no browser opens, so the user follows the link themselves
+    let (tx, mut answers) = unbounded_channel();
+    let no_browser = |url: &str| {
+        let _ = browser(tx)(url);
+        Err(std::io::Error::other("no browser"))
+    };
+    let (result, code, progress) = login(&redirect, &url, no_browser).await;
+    assert_eq!(result, Ok(()));
+    assert_eq!(code.as_deref(), Some("manual"));
+    assert_eq!(progress[0], AuthProgress::OpenManually(url));
+    assert!(answers.recv().await.unwrap().contains("200"));
+}
+
+#[tokio::test]
+async fn stray_connections_dont_block_the_callback() {
+    let server = CallbackServer::bind(&redirect_uri()).await.unwrap();
+    let addr = server.local_addr().unwrap();
+
+    let requests = tokio::spawn(async move {
+        // a preconnect that never sends anything, then a favicon request
+        let _idle = TcpStream::connect(addr).await.unwrap();
+        let (favicon, _) = get(&format!("http://{}/favicon.ico", addr)).await;
+        let (callback, _) = get(&format!("http://{}/callback?code=ok&state={}", addr, STATE)).await;
+        (favicon, callback)
+    });
+    assert_eq!(server.wait_for_code(STATE).await, Ok("ok".to_owned()));
+    let (favicon, callback) = requests.await.unwrap();
+    assert!(favicon.contains("404"));
+    assert!(callback.contains("200"));
+}
+
+#[tokio::test]
+async fn a_taken_port_is_reported() {
+    let taken = StdListener::bind("127.0.0.1:0").unwrap();
+    let redirect = format!("http://{}/callback", taken.local_addr().unwrap());
+    assert!(matches!(CallbackServer::bind(&redirect).await, Err(AuthError::Bind(_))));
+}
@@ -0,0 +1,226 @@
+// Player logic against the in-memory fake backend, so no network or account is needed.
+use std::time::Duration;
+use SpotifyBinds::hotkeyreg::{PlayTarget, PlaylistTarget};
+use SpotifyBinds::player::fake::{device, FakePlayer, FakeState};
+use SpotifyBinds::player::{BackendError, DeviceIds, Player, PlayingItem, VolumeState, UNMUTE_FALLBACK_VOLUME};
+
+const TRACK: &str = "spotify:track:4uLU6hMCjMI75M1A2tKUQC";
+const PLAYLIST: &str = "spotify:playlist:37i9dQZF1DXc8kgYqQLMfH";
+
+// A desktop and a speaker, with the desktop active and playing a 3 minute track
+fn playing() -> FakeState {
+    let mut desktop = device("Desktop", Some(40));
+    desktop.is_active = true;
+    FakeState {
+        devices: vec![desktop, device("Speaker", Some(20))],
+        is_playing: true,
+        progress: Duration::from_secs(60),
+        item: Some(PlayingItem {
+            uri: Some(TRACK.parse().unwrap()),
+            name: "Song".to_owned(),
+            duration: Duration::from_secs(180),
+        }),
+        ..Default::default()
+    }
+}
+
+// Both devices online but neither is playing
+fn idle() -> FakeState {
+    FakeState { devices: vec![device("Desktop", Some(40)), device("Speaker", Some(20))], ..Default::default() }
+}
+
+fn player(state: FakeState) -> Player<FakePlayer> {
+    Player { backend: FakePlayer::new(state), preferred_device: None }
+}
+
+fn volume(player: &Player<FakePlayer>, name: &str) -> Option<u32> {
+    player.backend.state().device_named(name).unwrap().volume_percent
+}
+
+fn active(player: &Player<FakePlayer>) -> Option<String> {
+    player.backend.state().active_device().map(|d| d.name.clone())
+}
+
+#[tokio::test]
+async fn toggle_pauses_and_resumes() {
+    let player = player(playing());
+    player.toggle_playback(None).await.unwrap();
+    assert!(!player.backend.state().is_playing);
+    player.toggle_playback(None).await.unwrap();
+    assert!(player.backend.state().is_playing);
+}
+
+#[tokio::test]
+async fn toggle_with_nothing_active_starts_on_the_preferred_device() {
+    let mut player = player(idle());
+    player.preferred_device = Some("speaker".to_owned());
+    player.toggle_playback(None).await.unwrap();
+    assert!(player.backend.state().is_playing);
+    assert_eq!(active(&player).as_deref(), Some("Speaker"));
+}
+
+#[tokio::test]
+async fn play_falls_back_to_the_first_device() {
+    let mut player = player(idle());
+    // preferred device is offline
+    player.preferred_device = Some("Kitchen".to_owned());
+    player.play(None).await.unwrap();
+    assert!(player.backend.state().is_playing);
+    assert_eq!(active(&player).as_deref(), Some("Desktop"));
+}
+
+#[tokio::test]
+async fn play_with_no_devices_does_nothing() {
+    let player = player(FakeState::default());
+    player.play(None).await.unwrap();
+    player.toggle_playback(None).await.unwrap();
+    assert!(!player.backend.state().is_playing);
+}
+
+#[tokio::test]
+async fn pausing_twice_is_not_an_error() {
+    let player = player(playing());
+    player.pause(None).await.unwrap();
+    player.pause(None).await.unwrap();
+    assert!(!player.backend.state().is_playing);
+}
+
+#[tokio::test]
+async fn volume_changes_are_clamped() {
+    let player = player(playing());
+    player.change_volume(None, 75).await.unwrap();
+    assert_eq!(volume(&player, "Desktop"), Some(100));
+    player.change_volume(None, -250).await.unwrap();
+    assert_eq!(volume(&player, "Desktop"), Some(0));
+    player.set_volume(None, 180).await.unwrap();
+    assert_eq!(volume(&player, "Desktop"), Some(100));
+
+    // a named device is changed from its own volume, not the active one's
+    player.change_volume(Some("id-Speaker"), 5).await.unwrap();
+    assert_eq!(volume(&player, "Speaker"), Some(25));
+}
+
+#[tokio::test]
+async fn volume_change_without_a_reported_volume_is_skipped() {
+    let mut state = playing();
+    state.devices[0].volume_percent = None;
+    let player = player(state);
+    player.change_volume(None, 10).await.unwrap();
+    assert_eq!(volume(&player, "Desktop"), None);
+}
+
+#[tokio::test]
+async fn mute_remembers_each_devices_volume() {
+    let player = player(playing());
+    let mut volumes = VolumeState::default();
+
+    assert!(player.toggle_mute(None, &mut volumes).await.unwrap());
+    assert!(player.toggle_mute(Some("id-Speaker"), &mut volumes).await.unwrap());
+    assert_eq!(volume(&player, "Desktop"), Some(0));
+    assert_eq!(volume(&player, "Speaker"), Some(0));
+    assert_eq!(volumes.premute.get("Desktop"), Some(&40));
+
+    assert!(player.toggle_mute(Some("id-Desktop"), &mut volumes).await.unwrap());
+    assert!(player.toggle_mute(Some("id-Speaker"), &mut volumes).await.unwrap());
+    assert_eq!(volume(&player, "Desktop"), Some(40));
+    assert_eq!(volume(&player, "Speaker"), Some(20));
+    assert!(volumes.premute.is_empty());
+}
+
+#[tokio::test]
+async fn unmuting_with_nothing_remembered_uses_the_fallback() {
+    let mut state = playing();
+    state.devices[0].volume_percent = Some(0);
+    let player = player(state);
+    let mut volumes = VolumeState::default();
+
+    assert!(player.toggle_mute(None, &mut volumes).await.unwrap());
+    assert_eq!(volume(&player, "Desktop"), Some(UNMUTE_FALLBACK_VOLUME));
+}
+
+#[tokio::test]
+async fn mute_skips_devices_without_a_volume() {
+    let mut state = playing();
+    state.devices[0].volume_percent = None;
+    let player = player(state);
+    let mut volumes = VolumeState::default();
+
+    assert!(!player.toggle_mute(None, &mut volumes).await.unwrap());
+    assert!(volumes.premute.is_empty());
+}
+
+#[tokio::test]
+async fn seek_stays_inside_the_track() {
+    let player = player(playing());
+    player.seek_relative(None, -90_000).await.unwrap();
+    assert_eq!(player.backend.state().progress, Duration::ZERO);
+    player.seek_relative(None, 500_000).await.unwrap();
+    assert_eq!(player.backend.state().progress, Duration::from_secs(180));
+}
+
+#[tokio::test]
+async fn play_target_starts_on_an_available_device() {
+    let player = player(idle());
+    let link = "https://open.spotify.com/playlist/37i9dQZF1DXc8kgYqQLMfH?si=1".to_owned();
+    let target = PlayTarget { uri: link, shuffle: true, offset: 3 };
+    player.play_target(None, &target).await.unwrap();
+
+    let state = player.backend.state();
+    assert_eq!(state.started, Some((PLAYLIST.to_owned(), 3)));
+    assert!(state.shuffle);
+}
+
+#[tokio::test]
+async fn play_target_without_shuffle_keeps_the_users_shuffle() {
+    let mut state = playing();
+    state.shuffle = true;
+    let player = player(state);
+    let target = PlayTarget { uri: PLAYLIST.to_owned(), shuffle: false, offset: 0 };
+    player.play_target(None, &target).await.unwrap();
+
+    let state = player.backend.state();
+    assert_eq!(state.started, Some((PLAYLIST.to_owned(), 0)));
+    assert!(state.shuffle);
+}
+
+#[tokio::test]
+async fn adding_to_a_playlist_skips_duplicates() {
+    let mut state = playing();
+    state.playlists.insert(PLAYLIST.to_owned(), Vec::new());
+    let player = player(state);
+    let target = PlaylistTarget { uri: PLAYLIST.to_owned(), allow_duplicates: false };
+
+    assert_eq!(player.add_to_playlist(&target).await.unwrap(), "Added Song to the playlist");
+    assert_eq!(player.add_to_playlist(&target).await.unwrap(), "Song is already in the playlist");
+    assert_eq!(player.backend.state().playlists[PLAYLIST], vec![TRACK.to_owned()]);
+}
+
+#[tokio::test]
+async fn adding_to_a_playlist_needs_something_playing() {
+    let player = player(idle());
+    let target = PlaylistTarget { uri: PLAYLIST.to_owned(), allow_duplicates: false };
+    assert_eq!(player.add_to_playlist(&target).await, Err(BackendError::NothingPlaying));
+}
+
+#[tokio::test]
+async fn cycle_device_moves_to_the_next_one() {
+    let player = player(playing());
+    player.cycle_device().await.unwrap();
+    assert_eq!(active(&player).as_deref(), Some("Speaker"));
+    player.cycle_device().await.unwrap();
+    assert_eq!(active(&player).as_deref(), Some("Desktop"));
+}
+
+#[tokio::test]
+async fn device_ids_are_looked_up_once() {
+    let player = player(idle());
+    let mut ids = DeviceIds::default();
+    assert_eq!(ids.get(&player, "speaker").await.unwrap().as_deref(), Some("id-Speaker"));
+
+    // the speaker reconnects with a new id, which isn't noticed until a command fails
+    player.backend.edit(|s| s.devices[1].id = Some("id-Speaker-2".to_owned()));
+    assert_eq!(ids.get(&player, "Speaker").await.unwrap().as_deref(), Some("id-Speaker"));
+    ids.forget("Speaker");
+    assert_eq!(ids.get(&player, "Speaker").await.unwrap().as_deref(), Some("id-Speaker-2"));
+    assert_eq!(ids.get(&player, "Kitchen").await.unwrap(), None);
+}
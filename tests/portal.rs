@@ -0,0 +1,221 @@
+// Runs the portal backend against a mock GlobalShortcuts service on a private
+// session bus. Skipped when dbus-daemon isn't installed.
+#![cfg(target_os = "linux")]
+
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader};
+use std::process::{Child, Command, Stdio};
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc::unbounded_channel;
+use zbus::blocking::{connection, Connection};
+use zbus::zvariant::{ObjectPath, OwnedObjectPath, OwnedValue, Value};
+use SpotifyBinds::hotkeyreg::portal::{
+    portal_binds, preferred_trigger, shortcut_id, skipped_binds, PortalInput, PORTAL_DESTINATION, PORTAL_PATH, REQUEST_INTERFACE, SESSION_INTERFACE,
+    SHORTCUTS_INTERFACE,
+};
+use SpotifyBinds::hotkeyreg::{run_listener, Binding, KeyChord, KeyEvent, Trigger, DEFAULT_SEQUENCE_TIMEOUT};
+
+const SESSION: &str = "/org/freedesktop/portal/desktop/session/test/spotifybinds";
+
+type Shortcuts = Vec<(String, HashMap<String, OwnedValue>)>;
+
+struct MockPortal {
+    bound: Arc<Mutex<Shortcuts>>,
+}
+
+fn request_path(options: &HashMap<String, OwnedValue>) -> OwnedObjectPath {
+    let token: String = options["handle_token"].try_clone().unwrap().try_into().unwrap();
+    OwnedObjectPath::try_from(format!("/org/freedesktop/portal/desktop/request/test/{}", token)).unwrap()
+}
+
+// Real portals may answer before the method returns, so the mock always does
+async fn respond(conn: &zbus::Connection, request: &OwnedObjectPath, results: HashMap<&str, Value<'_>>) -> zbus::fdo::Result<()> {
+    conn.emit_signal(None::<()>, request, REQUEST_INTERFACE, "Response", &(0u32, results)).await?;
+    Ok(())
+}
+
+#[zbus::interface(name = "org.freedesktop.portal.GlobalShortcuts")]
+impl MockPortal {
+    async fn create_session(
+        &self,
+        #[zbus(connection)] conn: &zbus::Connection,
+        options: HashMap<String, OwnedValue>,
+    ) -> zbus::fdo::Result<OwnedObjectPath> {
+        let request = request_path(&options);
+        // sent as a string, like older portals do
+        respond(conn, &request, HashMap::from([("session_handle", Value::from(SESSION))])).await?;
+        Ok(request)
+    }
+
+    async fn bind_shortcuts(
+        &self,
+        #[zbus(connection)] conn: &zbus::Connection,
+        _session: OwnedObjectPath,
+        shortcuts: Shortcuts,
+        _parent_window: String,
+        options: HashMap<String, OwnedValue>,
+    ) -> zbus::fdo::Result<OwnedObjectPath> {
+        *self.bound.lock().unwrap() = shortcuts;
+        let request = request_path(&options);
+        respond(conn, &request, HashMap::new()).await?;
+        Ok(request)
+    }
+}
+
+struct Bus {
+    daemon: Child,
+    address: String,
+}
+
+impl Drop for Bus {
+    fn drop(&mut self) {
+        let _ = self.daemon.kill();
+        let _ = self.daemon.wait();
+    }
+}
+
+fn private_bus() -> Option<Bus> {
+    let mut daemon = Command::new("dbus-daemon")
+        .args(["--session", "--nofork", "--print-address"])
+        .stdout(Stdio::piped())
+        .spawn()
+        .ok()?;
+    let mut address = String::new();
+    BufReader::new(daemon.stdout.take()?).read_line(&mut address).ok()?;
+    Some(Bus { daemon, address: address.trim().to_owned() })
+}
+
+fn bind(keys: &str, action: KeyEvent) -> Binding {
+    Binding { keys: Some(keys.parse().unwrap()), ..Binding::new(action) }
+}
+
+fn emit(conn: &Connection, path: &str, interface: &str, member: &str, session: &str, id: &str) {
+    let body = (ObjectPath::try_from(session).unwrap(), id, 0u64, HashMap::<&str, Value>::new());
+    conn.emit_signal(None::<()>, path, interface, member, &body).unwrap();
+}
+
+#[test]
+fn portal_shortcuts_reach_the_channel() {
+    let Some(bus) = private_bus() else {
+        eprintln!("dbus-daemon not available, skipping");
+        return;
+    };
+
+    let bound = Arc::new(Mutex::new(Vec::new()));
+    let portal = connection::Builder::address(bus.address.as_str())
+        .unwrap()
+        .name(PORTAL_DESTINATION)
+        .unwrap()
+        .serve_at(PORTAL_PATH, MockPortal { bound: bound.clone() })
+        .unwrap()
+        .build()
+        .unwrap();
+    let client = connection::Builder::address(bus.address.as_str()).unwrap().build().unwrap();
+
+    let binds = vec![
+        bind("Ctrl+Shift+P", KeyEvent::Toggle),
+        Binding { trigger: Trigger::Release, ..bind("Super+N", KeyEvent::Next) },
+        Binding::new(KeyEvent::Mute),
+        bind("Ctrl+K, P", KeyEvent::Previous),
+    ];
+    let source = PortalInput::with_connection(client, &binds).unwrap();
+
+    // only the single-chord binds were offered, with their chords as suggested triggers
+    let offered: Vec<(String, String)> = bound
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(id, details)| (id.clone(), details["preferred_trigger"].try_clone().unwrap().try_into().unwrap()))
+        .collect();
+    assert_eq!(
+        offered,
+        vec![(shortcut_id(0), "CTRL+SHIFT+p".to_owned()), (shortcut_id(1), "LOGO+n".to_owned())]
+    );
+
+    let (tx, mut rx) = unbounded_channel();
+    let engine_binds = portal_binds(&binds);
+    let listener = std::thread::spawn(move || run_listener(source, tx, engine_binds, DEFAULT_SEQUENCE_TIMEOUT));
+
+    emit(&portal, PORTAL_PATH, SHORTCUTS_INTERFACE, "Activated", SESSION, "bind-0");
+    emit(&portal, PORTAL_PATH, SHORTCUTS_INTERFACE, "Deactivated", SESSION, "bind-0");
+    emit(&portal, PORTAL_PATH, SHORTCUTS_INTERFACE, "Activated", SESSION, "bind-1");
+    emit(&portal, PORTAL_PATH, SHORTCUTS_INTERFACE, "Deactivated", SESSION, "bind-1");
+    // another app's session is ignored
+    let other = "/org/freedesktop/portal/desktop/session/test/other";
+    emit(&portal, PORTAL_PATH, SHORTCUTS_INTERFACE, "Activated", other, "bind-0");
+    portal
+        .emit_signal(None::<()>, SESSION, SESSION_INTERFACE, "Closed", &HashMap::<&str, Value>::new())
+        .unwrap();
+
+    listener.join().unwrap();
+    let mut actions = Vec::new();
+    while let Ok(fired) = rx.try_recv() {
+        actions.push(fired.action);
+    }
+    assert_eq!(actions, vec![KeyEvent::Toggle, KeyEvent::Next]);
+}
+
+#[test]
+fn triggers_use_xkb_keysyms() {
+    let trigger = |chord: &str| preferred_trigger(&chord.parse::<KeyChord>().unwrap());
+    assert_eq!(trigger("Ctrl+Semicolon").as_deref(), Some("CTRL+semicolon"));
+    assert_eq!(trigger("Alt+Period").as_deref(), Some("ALT+period"));
+    assert_eq!(trigger("Shift+PageDown").as_deref(), Some("SHIFT+Next"));
+    assert_eq!(trigger("Numpad7").as_deref(), Some("KP_7"));
+    assert_eq!(trigger("Ctrl+NumpadPlus").as_deref(), Some("CTRL+KP_Add"));
+    assert_eq!(trigger("Ctrl+Fn"), None);
+}
+
+// Taps on one chord all go through the same shortcut, so the engine can count them
+#[test]
+fn triggers_on_one_chord_share_a_shortcut() {
+    let Some(bus) = private_bus() else {
+        eprintln!("dbus-daemon not available, skipping");
+        return;
+    };
+
+    let bound = Arc::new(Mutex::new(Vec::new()));
+    let portal = connection::Builder::address(bus.address.as_str())
+        .unwrap()
+        .name(PORTAL_DESTINATION)
+        .unwrap()
+        .serve_at(PORTAL_PATH, MockPortal { bound: bound.clone() })
+        .unwrap()
+        .build()
+        .unwrap();
+    let client = connection::Builder::address(bus.address.as_str()).unwrap().build().unwrap();
+
+    let binds = vec![
+        Binding { trigger: Trigger::Tap(1), ..bind("F9", KeyEvent::Toggle) },
+        Binding { trigger: Trigger::Tap(2), ..bind("F9", KeyEvent::Next) },
+        bind("Ctrl+K, P", KeyEvent::Previous),
+    ];
+    let source = PortalInput::with_connection(client, &binds).unwrap();
+
+    let offered: Vec<(String, String)> = bound
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(id, details)| (id.clone(), details["description"].try_clone().unwrap().try_into().unwrap()))
+        .collect();
+    assert_eq!(offered, vec![(shortcut_id(0), "Toggle playback, Skip".to_owned())]);
+    let skipped: Vec<KeyEvent> = skipped_binds(&binds).map(|b| b.action.clone()).collect();
+    assert_eq!(skipped, vec![KeyEvent::Previous]);
+
+    let (tx, mut rx) = unbounded_channel();
+    let engine_binds = portal_binds(&binds);
+    let listener = std::thread::spawn(move || run_listener(source, tx, engine_binds, DEFAULT_SEQUENCE_TIMEOUT));
+
+    // a double tap
+    for _ in 0..2 {
+        emit(&portal, PORTAL_PATH, SHORTCUTS_INTERFACE, "Activated", SESSION, "bind-0");
+        emit(&portal, PORTAL_PATH, SHORTCUTS_INTERFACE, "Deactivated", SESSION, "bind-0");
+    }
+    portal
+        .emit_signal(None::<()>, SESSION, SESSION_INTERFACE, "Closed", &HashMap::<&str, Value>::new())
+        .unwrap();
+
+    listener.join().unwrap();
+    let actions: Vec<KeyEvent> = std::iter::from_fn(|| rx.try_recv().ok()).map(|f| f.action).collect();
+    assert_eq!(actions, vec![KeyEvent::Next]);
+}